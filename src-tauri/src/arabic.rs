@@ -0,0 +1,216 @@
+/// Arabic letters and the first of their contextual forms in the Arabic
+/// Presentation Forms-B block: isolated, final, initial then medial. Letters
+/// with two forms only join the letter before them.
+const LETTER_FORMS: [(char, u32, u8); 36] = [
+    ('\u{0621}', 0xFE80, 1),
+    ('\u{0622}', 0xFE81, 2),
+    ('\u{0623}', 0xFE83, 2),
+    ('\u{0624}', 0xFE85, 2),
+    ('\u{0625}', 0xFE87, 2),
+    ('\u{0626}', 0xFE89, 4),
+    ('\u{0627}', 0xFE8D, 2),
+    ('\u{0628}', 0xFE8F, 4),
+    ('\u{0629}', 0xFE93, 2),
+    ('\u{062A}', 0xFE95, 4),
+    ('\u{062B}', 0xFE99, 4),
+    ('\u{062C}', 0xFE9D, 4),
+    ('\u{062D}', 0xFEA1, 4),
+    ('\u{062E}', 0xFEA5, 4),
+    ('\u{062F}', 0xFEA9, 2),
+    ('\u{0630}', 0xFEAB, 2),
+    ('\u{0631}', 0xFEAD, 2),
+    ('\u{0632}', 0xFEAF, 2),
+    ('\u{0633}', 0xFEB1, 4),
+    ('\u{0634}', 0xFEB5, 4),
+    ('\u{0635}', 0xFEB9, 4),
+    ('\u{0636}', 0xFEBD, 4),
+    ('\u{0637}', 0xFEC1, 4),
+    ('\u{0638}', 0xFEC5, 4),
+    ('\u{0639}', 0xFEC9, 4),
+    ('\u{063A}', 0xFECD, 4),
+    ('\u{0641}', 0xFED1, 4),
+    ('\u{0642}', 0xFED5, 4),
+    ('\u{0643}', 0xFED9, 4),
+    ('\u{0644}', 0xFEDD, 4),
+    ('\u{0645}', 0xFEE1, 4),
+    ('\u{0646}', 0xFEE5, 4),
+    ('\u{0647}', 0xFEE9, 4),
+    ('\u{0648}', 0xFEED, 2),
+    ('\u{0649}', 0xFEEF, 2),
+    ('\u{064A}', 0xFEF1, 4),
+];
+
+/// Ligatures of lam followed by each alef, isolated form first.
+const LAM_ALEF: [(char, u32); 4] = [('\u{0622}', 0xFEF5), ('\u{0623}', 0xFEF7), ('\u{0625}', 0xFEF9), ('\u{0627}', 0xFEFB)];
+
+const LAM: char = '\u{0644}';
+const TATWEEL: char = '\u{0640}';
+
+pub fn is_arabic(c: char) -> bool {
+    matches!(c, '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// Vowel marks sit on a letter without breaking its joins.
+fn is_mark(c: char) -> bool {
+    matches!(c, '\u{064B}'..='\u{065F}' | '\u{0670}')
+}
+
+fn forms(c: char) -> Option<(u32, u8)> {
+    LETTER_FORMS.iter().find(|(letter, _, _)| *letter == c).map(|(_, first, count)| (*first, *count))
+}
+
+fn joins_next(c: char) -> bool {
+    c == TATWEEL || forms(c).is_some_and(|(_, count)| count == 4)
+}
+
+fn joins_previous(c: char) -> bool {
+    c == TATWEEL || forms(c).is_some_and(|(_, count)| count > 1)
+}
+
+/// Replaces Arabic letters by the form they take next to their neighbours,
+/// for renderers that draw one glyph per character without shaping.
+pub fn join_letters(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let neighbour = |from: usize, step: isize| {
+        let mut index = from as isize + step;
+        while index >= 0 && (index as usize) < chars.len() && is_mark(chars[index as usize]) {
+            index += step;
+        }
+        (index >= 0 && (index as usize) < chars.len()).then(|| (index as usize, chars[index as usize]))
+    };
+
+    let mut joined = String::with_capacity(text.len());
+    let mut skip = None;
+    for (index, &c) in chars.iter().enumerate() {
+        if skip == Some(index) {
+            continue;
+        }
+        let Some((first, count)) = forms(c) else {
+            joined.push(c);
+            continue;
+        };
+
+        let after_joiner = neighbour(index, -1).is_some_and(|(_, p)| joins_next(p));
+        let next = neighbour(index, 1);
+        if c == LAM {
+            if let Some((alef_index, ligature)) = next.and_then(|(i, n)| LAM_ALEF.iter().find(|(a, _)| *a == n).map(|(_, l)| (i, *l))) {
+                joined.extend(char::from_u32(ligature + after_joiner as u32));
+                skip = Some(alef_index);
+                continue;
+            }
+        }
+
+        let before_joiner = count == 4 && next.is_some_and(|(_, n)| joins_previous(n));
+        let offset = match (after_joiner && count > 1, before_joiner) {
+            (true, true) => 3,
+            (false, true) => 2,
+            (true, false) => 1,
+            (false, false) => 0,
+        };
+        joined.extend(char::from_u32(first + offset));
+    }
+
+    joined
+}
+
+/// Text is laid out left to right, so Arabic is put in visual order: each
+/// run of Arabic words has its words and letters reversed, and a line that
+/// starts in Arabic also has its runs reversed.
+pub fn visual_order(text: &str) -> String {
+    if !text.chars().any(is_arabic) {
+        return text.to_string();
+    }
+
+    let mut runs: Vec<(bool, Vec<String>)> = Vec::new();
+    for word in text.split(' ') {
+        let arabic = word.chars().any(is_arabic);
+        let word = if arabic { word.chars().rev().collect() } else { word.to_string() };
+        match runs.last_mut() {
+            Some((run_arabic, words)) if *run_arabic == arabic => words.push(word),
+            _ => runs.push((arabic, vec![word])),
+        }
+    }
+
+    let mut runs: Vec<String> = runs
+        .into_iter()
+        .map(|(arabic, mut words)| {
+            if arabic {
+                words.reverse();
+            }
+            words.join(" ")
+        })
+        .collect();
+
+    let starts_arabic = text.chars().find(|c| c.is_alphabetic()).is_some_and(is_arabic);
+    if starts_arabic {
+        runs.reverse();
+    }
+    runs.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(text: &str) -> Vec<u32> {
+        text.chars().map(|c| c as u32).collect()
+    }
+
+    #[test]
+    fn letters_take_their_initial_medial_and_final_forms() {
+        // beh, yeh, teh
+        assert_eq!(codes(&join_letters("بيت")), [0xFE91, 0xFEF4, 0xFE96]);
+        assert_eq!(codes(&join_letters("ب")), [0xFE8F]);
+        // A vowel mark does not break the join
+        assert_eq!(codes(&join_letters("بَت")), [0xFE91, 0x064E, 0xFE96]);
+    }
+
+    #[test]
+    fn letters_with_two_forms_do_not_join_the_next_one() {
+        // dal, alef then meem: each starts a new group
+        assert_eq!(codes(&join_letters("دام")), [0xFEA9, 0xFE8D, 0xFEE1]);
+        // seen, waw with hamza, alef, lam
+        assert_eq!(codes(&join_letters("سؤال")), [0xFEB3, 0xFE86, 0xFE8D, 0xFEDD]);
+    }
+
+    #[test]
+    fn hamza_joins_neither_side() {
+        // meem, alef, hamza
+        assert_eq!(codes(&join_letters("ماء")), [0xFEE3, 0xFE8E, 0xFE80]);
+        // beh after a hamza starts a new group
+        assert_eq!(codes(&join_letters("ءبت")), [0xFE80, 0xFE91, 0xFE96]);
+    }
+
+    #[test]
+    fn lam_and_alef_become_a_ligature() {
+        assert_eq!(codes(&join_letters("لا")), [0xFEFB]);
+        assert_eq!(codes(&join_letters("لأ")), [0xFEF7]);
+        // seen, lam-alef after a joining letter, then an isolated meem
+        assert_eq!(codes(&join_letters("سلام")), [0xFEB3, 0xFEFC, 0xFEE1]);
+    }
+
+    #[test]
+    fn latin_text_is_left_alone() {
+        assert_eq!(join_letters("Reçu n° 12"), "Reçu n° 12");
+        assert_eq!(visual_order("Reçu n° 12"), "Reçu n° 12");
+    }
+
+    #[test]
+    fn arabic_runs_are_reversed_in_a_french_line() {
+        assert_eq!(visual_order("Élève : محمد علي"), "Élève : يلع دمحم");
+        assert_eq!(visual_order("Parent : علي (0550 12 34 56)"), "Parent : يلع (0550 12 34 56)");
+    }
+
+    #[test]
+    fn a_line_starting_in_arabic_reads_from_the_right() {
+        assert_eq!(visual_order("علي Benali"), "Benali يلع");
+        assert_eq!(visual_order("محمد علي Benali Amine"), "Benali Amine يلع دمحم");
+    }
+
+    #[test]
+    fn joined_forms_are_kept_in_visual_order() {
+        let line = visual_order(&join_letters("Nom : بيت"));
+        assert_eq!(codes(&line[6..]), [0xFE96, 0xFEF4, 0xFE91]);
+        assert!(line.starts_with("Nom : "));
+    }
+}
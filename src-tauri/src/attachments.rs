@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+
+/// Kinds of documents kept for a student.
+pub const ATTACHMENT_KINDS: [&str; 5] = ["photo", "birth_certificate", "registration_form", "medical", "other"];
+
+/// Largest side of a thumbnail, in pixels.
+const THUMBNAIL_SIZE: u32 = 256;
+
+/// Largest file accepted, 20 MiB.
+const MAX_FILE_SIZE: u64 = 20 * 1024 * 1024;
+
+/// A file copied into the store, named after the SHA-256 of its content so
+/// the same scan uploaded twice is kept once.
+pub struct StoredFile {
+    pub content_hash: String,
+    pub extension: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub has_thumbnail: bool,
+}
+
+pub fn mime_type(extension: &str) -> &'static str {
+    match extension {
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+fn is_image(extension: &str) -> bool {
+    matches!(extension, "jpg" | "jpeg" | "png")
+}
+
+/// Path of a stored file within `dir`.
+pub fn file_path(dir: &Path, content_hash: &str, extension: &str) -> PathBuf {
+    dir.join(format!("{}.{}", content_hash, extension))
+}
+
+pub fn thumbnail_path(dir: &Path, content_hash: &str) -> PathBuf {
+    dir.join("thumbnails").join(format!("{}.png", content_hash))
+}
+
+/// Copies `source` into `dir` unless the same content is already there,
+/// and makes a thumbnail for images.
+pub fn store(dir: &Path, source: &Path) -> Result<StoredFile> {
+    let size = fs::metadata(source).map_err(|e| anyhow!("Cannot read {}: {}", source.display(), e))?.len();
+    if size > MAX_FILE_SIZE {
+        return Err(anyhow!("{} is larger than {} MiB", source.display(), MAX_FILE_SIZE / 1024 / 1024));
+    }
+
+    let bytes = fs::read(source)?;
+    let content_hash = hex::encode(Sha256::digest(&bytes));
+    let extension = source
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("bin")
+        .to_lowercase();
+
+    fs::create_dir_all(dir)?;
+    let target = file_path(dir, &content_hash, &extension);
+    if !target.exists() {
+        // Written aside then renamed, so a crash never leaves a truncated file under its hash
+        let partial = target.with_extension("part");
+        fs::write(&partial, &bytes)?;
+        fs::rename(&partial, &target)?;
+    }
+
+    let has_thumbnail = is_image(&extension) && make_thumbnail(dir, &content_hash, &bytes).is_ok();
+
+    Ok(StoredFile {
+        mime_type: mime_type(&extension).to_string(),
+        content_hash,
+        extension,
+        size: size as i64,
+        has_thumbnail,
+    })
+}
+
+fn make_thumbnail(dir: &Path, content_hash: &str, bytes: &[u8]) -> Result<()> {
+    let target = thumbnail_path(dir, content_hash);
+    if target.exists() {
+        return Ok(());
+    }
+
+    let image = image::load_from_memory(bytes)?;
+    fs::create_dir_all(dir.join("thumbnails"))?;
+    image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE).save_with_format(&target, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+/// Deletes a stored file and its thumbnail once no attachment uses them.
+pub fn remove(dir: &Path, content_hash: &str, extension: &str) -> Result<()> {
+    for path in [file_path(dir, content_hash, extension), thumbnail_path(dir, content_hash)] {
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Copies the files of `from` missing from `to`, thumbnails included.
+/// Stored files never change, so a name already present is skipped.
+pub fn copy_missing(from: &Path, to: &Path) -> Result<u64> {
+    if !from.is_dir() {
+        return Ok(0);
+    }
+
+    fs::create_dir_all(to)?;
+    let mut copied = 0;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copied += copy_missing(&entry.path(), &target)?;
+        } else if !target.exists() && entry.path().extension().and_then(|e| e.to_str()) != Some("part") {
+            fs::copy(entry.path(), &target)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
@@ -0,0 +1,536 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use std::collections::{BTreeMap, HashMap};
+use crate::models::{CashSessionTotal, CategoryTotal, Discount, Expense, MonthlyProfit, Payment, PaymentReversal, ProfitReport, Student};
+
+/// Amounts closer than this are considered equal (half a centime).
+pub const AMOUNT_EPSILON: f64 = 0.005;
+
+/// Month labels and due dates of `installments` consecutive months starting
+/// at `start_month` (`YYYY-MM`). `due_day` is clamped to the month length.
+pub fn installment_dates(start_month: &str, installments: u32, due_day: u32) -> Result<Vec<(String, NaiveDate)>> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", start_month), "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid start month {}, expected YYYY-MM", start_month))?;
+    if installments == 0 {
+        return Err(anyhow!("A payment plan needs at least one installment"));
+    }
+    if !(1..=31).contains(&due_day) {
+        return Err(anyhow!("Invalid due day {}", due_day));
+    }
+
+    let mut dates = Vec::new();
+    for offset in 0..installments {
+        let month = first
+            .checked_add_months(chrono::Months::new(offset))
+            .ok_or_else(|| anyhow!("Installment {} is out of range", offset + 1))?;
+        let day = due_day.min(days_in_month(month));
+        let due_date = month.with_day(day).ok_or_else(|| anyhow!("Invalid due day {}", due_day))?;
+        dates.push((month.format("%Y-%m").to_string(), due_date));
+    }
+
+    Ok(dates)
+}
+
+fn days_in_month(first_day: NaiveDate) -> u32 {
+    first_day
+        .checked_add_months(chrono::Months::new(1))
+        .map(|next| next.pred_opt().map(|d| d.day()).unwrap_or(28))
+        .unwrap_or(31)
+}
+
+pub fn due_status(amount: f64, paid_amount: f64) -> &'static str {
+    if paid_amount + AMOUNT_EPSILON >= amount {
+        "paid"
+    } else if paid_amount > AMOUNT_EPSILON {
+        "partial"
+    } else {
+        "unpaid"
+    }
+}
+
+/// Start of `date` in the local time zone, as stored in the database.
+pub fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+/// A due that still has something to pay, as read before allocating.
+pub struct OpenDue {
+    pub id: String,
+    pub month: String,
+    pub due_date: DateTime<Utc>,
+    pub amount: f64,
+    pub paid_amount: f64,
+}
+
+impl OpenDue {
+    pub fn remaining(&self) -> f64 {
+        (self.amount - self.paid_amount).max(0.0)
+    }
+}
+
+pub struct Allocation<'a> {
+    pub due: &'a OpenDue,
+    pub amount: f64,
+    /// Status of the due once the allocation is applied.
+    pub status: &'static str,
+}
+
+/// Spreads `amount` over `dues` in order. Without partial payments a due is
+/// only touched when it can be settled: the first one must be, later ones
+/// are left for a following payment and the rest remains as credit.
+pub fn allocate(amount: f64, dues: &[OpenDue], allow_partial: bool) -> Result<Vec<Allocation<'_>>> {
+    let mut remaining = amount;
+    let mut allocations = Vec::new();
+
+    for due in dues {
+        if remaining <= AMOUNT_EPSILON {
+            break;
+        }
+        let applied = remaining.min(due.remaining());
+        if applied <= AMOUNT_EPSILON {
+            continue;
+        }
+        if !allow_partial && applied + AMOUNT_EPSILON < due.remaining() {
+            if allocations.is_empty() {
+                return Err(anyhow!(
+                    "Partial payments are disabled: {:.2} does not settle the {} due of {:.2}",
+                    amount,
+                    due.month,
+                    due.remaining()
+                ));
+            }
+            break;
+        }
+
+        allocations.push(Allocation {
+            due,
+            amount: applied,
+            status: due_status(due.amount, due.paid_amount + applied),
+        });
+        remaining -= applied;
+    }
+
+    Ok(allocations)
+}
+
+/// Checks a discount rule before it is saved.
+pub fn validate_discount(discount: &Discount) -> Result<()> {
+    if discount.name.trim().is_empty() {
+        return Err(anyhow!("A discount needs a name"));
+    }
+    match discount.scope.as_str() {
+        "student" | "level" if discount.target.as_deref().map_or(true, |t| t.trim().is_empty()) => {
+            return Err(anyhow!("A {} discount needs a target", discount.scope));
+        }
+        "student" | "level" | "family" => {}
+        other => return Err(anyhow!("Unknown discount scope {}", other)),
+    }
+    match discount.kind.as_str() {
+        "percent" if discount.value > 100.0 => return Err(anyhow!("A percentage discount cannot exceed 100")),
+        "percent" | "fixed" => {}
+        other => return Err(anyhow!("Unknown discount kind {}", other)),
+    }
+    if discount.value <= 0.0 {
+        return Err(anyhow!("The discount value must be positive"));
+    }
+    if let (Some(starts_on), Some(ends_on)) = (discount.starts_on, discount.ends_on) {
+        if ends_on < starts_on {
+            return Err(anyhow!("The discount ends before it starts"));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `discount` covers `student`, whose family has `siblings` active
+/// children counting the student.
+pub fn discount_applies(discount: &Discount, student: &Student, siblings: i64) -> bool {
+    if !discount.is_active {
+        return false;
+    }
+    match (discount.scope.as_str(), discount.target.as_deref()) {
+        ("student", Some(target)) => target == student.id,
+        ("level", Some(target)) => target == student.academic_level,
+        ("family", Some(target)) => !student.parent_phone.is_empty() && target == student.parent_phone,
+        ("family", None) => !student.parent_phone.is_empty() && siblings >= discount.min_siblings.max(2),
+        _ => false,
+    }
+}
+
+/// Discounts taken off a due of `fee` falling on `due_date`. Each one is
+/// computed on the full fee; together they never exceed it.
+pub fn discount_lines(fee: f64, due_date: NaiveDate, discounts: &[Discount]) -> Vec<(&Discount, f64)> {
+    let mut remaining = fee;
+    let mut lines = Vec::new();
+
+    for discount in discounts {
+        if discount.starts_on.is_some_and(|d| due_date < d) || discount.ends_on.is_some_and(|d| due_date > d) {
+            continue;
+        }
+        let amount = match discount.kind.as_str() {
+            "percent" => (fee * discount.value).round() / 100.0,
+            _ => discount.value,
+        }
+        .min(remaining);
+        if amount <= AMOUNT_EPSILON {
+            continue;
+        }
+
+        lines.push((discount, amount));
+        remaining -= amount;
+    }
+
+    lines
+}
+
+pub fn discount_label(discount: &Discount) -> String {
+    match discount.kind.as_str() {
+        "percent" => format!("{} ({} %)", discount.name, discount.value),
+        _ => discount.name.clone(),
+    }
+}
+
+/// Expected and counted amounts per payment method of a cash session.
+/// `collected` holds (method, payment count, amount) and `refunded`
+/// (method, amount); cash always has a line since it holds the float.
+/// Methods missing from `counted` are left without a count.
+pub fn session_totals(
+    opening_float: f64,
+    collected: &[(String, i64, f64)],
+    refunded: &[(String, f64)],
+    counted: Option<&HashMap<String, f64>>,
+) -> Vec<CashSessionTotal> {
+    let mut methods = vec!["cash".to_string()];
+    let others = collected
+        .iter()
+        .map(|(method, _, _)| method)
+        .chain(refunded.iter().map(|(method, _)| method))
+        .chain(counted.into_iter().flat_map(|c| c.keys()));
+    for method in others {
+        if !methods.contains(method) {
+            methods.push(method.clone());
+        }
+    }
+
+    methods
+        .into_iter()
+        .map(|method| {
+            let (payment_count, collected) = collected
+                .iter()
+                .filter(|(m, _, _)| *m == method)
+                .fold((0, 0.0), |(count, sum), (_, c, amount)| (count + c, sum + amount));
+            let refunded = refunded.iter().filter(|(m, _)| *m == method).fold(0.0, |sum, (_, amount)| sum + amount);
+            let float = if method == "cash" { opening_float } else { 0.0 };
+            let expected = float + collected - refunded;
+            let counted = counted.and_then(|c| c.get(&method).copied());
+
+            CashSessionTotal {
+                payment_method: method,
+                payment_count,
+                collected,
+                refunded,
+                expected,
+                counted,
+                difference: counted.map(|c| c - expected),
+            }
+        })
+        .collect()
+}
+
+/// Revenue, expenses and net profit per local month between `from` and
+/// `to` (`YYYY-MM`, inclusive). Refunds count in the month they were made
+/// and against the payment type of the refunded payment.
+pub fn profit_report(
+    payments: &[Payment],
+    reversals: &[PaymentReversal],
+    expenses: &[Expense],
+    from: Option<&str>,
+    to: Option<&str>,
+) -> ProfitReport {
+    let month_of = |date: DateTime<Utc>| date.with_timezone(&Local).format("%Y-%m").to_string();
+    let in_range = |month: &String| from.map_or(true, |f| month.as_str() >= f) && to.map_or(true, |t| month.as_str() <= t);
+
+    // (revenue, refunds, expenses) per month and amount per (month, kind, category)
+    let mut months: BTreeMap<String, (f64, f64, f64)> = BTreeMap::new();
+    let mut categories: BTreeMap<(String, &str, String), f64> = BTreeMap::new();
+
+    for payment in payments {
+        let month = month_of(payment.payment_date);
+        if in_range(&month) {
+            months.entry(month.clone()).or_default().0 += payment.amount;
+            *categories.entry((month, "revenue", payment.payment_type.clone())).or_default() += payment.amount;
+        }
+    }
+    let payment_types: HashMap<&str, &str> = payments.iter().map(|p| (p.id.as_str(), p.payment_type.as_str())).collect();
+    for reversal in reversals {
+        let month = month_of(reversal.created_at);
+        if in_range(&month) {
+            let payment_type = payment_types.get(reversal.payment_id.as_str()).copied().unwrap_or_default().to_string();
+            months.entry(month.clone()).or_default().1 += reversal.amount;
+            *categories.entry((month, "revenue", payment_type)).or_default() -= reversal.amount;
+        }
+    }
+    for expense in expenses {
+        let month = month_of(expense.expense_date);
+        if in_range(&month) {
+            months.entry(month.clone()).or_default().2 += expense.amount;
+            *categories.entry((month, "expense", expense.category.clone())).or_default() += expense.amount;
+        }
+    }
+
+    let months: Vec<MonthlyProfit> = months
+        .into_iter()
+        .map(|(month, (collected, refunds, expenses))| MonthlyProfit {
+            month,
+            revenue: collected - refunds,
+            refunds,
+            expenses,
+            net_profit: collected - refunds - expenses,
+        })
+        .collect();
+    let total_revenue = months.iter().map(|m| m.revenue).fold(0.0, |a, b| a + b);
+    let total_expenses = months.iter().map(|m| m.expenses).fold(0.0, |a, b| a + b);
+
+    ProfitReport {
+        months,
+        categories: categories
+            .into_iter()
+            .map(|((month, kind, category), amount)| CategoryTotal { month, kind: kind.to_string(), category, amount })
+            .collect(),
+        total_revenue,
+        total_expenses,
+        net_profit: total_revenue - total_expenses,
+    }
+}
+
+/// Label shown on statements for a ledger line.
+pub fn ledger_description(entry_type: &str, reference: Option<&str>, detail: Option<&str>) -> String {
+    let reference = reference.unwrap_or("-");
+    match (entry_type, detail) {
+        ("installment", _) => format!("Mensualité {}", reference),
+        ("late_fee", _) => format!("Pénalité de retard {}", reference),
+        ("discount", Some(label)) => format!("Réduction {} : {}", reference, label),
+        ("waiver", Some(reason)) => format!("Pénalité annulée {} : {}", reference, reason),
+        ("waiver", None) => format!("Pénalité annulée {}", reference),
+        ("payment", Some(detail)) => format!("Paiement {} ({})", reference, detail),
+        ("cancellation", Some(reason)) => format!("Annulation - avoir {} : {}", reference, reason),
+        ("refund", Some(reason)) => format!("Remboursement - avoir {} : {}", reference, reason),
+        (other, _) => format!("{} {}", other, reference),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open_due(id: &str, amount: f64, paid_amount: f64) -> OpenDue {
+        OpenDue {
+            id: id.to_string(),
+            month: format!("2026-{}", id),
+            due_date: Utc.with_ymd_and_hms(2026, 10, 5, 0, 0, 0).unwrap(),
+            amount,
+            paid_amount,
+        }
+    }
+
+    fn discount(name: &str, kind: &str, value: f64) -> Discount {
+        Discount {
+            id: name.to_string(),
+            name: name.to_string(),
+            scope: "family".to_string(),
+            target: None,
+            kind: kind.to_string(),
+            value,
+            min_siblings: 2,
+            starts_on: None,
+            ends_on: None,
+            is_active: true,
+            notes: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn payment(id: &str, payment_type: &str, amount: f64, date: DateTime<Utc>) -> Payment {
+        Payment {
+            id: id.to_string(),
+            student_id: "s1".to_string(),
+            amount,
+            payment_type: payment_type.to_string(),
+            payment_method: "cash".to_string(),
+            payment_date: date,
+            due_date: None,
+            status: "paid".to_string(),
+            notes: None,
+            receipt_number: None,
+            cashier: None,
+            sessions: 0,
+            cash_session_id: None,
+            group_id: None,
+            created_at: date,
+            updated_at: date,
+        }
+    }
+
+    fn mid_month(year: i32, month: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, 15, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn due_status_tolerates_half_a_centime() {
+        assert_eq!(due_status(1000.0, 1000.0), "paid");
+        assert_eq!(due_status(1000.0, 999.996), "paid");
+        assert_eq!(due_status(1000.0, 999.99), "partial");
+        assert_eq!(due_status(1000.0, 0.004), "unpaid");
+        assert_eq!(due_status(1000.0, 0.0), "unpaid");
+    }
+
+    #[test]
+    fn allocate_settles_dues_in_order_and_leaves_credit() {
+        let dues = [open_due("09", 1000.0, 0.0), open_due("10", 1000.0, 400.0), open_due("11", 1000.0, 0.0)];
+
+        let allocations = allocate(1800.0, &dues, true).unwrap();
+        let applied: Vec<(&str, f64, &str)> = allocations.iter().map(|a| (a.due.id.as_str(), a.amount, a.status)).collect();
+        assert_eq!(applied, [("09", 1000.0, "paid"), ("10", 600.0, "paid"), ("11", 200.0, "partial")]);
+
+        let allocations = allocate(5000.0, &dues, true).unwrap();
+        assert_eq!(allocations.iter().map(|a| a.amount).sum::<f64>(), 2600.0);
+    }
+
+    #[test]
+    fn allocate_skips_settled_dues() {
+        let dues = [open_due("09", 1000.0, 1000.0), open_due("10", 1000.0, 0.0)];
+        let allocations = allocate(1000.0, &dues, false).unwrap();
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].due.id, "10");
+    }
+
+    #[test]
+    fn allocate_without_partial_payments() {
+        let dues = [open_due("09", 1000.0, 0.0), open_due("10", 1000.0, 0.0)];
+
+        assert!(allocate(600.0, &dues, false).is_err());
+
+        // The second due cannot be settled: it is left alone and the rest is credit
+        let allocations = allocate(1500.0, &dues, false).unwrap();
+        assert_eq!(allocations.len(), 1);
+        assert_eq!((allocations[0].amount, allocations[0].status), (1000.0, "paid"));
+    }
+
+    #[test]
+    fn discount_lines_are_computed_on_the_full_fee_and_capped() {
+        let discounts = [discount("Fratrie", "percent", 10.0), discount("Bourse", "fixed", 500.0)];
+        let lines: Vec<(&str, f64)> = discount_lines(2500.0, NaiveDate::from_ymd_opt(2026, 10, 5).unwrap(), &discounts)
+            .into_iter()
+            .map(|(d, amount)| (d.name.as_str(), amount))
+            .collect();
+        assert_eq!(lines, [("Fratrie", 250.0), ("Bourse", 500.0)]);
+
+        let discounts = [discount("Bourse", "fixed", 800.0), discount("Gratuité", "percent", 100.0)];
+        let lines = discount_lines(1000.0, NaiveDate::from_ymd_opt(2026, 10, 5).unwrap(), &discounts);
+        assert_eq!(lines.iter().map(|(_, amount)| *amount).collect::<Vec<_>>(), [800.0, 200.0]);
+    }
+
+    #[test]
+    fn discount_lines_respect_the_validity_period() {
+        let mut seasonal = discount("Rentrée", "fixed", 300.0);
+        seasonal.starts_on = NaiveDate::from_ymd_opt(2026, 9, 1);
+        seasonal.ends_on = NaiveDate::from_ymd_opt(2026, 10, 31);
+        let discounts = [seasonal];
+
+        assert_eq!(discount_lines(1000.0, NaiveDate::from_ymd_opt(2026, 10, 31).unwrap(), &discounts).len(), 1);
+        assert!(discount_lines(1000.0, NaiveDate::from_ymd_opt(2026, 11, 1).unwrap(), &discounts).is_empty());
+        assert!(discount_lines(1000.0, NaiveDate::from_ymd_opt(2026, 8, 31).unwrap(), &discounts).is_empty());
+    }
+
+    #[test]
+    fn installment_dates_clamp_the_due_day() {
+        let dates = installment_dates("2026-01", 3, 31).unwrap();
+        let days: Vec<String> = dates.iter().map(|(_, d)| d.to_string()).collect();
+        assert_eq!(days, ["2026-01-31", "2026-02-28", "2026-03-31"]);
+        assert!(installment_dates("2026-13", 3, 5).is_err());
+        assert!(installment_dates("2026-01", 0, 5).is_err());
+    }
+
+    #[test]
+    fn session_totals_per_method() {
+        let collected = [("cash".to_string(), 3, 4500.0), ("check".to_string(), 1, 2000.0)];
+        let refunded = [("cash".to_string(), 500.0)];
+        let counted = HashMap::from([("cash".to_string(), 8900.0), ("bank".to_string(), 0.0)]);
+
+        let totals = session_totals(5000.0, &collected, &refunded, Some(&counted));
+        let methods: Vec<&str> = totals.iter().map(|t| t.payment_method.as_str()).collect();
+        assert_eq!(methods, ["cash", "check", "bank"]);
+
+        let cash = &totals[0];
+        assert_eq!((cash.payment_count, cash.collected, cash.refunded, cash.expected), (3, 4500.0, 500.0, 9000.0));
+        assert_eq!(cash.difference, Some(-100.0));
+        assert_eq!((totals[1].expected, totals[1].counted), (2000.0, None));
+        assert_eq!(totals[2].difference, Some(0.0));
+    }
+
+    #[test]
+    fn session_totals_always_list_cash() {
+        let totals = session_totals(1000.0, &[], &[], None);
+        assert_eq!(totals.len(), 1);
+        assert_eq!((totals[0].payment_method.as_str(), totals[0].expected, totals[0].counted), ("cash", 1000.0, None));
+    }
+
+    #[test]
+    fn profit_report_counts_refunds_in_their_own_month() {
+        let payments = [
+            payment("p1", "Mensualité", 3000.0, mid_month(2026, 9)),
+            payment("p2", "Inscription", 1000.0, mid_month(2026, 9)),
+            payment("p3", "Mensualité", 2000.0, mid_month(2026, 10)),
+        ];
+        let reversals = [PaymentReversal {
+            id: "r1".to_string(),
+            payment_id: "p1".to_string(),
+            kind: "refund".to_string(),
+            amount: 500.0,
+            sessions: 0,
+            refund_method: Some("cash".to_string()),
+            reason: "Départ".to_string(),
+            created_by: "admin".to_string(),
+            credit_note_number: "REC-AV-2026-000001".to_string(),
+            created_at: mid_month(2026, 10),
+        }];
+        let expenses = [Expense {
+            id: "e1".to_string(),
+            category: "rent".to_string(),
+            description: "Loyer".to_string(),
+            amount: 1500.0,
+            expense_date: mid_month(2026, 10),
+            payment_method: "bank".to_string(),
+            supplier: None,
+            receipt_path: None,
+            notes: None,
+            created_by: None,
+            created_at: mid_month(2026, 10),
+            updated_at: mid_month(2026, 10),
+        }];
+
+        let report = profit_report(&payments, &reversals, &expenses, None, None);
+        let months: Vec<(&str, f64, f64, f64, f64)> = report
+            .months
+            .iter()
+            .map(|m| (m.month.as_str(), m.revenue, m.refunds, m.expenses, m.net_profit))
+            .collect();
+        assert_eq!(months, [("2026-09", 4000.0, 0.0, 0.0, 4000.0), ("2026-10", 1500.0, 500.0, 1500.0, 0.0)]);
+        assert_eq!((report.total_revenue, report.total_expenses, report.net_profit), (5500.0, 1500.0, 4000.0));
+
+        let october_fees = report
+            .categories
+            .iter()
+            .find(|c| c.month == "2026-10" && c.kind == "revenue" && c.category == "Mensualité")
+            .unwrap();
+        assert_eq!(october_fees.amount, 1500.0);
+
+        let report = profit_report(&payments, &reversals, &expenses, Some("2026-10"), Some("2026-10"));
+        assert_eq!(report.months.len(), 1);
+        assert_eq!(report.net_profit, 0.0);
+    }
+}
@@ -0,0 +1,135 @@
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, attachments, models::{Attachment, AttachmentFile}};
+use super::db_error;
+
+pub fn attachments_dir(app: &tauri::AppHandle) -> PathBuf {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("attachments"))
+        .unwrap_or_else(|| "attachments".into())
+}
+
+fn attachment_file(dir: &Path, attachment: Attachment) -> AttachmentFile {
+    let path = attachments::file_path(dir, &attachment.content_hash, &attachment.extension);
+    let thumbnail_path = attachment
+        .has_thumbnail
+        .then(|| attachments::thumbnail_path(dir, &attachment.content_hash).to_string_lossy().to_string());
+
+    AttachmentFile {
+        path: path.to_string_lossy().to_string(),
+        thumbnail_path,
+        attachment,
+    }
+}
+
+/// Copies `source_file` into the attachment store and links it to the
+/// student. A "photo" becomes the student's current photo.
+#[tauri::command]
+pub async fn upload_attachment(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    student_id: String,
+    kind: String,
+    source_file: String,
+    notes: Option<String>,
+    uploaded_by: Option<String>,
+) -> Result<AttachmentFile, String> {
+    if !attachments::ATTACHMENT_KINDS.contains(&kind.as_str()) {
+        return Err(format!("Unknown attachment kind {}", kind));
+    }
+    let file_name = Path::new(&source_file)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format!("Invalid file {}", source_file))?
+        .to_string();
+
+    let dir = attachments_dir(&app);
+    let stored = {
+        let dir = dir.clone();
+        tokio::task::spawn_blocking(move || attachments::store(&dir, Path::new(&source_file)))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(db_error)?
+    };
+    if kind == "photo" && !stored.has_thumbnail {
+        return Err(format!("{} is not a PNG or JPEG image", file_name));
+    }
+
+    let attachment = Attachment {
+        id: Uuid::new_v4().to_string(),
+        student_id,
+        kind,
+        file_name,
+        content_hash: stored.content_hash,
+        extension: stored.extension,
+        mime_type: stored.mime_type,
+        size: stored.size,
+        has_thumbnail: stored.has_thumbnail,
+        notes,
+        uploaded_by,
+        created_at: Utc::now(),
+    };
+
+    let db = state.db.lock().await;
+    let attachment = db.create_attachment(attachment).await.map_err(db_error)?;
+    log::info!(target: "attachments", "{} {} attached to student {}", attachment.kind, attachment.file_name, attachment.student_id);
+    Ok(attachment_file(&dir, attachment))
+}
+
+#[tauri::command]
+pub async fn get_attachments(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    student_id: String,
+    kind: Option<String>,
+) -> Result<Vec<AttachmentFile>, String> {
+    let dir = attachments_dir(&app);
+    let db = state.db.lock().await;
+    let attachments = db.get_attachments(&student_id, kind.as_deref()).await.map_err(db_error)?;
+    Ok(attachments.into_iter().map(|a| attachment_file(&dir, a)).collect())
+}
+
+/// Locates an attachment, or copies it to `save_as` when given.
+#[tauri::command]
+pub async fn get_attachment(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    attachment_id: String,
+    save_as: Option<String>,
+) -> Result<AttachmentFile, String> {
+    let dir = attachments_dir(&app);
+    let attachment = {
+        let db = state.db.lock().await;
+        db.get_attachment(&attachment_id)
+            .await
+            .map_err(db_error)?
+            .ok_or_else(|| format!("Attachment {} not found", attachment_id))?
+    };
+
+    let file = attachment_file(&dir, attachment);
+    if !Path::new(&file.path).exists() {
+        return Err(format!("The file of {} is missing from the attachment store", file.attachment.file_name));
+    }
+    if let Some(target) = save_as {
+        tokio::fs::copy(&file.path, &target).await.map_err(|e| e.to_string())?;
+    }
+    Ok(file)
+}
+
+/// Deletes an attachment, and its file when no other attachment shares it.
+#[tauri::command]
+pub async fn delete_attachment(app: tauri::AppHandle, state: State<'_, AppState>, attachment_id: String) -> Result<(), String> {
+    let (attachment, shared) = {
+        let db = state.db.lock().await;
+        db.delete_attachment(&attachment_id).await.map_err(db_error)?
+    };
+
+    if !shared {
+        attachments::remove(&attachments_dir(&app), &attachment.content_hash, &attachment.extension).map_err(db_error)?;
+    }
+    log::info!(target: "attachments", "{} {} removed from student {}", attachment.kind, attachment.file_name, attachment.student_id);
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use chrono::{Duration, Local, Utc};
+use tauri::State;
+use crate::{AppState, billing, schedule, database::Database, models::{Attendance, SessionOccurrence, Student}};
+use super::db_error;
+
+/// Matches a card scan of `student` to the session they are checked in
+/// to, or else their current or upcoming one, and records it. Returns
+/// nothing when no session is due.
+pub async fn record_scan(db: &Database, student: &Student) -> anyhow::Result<Option<(SessionOccurrence, Attendance)>> {
+    let settings = db.load_settings().await?.unwrap_or_default().attendance;
+    let now = Utc::now();
+
+    let since = billing::local_midnight(now.with_timezone(&Local).date_naive());
+    let checked_in = db.get_open_check_in(&student.id, since).await?;
+    let sessions = db
+        .get_student_sessions(&student.id, now, now + Duration::minutes(settings.check_in_window_minutes), checked_in.as_deref())
+        .await?;
+    let Some(session) = schedule::session_for_scan(&sessions, checked_in.as_deref(), now, settings.check_in_window_minutes) else {
+        return Ok(None);
+    };
+
+    let status = schedule::arrival_status(session, now, settings.grace_minutes);
+    let attendance = db.record_session_scan(&student.id, session, status, now).await?;
+    log::info!(
+        target: "attendance",
+        "{} {} scanned for {} ({})",
+        student.first_name,
+        student.last_name,
+        session.group_name,
+        attendance.status
+    );
+    Ok(Some((session.clone(), attendance)))
+}
+
+/// Marks absent the students of sessions that have ended, when enabled in
+/// `AttendanceSettings`.
+pub async fn run_absence_marking(db: &Database) -> anyhow::Result<Vec<Attendance>> {
+    let settings = db.load_settings().await?.unwrap_or_default();
+    if !settings.attendance.auto_mark_absent {
+        return Ok(vec![]);
+    }
+
+    let absences = db.mark_absentees(Utc::now()).await?;
+    if !absences.is_empty() {
+        log::info!(target: "attendance", "Marked {} absence(s) for ended sessions", absences.len());
+    }
+    Ok(absences)
+}
+
+#[tauri::command]
+pub async fn mark_absentees(state: State<'_, AppState>) -> Result<Vec<Attendance>, String> {
+    let db = state.db.lock().await;
+    run_absence_marking(&db).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_session_attendance(
+    state: State<'_, AppState>,
+    occurrence_id: String,
+) -> Result<Vec<Attendance>, String> {
+    let db = state.db.lock().await;
+    db.get_session_attendance(&occurrence_id).await.map_err(db_error)
+}
@@ -0,0 +1,74 @@
+use tauri::State;
+use crate::{AppState, cloud_sync, models::{BackupSettings, RemoteBackup, SyncReport}};
+use std::path::{Path, PathBuf};
+use super::db_error;
+
+async fn backup_settings(state: &State<'_, AppState>) -> Result<BackupSettings, String> {
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?;
+    settings
+        .map(|s| s.backup)
+        .ok_or_else(|| "Settings have not been saved yet".to_string())
+}
+
+#[tauri::command]
+pub async fn sync_backups(state: State<'_, AppState>) -> Result<SyncReport, String> {
+    let settings = backup_settings(&state).await?;
+    if !settings.cloud_sync {
+        return Err("Cloud sync is disabled".to_string());
+    }
+
+    cloud_sync::sync_backup_location(&settings).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn upload_backup(state: State<'_, AppState>, backup_path: String) -> Result<SyncReport, String> {
+    if !Path::new(&backup_path).exists() {
+        return Err("Backup file does not exist".to_string());
+    }
+
+    let settings = backup_settings(&state).await?;
+    cloud_sync::upload_backup(&settings, Path::new(&backup_path))
+        .await
+        .map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn list_remote_backups(state: State<'_, AppState>) -> Result<Vec<RemoteBackup>, String> {
+    let settings = backup_settings(&state).await?;
+    let store = cloud_sync::RemoteStore::from_settings(&settings).map_err(db_error)?;
+
+    let mut backups = store.list_backups().await.map_err(db_error)?;
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.last_modified));
+    Ok(backups)
+}
+
+#[tauri::command]
+pub async fn restore_from_remote(app: tauri::AppHandle, state: State<'_, AppState>, name: String) -> Result<String, String> {
+    // The name becomes a local path: only a bare file name listed by the remote is accepted
+    if Path::new(&name).file_name() != Some(name.as_ref()) {
+        return Err(format!("Invalid backup name: {}", name));
+    }
+
+    let settings = backup_settings(&state).await?;
+    let store = cloud_sync::RemoteStore::from_settings(&settings).map_err(db_error)?;
+    let remote = store.list_backups().await.map_err(db_error)?;
+    if !remote.iter().any(|backup| backup.name == name) {
+        return Err(format!("Backup {} not found on the remote", name));
+    }
+
+    let folder = if settings.backup_location.is_empty() {
+        std::env::temp_dir()
+    } else {
+        PathBuf::from(&settings.backup_location)
+    };
+    let target = folder.join(&name);
+
+    store.download(&name, &target).await.map_err(db_error)?;
+    // Fetched before the database is replaced, so a failure leaves nothing half restored
+    let downloaded = cloud_sync::download_attachments(&store, &folder)
+        .await
+        .map_err(|e| format!("Attachments could not be downloaded, nothing was restored: {}", e))?;
+    log::info!(target: "commands", "Restoring {} from the remote with {} new attachment file(s)", name, downloaded);
+    super::system::restore_database(app, target.to_string_lossy().to_string()).await
+}
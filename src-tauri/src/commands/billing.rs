@@ -0,0 +1,390 @@
+use chrono::{DateTime, Local, Months, NaiveDate, Utc};
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, billing, database::{Database, ReversalDetails}, models::{AppSettings, CalendarDay, Discount, Due, DueDiscount, LedgerEntry, PaymentPlan, PaymentReversal, Student, StudentBalance, StudentStatement}};
+use super::db_error;
+
+/// Creates a monthly plan for a student. The fee defaults to the session
+/// price configured for the student's academic level.
+#[tauri::command]
+pub async fn create_payment_plan(
+    state: State<'_, AppState>,
+    student_id: String,
+    installments: u32,
+    start_month: String,
+    monthly_fee: Option<f64>,
+    due_day: Option<u32>,
+    notes: Option<String>,
+) -> Result<PaymentPlan, String> {
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let student = db
+        .get_student(&student_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Student {} not found", student_id))?;
+
+    new_payment_plan(&db, &settings, &student, installments, start_month, monthly_fee, due_day.unwrap_or(1), notes).await
+}
+
+/// Saves a plan for `student` in the configured academic year with its
+/// dues, discounted when the fee comes from the level's price.
+pub async fn new_payment_plan(
+    db: &Database,
+    settings: &AppSettings,
+    student: &Student,
+    installments: u32,
+    start_month: String,
+    monthly_fee: Option<f64>,
+    due_day: u32,
+    notes: Option<String>,
+) -> Result<PaymentPlan, String> {
+    // Discounts only apply to the configured price, not to a negotiated fee
+    let (monthly_fee, discounts) = match monthly_fee {
+        Some(fee) => (fee, vec![]),
+        None => {
+            let fee = *settings
+                .payments
+                .default_session_price
+                .get(&student.academic_level)
+                .ok_or_else(|| format!("No monthly fee configured for {}", student.academic_level))?;
+            (fee, student_discounts(db, student).await.map_err(db_error)?)
+        }
+    };
+    if monthly_fee <= 0.0 {
+        return Err("The monthly fee must be positive".to_string());
+    }
+
+    let schedule = billing::installment_dates(&start_month, installments, due_day).map_err(db_error)?;
+
+    let plan = PaymentPlan {
+        id: Uuid::new_v4().to_string(),
+        student_id: student.id.clone(),
+        academic_year: settings.general.academic_year.clone(),
+        monthly_fee,
+        installments: installments as i64,
+        start_month,
+        due_day: due_day as i64,
+        notes,
+        created_at: Utc::now(),
+    };
+
+    db.create_payment_plan(plan, &schedule, &discounts).await.map_err(db_error)
+}
+
+/// Active discount rules covering `student`.
+pub async fn student_discounts(db: &Database, student: &Student) -> anyhow::Result<Vec<Discount>> {
+    let siblings = db.count_family_students(student).await?;
+
+    Ok(db
+        .get_discounts(true)
+        .await?
+        .into_iter()
+        .filter(|d| billing::discount_applies(d, student, siblings))
+        .collect())
+}
+
+#[tauri::command]
+pub async fn create_discount(
+    state: State<'_, AppState>,
+    name: String,
+    scope: String,
+    target: Option<String>,
+    kind: String,
+    value: f64,
+    min_siblings: Option<i64>,
+    starts_on: Option<String>,
+    ends_on: Option<String>,
+    notes: Option<String>,
+) -> Result<Discount, String> {
+    let discount = Discount {
+        id: Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        scope,
+        target: target.filter(|t| !t.trim().is_empty()),
+        kind,
+        value,
+        min_siblings: min_siblings.unwrap_or(0),
+        starts_on: starts_on.as_deref().map(parse_day).transpose()?,
+        ends_on: ends_on.as_deref().map(parse_day).transpose()?,
+        is_active: true,
+        notes,
+        created_at: Utc::now(),
+    };
+    billing::validate_discount(&discount).map_err(db_error)?;
+
+    let db = state.db.lock().await;
+    db.create_discount(discount).await.map_err(db_error)
+}
+
+/// Updates a rule, including turning it off through `is_active`. Dues
+/// already generated are not recalculated.
+#[tauri::command]
+pub async fn update_discount(state: State<'_, AppState>, discount: Discount) -> Result<Discount, String> {
+    billing::validate_discount(&discount).map_err(db_error)?;
+
+    let db = state.db.lock().await;
+    db.update_discount(discount).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_discounts(state: State<'_, AppState>, active_only: Option<bool>) -> Result<Vec<Discount>, String> {
+    let db = state.db.lock().await;
+    db.get_discounts(active_only.unwrap_or(false)).await.map_err(db_error)
+}
+
+/// Discount lines of dues falling between `from` and `to` (YYYY-MM-DD, inclusive).
+#[tauri::command]
+pub async fn get_discount_lines(
+    state: State<'_, AppState>,
+    student_id: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<DueDiscount>, String> {
+    let from = from.as_deref().map(parse_day).transpose()?.map(billing::local_midnight);
+    let to = to
+        .as_deref()
+        .map(parse_day)
+        .transpose()?
+        .map(|to| billing::local_midnight(to + chrono::Days::new(1)));
+
+    let db = state.db.lock().await;
+    db.get_due_discounts(student_id.as_deref(), None, from, to).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_payment_plans(state: State<'_, AppState>, student_id: Option<String>) -> Result<Vec<PaymentPlan>, String> {
+    let db = state.db.lock().await;
+    db.get_payment_plans(student_id.as_deref()).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_dues(
+    state: State<'_, AppState>,
+    student_id: Option<String>,
+    status: Option<String>,
+) -> Result<Vec<Due>, String> {
+    let db = state.db.lock().await;
+    db.get_dues(student_id.as_deref(), status.as_deref(), None, None)
+        .await
+        .map_err(db_error)
+}
+
+/// Dues not fully paid whose due date has passed, plus upcoming ones when
+/// `include_upcoming` is set.
+#[tauri::command]
+pub async fn get_outstanding_dues(state: State<'_, AppState>, include_upcoming: Option<bool>) -> Result<Vec<Due>, String> {
+    let until = if include_upcoming.unwrap_or(false) {
+        None
+    } else {
+        Some(billing::local_midnight(Local::now().date_naive() + chrono::Days::new(1)))
+    };
+
+    let db = state.db.lock().await;
+    db.get_dues(None, Some("outstanding"), None, until)
+        .await
+        .map_err(db_error)
+}
+
+/// Dues of `month` (1-12) grouped by due date.
+#[tauri::command]
+pub async fn get_payment_calendar(state: State<'_, AppState>, year: i32, month: u32) -> Result<Vec<CalendarDay>, String> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(|| format!("Invalid month {}-{}", year, month))?;
+    let next = first + Months::new(1);
+
+    let db = state.db.lock().await;
+    let dues = db
+        .get_dues(None, None, Some(billing::local_midnight(first)), Some(billing::local_midnight(next)))
+        .await
+        .map_err(db_error)?;
+
+    let mut days: Vec<CalendarDay> = Vec::new();
+    for due in dues {
+        let date = due.due_date.with_timezone(&Local).date_naive();
+        if days.last().map(|d| d.date) != Some(date) {
+            days.push(CalendarDay { date, expected_amount: 0.0, paid_amount: 0.0, dues: vec![] });
+        }
+        if let Some(day) = days.last_mut() {
+            day.expected_amount += due.amount;
+            day.paid_amount += due.paid_amount;
+            day.dues.push(due);
+        }
+    }
+
+    Ok(days)
+}
+
+/// Charges the late fees due today according to `PaymentSettings`.
+pub async fn run_late_fees(db: &Database) -> anyhow::Result<Vec<Due>> {
+    let settings = db.load_settings().await?.unwrap_or_default();
+    if settings.payments.late_fee_amount <= 0.0 {
+        return Ok(vec![]);
+    }
+
+    let fees = db
+        .apply_late_fees(settings.payments.late_fee_amount, settings.payments.late_fee_after_days as i64, Utc::now())
+        .await?;
+    if !fees.is_empty() {
+        log::info!(target: "billing", "Charged {} late fee(s) of {}", fees.len(), settings.payments.late_fee_amount);
+    }
+    Ok(fees)
+}
+
+#[tauri::command]
+pub async fn apply_late_fees(state: State<'_, AppState>) -> Result<Vec<Due>, String> {
+    let db = state.db.lock().await;
+    run_late_fees(&db).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn waive_late_fee(
+    state: State<'_, AppState>,
+    due_id: String,
+    reason: String,
+    waived_by: String,
+) -> Result<Due, String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required to waive a late fee".to_string());
+    }
+
+    let db = state.db.lock().await;
+    let due = db.waive_late_fee(&due_id, reason.trim(), &waived_by).await.map_err(db_error)?;
+    log::info!(target: "billing", "Late fee {} waived by {}: {}", due_id, waived_by, reason.trim());
+    Ok(due)
+}
+
+#[tauri::command]
+pub async fn get_student_balance(state: State<'_, AppState>, student_id: String) -> Result<StudentBalance, String> {
+    let db = state.db.lock().await;
+    db.get_student_balance(&student_id, Utc::now()).await.map_err(db_error)
+}
+
+/// Account lines between `from` and `to` (RFC 3339 or YYYY-MM-DD, both
+/// optional), with the balance carried over from earlier lines.
+#[tauri::command]
+pub async fn get_student_statement(
+    state: State<'_, AppState>,
+    student_id: String,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<StudentStatement, String> {
+    let from = from.as_deref().map(parse_bound).transpose()?;
+    let to = match to.as_deref() {
+        // A plain end date includes the whole day
+        Some(to) if to.len() == 10 => billing::local_midnight(parse_day(to)? + chrono::Days::new(1)),
+        Some(to) => parse_bound(to)?,
+        None => Utc::now(),
+    };
+
+    let db = state.db.lock().await;
+    let student = db
+        .get_student(&student_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Student {} not found", student_id))?;
+    let ledger = db.get_ledger(&student_id, to).await.map_err(db_error)?;
+
+    let (earlier, entries): (Vec<LedgerEntry>, Vec<LedgerEntry>) =
+        ledger.into_iter().partition(|e| from.is_some_and(|from| e.date < from));
+    let opening_balance = earlier.last().map(|e| e.balance).unwrap_or(0.0);
+    let closing_balance = entries.last().map(|e| e.balance).unwrap_or(opening_balance);
+
+    Ok(StudentStatement {
+        student_id,
+        student_name: format!("{} {}", student.first_name, student.last_name),
+        academic_level: student.academic_level,
+        from,
+        to,
+        opening_balance,
+        entries,
+        closing_balance,
+    })
+}
+
+pub(super) fn parse_day(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("Invalid date {}", value))
+}
+
+pub(super) fn parse_bound(value: &str) -> Result<DateTime<Utc>, String> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(date) => Ok(date.with_timezone(&Utc)),
+        Err(_) => parse_day(value).map(billing::local_midnight),
+    }
+}
+
+async fn reverse_payment(
+    app: &tauri::AppHandle,
+    db: &Database,
+    payment_id: &str,
+    kind: &str,
+    amount: Option<f64>,
+    sessions: Option<i64>,
+    refund_method: Option<&str>,
+    reason: &str,
+    created_by: &str,
+) -> Result<PaymentReversal, String> {
+    if reason.trim().is_empty() {
+        return Err("A reason is required to reverse a payment".to_string());
+    }
+
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let details = ReversalDetails {
+        reason: reason.trim(),
+        created_by,
+        refund_method,
+        academic_year: &settings.general.academic_year,
+        prefix: &settings.payments.receipt_prefix,
+    };
+    let reversal = db
+        .reverse_payment(payment_id, kind, amount, sessions, details)
+        .await
+        .map_err(db_error)?;
+    log::info!(
+        target: "billing",
+        "{} of {:.2} on payment {} by {}: {}",
+        kind, reversal.amount, payment_id, created_by, reversal.reason
+    );
+
+    // The reversal is recorded either way; the credit note can be generated again.
+    if let Err(e) = super::receipts::write_credit_note(db, super::receipts::receipts_dir(app), &reversal).await {
+        log::error!(target: "receipts", "Failed to generate credit note {}: {:#}", reversal.credit_note_number, e);
+    }
+
+    Ok(reversal)
+}
+
+/// Voids a payment recorded by mistake. The payment keeps its receipt
+/// number; its dues reopen and its sessions are taken back.
+#[tauri::command]
+pub async fn cancel_payment(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    payment_id: String,
+    reason: String,
+    cancelled_by: String,
+) -> Result<PaymentReversal, String> {
+    let db = state.db.lock().await;
+    reverse_payment(&app, &db, &payment_id, "cancellation", None, None, None, &reason, &cancelled_by).await
+}
+
+/// Gives back all or part of a payment (`amount` defaults to what is left).
+#[tauri::command]
+pub async fn refund_payment(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    payment_id: String,
+    amount: Option<f64>,
+    sessions: Option<i64>,
+    refund_method: Option<String>,
+    reason: String,
+    refunded_by: String,
+) -> Result<PaymentReversal, String> {
+    let db = state.db.lock().await;
+    reverse_payment(&app, &db, &payment_id, "refund", amount, sessions, refund_method.as_deref(), &reason, &refunded_by).await
+}
+
+#[tauri::command]
+pub async fn get_payment_reversals(state: State<'_, AppState>, payment_id: Option<String>) -> Result<Vec<PaymentReversal>, String> {
+    let db = state.db.lock().await;
+    db.get_payment_reversals(payment_id.as_deref()).await.map_err(db_error)
+}
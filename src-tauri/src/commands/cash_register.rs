@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::path::Path;
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, export, models::{CashSession, ZReport}};
+use super::db_error;
+
+/// Opens the cash drawer with `opening_float` in cash. Only one session can
+/// be open at a time.
+#[tauri::command]
+pub async fn open_cash_session(
+    state: State<'_, AppState>,
+    opened_by: String,
+    opening_float: f64,
+    notes: Option<String>,
+) -> Result<CashSession, String> {
+    if opening_float < 0.0 {
+        return Err("The opening float cannot be negative".to_string());
+    }
+
+    let session = CashSession {
+        id: Uuid::new_v4().to_string(),
+        opened_by,
+        opened_at: Utc::now(),
+        opening_float,
+        status: "open".to_string(),
+        closed_by: None,
+        closed_at: None,
+        notes,
+    };
+
+    let db = state.db.lock().await;
+    let session = db.open_cash_session(session).await.map_err(db_error)?;
+    log::info!(target: "cash_register", "Cash session {} opened by {} with {:.2}", session.id, session.opened_by, opening_float);
+    Ok(session)
+}
+
+#[tauri::command]
+pub async fn get_current_cash_session(state: State<'_, AppState>) -> Result<Option<CashSession>, String> {
+    let db = state.db.lock().await;
+    db.get_open_cash_session().await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_cash_sessions(state: State<'_, AppState>) -> Result<Vec<CashSession>, String> {
+    let db = state.db.lock().await;
+    db.get_cash_sessions().await.map_err(db_error)
+}
+
+/// Closes the session with the amounts counted per payment method
+/// (`{"cash": 12500, "check": 5000}`). Cash must be counted.
+#[tauri::command]
+pub async fn close_cash_session(
+    state: State<'_, AppState>,
+    session_id: String,
+    closed_by: String,
+    counted: HashMap<String, f64>,
+    notes: Option<String>,
+) -> Result<ZReport, String> {
+    if !counted.contains_key("cash") {
+        return Err("The cash in the drawer must be counted before closing".to_string());
+    }
+
+    let db = state.db.lock().await;
+    let report = db
+        .close_cash_session(&session_id, &closed_by, &counted, notes.as_deref())
+        .await
+        .map_err(db_error)?;
+
+    for total in report.totals.iter().filter(|t| t.difference.is_some_and(|d| d.abs() > crate::billing::AMOUNT_EPSILON)) {
+        log::warn!(
+            target: "cash_register",
+            "Cash session {}: {} counted {:.2} for {:.2} expected",
+            session_id, total.payment_method, total.counted.unwrap_or(0.0), total.expected
+        );
+    }
+    log::info!(target: "cash_register", "Cash session {} closed by {}", session_id, closed_by);
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn get_z_report(state: State<'_, AppState>, session_id: String) -> Result<ZReport, String> {
+    let db = state.db.lock().await;
+    db.z_report(&session_id).await.map_err(db_error)
+}
+
+/// Writes the Z-report as JSON, CSV or XLSX depending on the file extension.
+#[tauri::command]
+pub async fn export_z_report(state: State<'_, AppState>, session_id: String, file_path: String) -> Result<String, String> {
+    let report = {
+        let db = state.db.lock().await;
+        db.z_report(&session_id).await.map_err(db_error)?
+    };
+
+    let path = Path::new(&file_path);
+    let format = path.extension().and_then(|e| e.to_str()).unwrap_or("json").to_lowercase();
+    match format.as_str() {
+        "json" => {
+            let json = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+            tokio::fs::write(path, json).await.map_err(|e| e.to_string())?;
+        }
+        "csv" => {
+            export::write_csv(path, &[export::z_report_sheet(&report)], b',').map_err(db_error)?;
+        }
+        "xlsx" => {
+            export::write_xlsx(path, &[export::z_report_sheet(&report)]).map_err(db_error)?;
+        }
+        _ => return Err(format!("Unsupported export format: {}", format)),
+    }
+
+    Ok(format!("Exported Z-report to {}", file_path))
+}
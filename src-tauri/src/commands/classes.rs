@@ -0,0 +1,240 @@
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, models::{ClassGroup, Enrollment, Subject, Teacher}};
+use super::db_error;
+
+fn validate_teacher(teacher: &Teacher) -> Result<(), String> {
+    if teacher.first_name.trim().is_empty() || teacher.last_name.trim().is_empty() {
+        return Err("A teacher needs a first and last name".to_string());
+    }
+    Ok(())
+}
+
+fn validate_group(group: &ClassGroup) -> Result<(), String> {
+    if group.name.trim().is_empty() {
+        return Err("A group needs a name".to_string());
+    }
+    if group.academic_level.trim().is_empty() {
+        return Err("A group needs an academic level".to_string());
+    }
+    if group.capacity <= 0 {
+        return Err("The group capacity must be positive".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn create_teacher(
+    state: State<'_, AppState>,
+    first_name: String,
+    last_name: String,
+    phone: Option<String>,
+    email: Option<String>,
+    notes: Option<String>,
+) -> Result<Teacher, String> {
+    let teacher = Teacher {
+        id: Uuid::new_v4().to_string(),
+        first_name: first_name.trim().to_string(),
+        last_name: last_name.trim().to_string(),
+        phone,
+        email,
+        is_active: true,
+        notes,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    validate_teacher(&teacher)?;
+
+    let db = state.db.lock().await;
+    db.create_teacher(teacher).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_teacher(state: State<'_, AppState>, teacher: Teacher) -> Result<Teacher, String> {
+    validate_teacher(&teacher)?;
+    let db = state.db.lock().await;
+    db.update_teacher(teacher).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_teachers(state: State<'_, AppState>, active_only: Option<bool>) -> Result<Vec<Teacher>, String> {
+    let db = state.db.lock().await;
+    db.get_teachers(active_only.unwrap_or(false)).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_teacher(state: State<'_, AppState>, teacher_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_teacher(&teacher_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn create_subject(
+    state: State<'_, AppState>,
+    name: String,
+    code: Option<String>,
+    description: Option<String>,
+) -> Result<Subject, String> {
+    if name.trim().is_empty() {
+        return Err("A subject needs a name".to_string());
+    }
+    let subject = Subject {
+        id: Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        code,
+        description,
+        created_at: Utc::now(),
+    };
+
+    let db = state.db.lock().await;
+    db.create_subject(subject).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_subject(state: State<'_, AppState>, subject: Subject) -> Result<Subject, String> {
+    if subject.name.trim().is_empty() {
+        return Err("A subject needs a name".to_string());
+    }
+    let db = state.db.lock().await;
+    db.update_subject(subject).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_subjects(state: State<'_, AppState>) -> Result<Vec<Subject>, String> {
+    let db = state.db.lock().await;
+    db.get_subjects().await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_subject(state: State<'_, AppState>, subject_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_subject(&subject_id).await.map_err(db_error)
+}
+
+/// Opens a group for a level and subject, in the current academic year
+/// unless `academic_year` is given.
+#[tauri::command]
+pub async fn create_class_group(
+    state: State<'_, AppState>,
+    name: String,
+    academic_level: String,
+    subject_id: String,
+    teacher_id: Option<String>,
+    capacity: i64,
+    academic_year: Option<String>,
+    notes: Option<String>,
+) -> Result<ClassGroup, String> {
+    let db = state.db.lock().await;
+    let academic_year = match academic_year {
+        Some(year) => year,
+        None => db.load_settings().await.map_err(db_error)?.unwrap_or_default().general.academic_year,
+    };
+
+    let group = ClassGroup {
+        id: Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        academic_level,
+        subject_id,
+        teacher_id,
+        capacity,
+        academic_year,
+        is_active: true,
+        notes,
+        subject_name: String::new(),
+        teacher_name: None,
+        enrolled_count: 0,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    validate_group(&group)?;
+
+    db.create_class_group(group).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_class_group(state: State<'_, AppState>, group: ClassGroup) -> Result<ClassGroup, String> {
+    validate_group(&group)?;
+    let db = state.db.lock().await;
+    db.update_class_group(group).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_class_groups(
+    state: State<'_, AppState>,
+    academic_level: Option<String>,
+    teacher_id: Option<String>,
+    active_only: Option<bool>,
+) -> Result<Vec<ClassGroup>, String> {
+    let db = state.db.lock().await;
+    db.get_class_groups(academic_level.as_deref(), teacher_id.as_deref(), active_only.unwrap_or(false))
+        .await
+        .map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_class_group(state: State<'_, AppState>, group_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_class_group(&group_id).await.map_err(db_error)
+}
+
+/// Enrolls a student in a group of their academic level.
+#[tauri::command]
+pub async fn enroll_student(
+    state: State<'_, AppState>,
+    group_id: String,
+    student_id: String,
+    notes: Option<String>,
+) -> Result<Enrollment, String> {
+    let db = state.db.lock().await;
+    let student = db
+        .get_student(&student_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Student {} not found", student_id))?;
+    let group = db
+        .get_class_group(&group_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Group {} not found", group_id))?;
+    if student.academic_level != group.academic_level {
+        return Err(format!(
+            "{} {} is in {}, group {} is for {}",
+            student.first_name, student.last_name, student.academic_level, group.name, group.academic_level
+        ));
+    }
+
+    let enrollment = Enrollment {
+        id: Uuid::new_v4().to_string(),
+        group_id,
+        student_id,
+        student_name: String::new(),
+        group_name: String::new(),
+        status: "active".to_string(),
+        enrolled_at: Utc::now(),
+        withdrawn_at: None,
+        notes,
+    };
+    let enrollment = db.enroll_student(enrollment).await.map_err(db_error)?;
+    log::info!(target: "classes", "{} enrolled in {}", enrollment.student_name, enrollment.group_name);
+    Ok(enrollment)
+}
+
+#[tauri::command]
+pub async fn withdraw_enrollment(state: State<'_, AppState>, enrollment_id: String) -> Result<Enrollment, String> {
+    let db = state.db.lock().await;
+    db.withdraw_enrollment(&enrollment_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_enrollments(
+    state: State<'_, AppState>,
+    group_id: Option<String>,
+    student_id: Option<String>,
+    active_only: Option<bool>,
+) -> Result<Vec<Enrollment>, String> {
+    let db = state.db.lock().await;
+    db.get_enrollments(group_id.as_deref(), student_id.as_deref(), active_only.unwrap_or(true))
+        .await
+        .map_err(db_error)
+}
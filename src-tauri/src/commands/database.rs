@@ -1,18 +1,34 @@
 use tauri::State;
-use crate::{AppState, models::{Student, Payment, Attendance}};
+use crate::{AppState, models::{Student, Payment, Attendance, ReceiptSequenceReport}};
 use uuid::Uuid;
 use chrono::Utc;
+use super::db_error;
 
 #[tauri::command]
 pub async fn get_database_stats(state: State<'_, AppState>) -> Result<crate::models::DatabaseStats, String> {
     let db = state.db.lock().await;
-    db.get_database_stats().await.map_err(|e| e.to_string())
+    db.get_database_stats().await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn optimize_database(state: State<'_, AppState>) -> Result<crate::models::DatabaseStats, String> {
+    let db = state.db.lock().await;
+    db.optimize().await.map_err(db_error)?;
+    db.get_database_stats().await.map_err(db_error)
+}
+
+/// Runs the full integrity check, which `get_database_stats` replaces with
+/// the quick one.
+#[tauri::command]
+pub async fn check_database_integrity(state: State<'_, AppState>) -> Result<String, String> {
+    let db = state.db.lock().await;
+    db.integrity_check().await.map_err(db_error)
 }
 
 #[tauri::command]
 pub async fn get_students(state: State<'_, AppState>) -> Result<Vec<Student>, String> {
     let db = state.db.lock().await;
-    db.get_students().await.map_err(|e| e.to_string())
+    db.get_students().await.map_err(db_error)
 }
 
 #[tauri::command]
@@ -43,12 +59,14 @@ pub async fn create_student(
         enrollment_date: Utc::now(),
         is_active: true,
         notes: None,
+        sessions_remaining: 0,
+        photo_id: None,
         created_at: Utc::now(),
         updated_at: Utc::now(),
     };
 
     let db = state.db.lock().await;
-    db.create_student(student).await.map_err(|e| e.to_string())
+    db.create_student(student).await.map_err(db_error)
 }
 
 #[tauri::command]
@@ -72,11 +90,12 @@ pub async fn delete_student(
 #[tauri::command]
 pub async fn get_payments(state: State<'_, AppState>) -> Result<Vec<Payment>, String> {
     let db = state.db.lock().await;
-    db.get_payments().await.map_err(|e| e.to_string())
+    db.get_payments().await.map_err(db_error)
 }
 
 #[tauri::command]
 pub async fn create_payment(
+    app: tauri::AppHandle,
     state: State<'_, AppState>,
     student_id: String,
     amount: f64,
@@ -84,6 +103,10 @@ pub async fn create_payment(
     payment_method: String,
     due_date: Option<String>,
     notes: Option<String>,
+    cashier: Option<String>,
+    due_id: Option<String>,
+    sessions: Option<i64>,
+    group_id: Option<String>,
 ) -> Result<Payment, String> {
     let payment = Payment {
         id: Uuid::new_v4().to_string(),
@@ -96,28 +119,66 @@ pub async fn create_payment(
             .map(|d| d.with_timezone(&Utc)),
         status: "paid".to_string(),
         notes,
-        receipt_number: Some(format!("REC-{}", Uuid::new_v4().to_string()[..8].to_uppercase())),
+        receipt_number: None,
+        cashier,
+        sessions: sessions.unwrap_or(0),
+        cash_session_id: None,
+        group_id,
         created_at: Utc::now(),
         updated_at: Utc::now(),
     };
 
-    // TODO: Implement create_payment in database
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let payment = db
+        .create_payment(
+            payment,
+            &settings.general.academic_year,
+            &settings.payments.receipt_prefix,
+            due_id.as_deref(),
+            settings.payments.allow_partial_payments,
+            settings.payments.require_cash_session,
+        )
+        .await
+        .map_err(db_error)?;
+
+    if settings.payments.auto_generate_receipts {
+        // The payment is recorded either way; the receipt can be re-printed later.
+        if let Err(e) = super::receipts::write_receipt(&db, super::receipts::receipts_dir(&app), &payment).await {
+            log::error!(target: "receipts", "Failed to generate receipt for payment {}: {:#}", payment.id, e);
+        }
+    }
+    if settings.printer.enabled && settings.printer.print_on_payment {
+        if let Err(e) = super::printing::print_payment(&db, &settings, &payment).await {
+            log::error!(target: "printer", "Failed to print ticket for payment {}: {:#}", payment.id, e);
+        }
+    }
+
     Ok(payment)
 }
 
+#[tauri::command]
+pub async fn get_receipt_sequence_report(
+    state: State<'_, AppState>,
+    academic_year: Option<String>,
+) -> Result<Vec<ReceiptSequenceReport>, String> {
+    let db = state.db.lock().await;
+    db.receipt_sequence_report(academic_year.as_deref()).await.map_err(db_error)
+}
+
 #[tauri::command]
 pub async fn update_payment(
     state: State<'_, AppState>,
     payment: Payment,
 ) -> Result<Payment, String> {
-    // TODO: Implement update_payment in database
-    Ok(payment)
+    let db = state.db.lock().await;
+    db.update_payment(payment).await.map_err(db_error)
 }
 
 #[tauri::command]
 pub async fn get_attendance(state: State<'_, AppState>) -> Result<Vec<Attendance>, String> {
-    // TODO: Implement get_attendance in database
-    Ok(vec![])
+    let db = state.db.lock().await;
+    db.get_attendance().await.map_err(db_error)
 }
 
 #[tauri::command]
@@ -126,6 +187,7 @@ pub async fn create_attendance(
     student_id: String,
     status: String,
     notes: Option<String>,
+    group_id: Option<String>,
 ) -> Result<Attendance, String> {
     let attendance = Attendance {
         id: Uuid::new_v4().to_string(),
@@ -135,9 +197,11 @@ pub async fn create_attendance(
         check_in_time: Some(Utc::now()),
         check_out_time: None,
         notes,
+        group_id,
+        occurrence_id: None,
         created_at: Utc::now(),
     };
 
-    // TODO: Implement create_attendance in database
-    Ok(attendance)
+    let db = state.db.lock().await;
+    db.create_attendance(attendance).await.map_err(db_error)
 }
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, billing, models::{Expense, ProfitReport}};
+use super::billing::{parse_bound, parse_day};
+use super::db_error;
+
+fn expenses_dir(app: &tauri::AppHandle) -> PathBuf {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("expenses"))
+        .unwrap_or_else(|| "expenses".into())
+}
+
+/// Copies the supplier's receipt next to the database as `<expense id>.<ext>`.
+async fn store_receipt(dir: PathBuf, expense_id: &str, source: &str) -> Result<String, String> {
+    let source = Path::new(source);
+    let extension = source.extension().and_then(|e| e.to_str()).unwrap_or("bin");
+    let target = dir.join(format!("{}.{}", expense_id, extension.to_lowercase()));
+
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| e.to_string())?;
+    tokio::fs::copy(source, &target)
+        .await
+        .map_err(|e| format!("Failed to copy {}: {}", source.display(), e))?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+fn validate(expense: &Expense) -> Result<(), String> {
+    if expense.category.trim().is_empty() {
+        return Err("An expense needs a category".to_string());
+    }
+    if expense.amount <= 0.0 {
+        return Err("The expense amount must be positive".to_string());
+    }
+    Ok(())
+}
+
+/// Records an expense. `expense_date` is RFC 3339 or YYYY-MM-DD (now by
+/// default); `receipt_file` is copied into the app data folder.
+#[tauri::command]
+pub async fn create_expense(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    category: String,
+    description: String,
+    amount: f64,
+    expense_date: Option<String>,
+    payment_method: String,
+    supplier: Option<String>,
+    receipt_file: Option<String>,
+    notes: Option<String>,
+    created_by: Option<String>,
+) -> Result<Expense, String> {
+    let mut expense = Expense {
+        id: Uuid::new_v4().to_string(),
+        category: category.trim().to_lowercase(),
+        description,
+        amount,
+        expense_date: expense_date.as_deref().map(parse_bound).transpose()?.unwrap_or_else(Utc::now),
+        payment_method,
+        supplier,
+        receipt_path: None,
+        notes,
+        created_by,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    validate(&expense)?;
+
+    if let Some(file) = receipt_file.as_deref() {
+        expense.receipt_path = Some(store_receipt(expenses_dir(&app), &expense.id, file).await?);
+    }
+
+    let db = state.db.lock().await;
+    let expense = db.create_expense(expense).await.map_err(db_error)?;
+    log::info!(target: "expenses", "Expense {} of {:.2} recorded in {}", expense.id, expense.amount, expense.category);
+    Ok(expense)
+}
+
+/// Saves changes to an expense, replacing its receipt when `receipt_file` is given.
+#[tauri::command]
+pub async fn update_expense(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    mut expense: Expense,
+    receipt_file: Option<String>,
+) -> Result<Expense, String> {
+    expense.category = expense.category.trim().to_lowercase();
+    validate(&expense)?;
+
+    if let Some(file) = receipt_file.as_deref() {
+        expense.receipt_path = Some(store_receipt(expenses_dir(&app), &expense.id, file).await?);
+    }
+
+    let db = state.db.lock().await;
+    db.update_expense(expense).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_expense(state: State<'_, AppState>, expense_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let expense = db
+        .get_expense(&expense_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Expense {} not found", expense_id))?;
+    db.delete_expense(&expense_id).await.map_err(db_error)?;
+
+    if let Some(path) = expense.receipt_path {
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            log::warn!(target: "expenses", "Failed to remove receipt {}: {}", path, e);
+        }
+    }
+    log::info!(target: "expenses", "Expense {} deleted", expense_id);
+    Ok(())
+}
+
+/// Expenses between `from` and `to` (YYYY-MM-DD, inclusive).
+#[tauri::command]
+pub async fn get_expenses(
+    state: State<'_, AppState>,
+    category: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<Expense>, String> {
+    let from = from.as_deref().map(parse_day).transpose()?.map(billing::local_midnight);
+    let to = to
+        .as_deref()
+        .map(parse_day)
+        .transpose()?
+        .map(|to| billing::local_midnight(to + chrono::Days::new(1)));
+
+    let db = state.db.lock().await;
+    db.get_expenses(category.as_deref(), from, to).await.map_err(db_error)
+}
+
+/// Revenue, expenses and net profit per month and per category between
+/// `from_month` and `to_month` (YYYY-MM, inclusive).
+#[tauri::command]
+pub async fn get_profit_report(
+    state: State<'_, AppState>,
+    from_month: Option<String>,
+    to_month: Option<String>,
+) -> Result<ProfitReport, String> {
+    let db = state.db.lock().await;
+    let payments = db.get_payments().await.map_err(db_error)?;
+    let reversals = db.get_payment_reversals(None).await.map_err(db_error)?;
+    let expenses = db.get_expenses(None, None, None).await.map_err(db_error)?;
+    drop(db);
+
+    Ok(billing::profit_report(&payments, &reversals, &expenses, from_month.as_deref(), to_month.as_deref()))
+}
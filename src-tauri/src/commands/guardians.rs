@@ -0,0 +1,201 @@
+use std::collections::{BTreeSet, HashMap};
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, database::Database, models::{Family, FamilyChild, Guardian, StudentGuardian}};
+use super::db_error;
+
+const RELATIONSHIPS: [&str; 5] = ["father", "mother", "tutor", "sibling", "other"];
+
+fn validate(guardian: &Guardian) -> Result<(), String> {
+    if guardian.first_name.trim().is_empty() && guardian.last_name.trim().is_empty() {
+        return Err("A guardian needs a name".to_string());
+    }
+    if guardian.phone.trim().is_empty() {
+        return Err("A guardian needs a phone number".to_string());
+    }
+    if !matches!(guardian.preferred_language.as_str(), "fr" | "ar") {
+        return Err(format!("Unsupported language {}", guardian.preferred_language));
+    }
+    Ok(())
+}
+
+/// Everyone connected to `guardian_ids` through shared children, so two
+/// parents of the same siblings make one family.
+pub async fn load_family(db: &Database, guardian_ids: Vec<String>) -> anyhow::Result<Family> {
+    let mut guardian_ids: BTreeSet<String> = guardian_ids.into_iter().collect();
+    let mut links: HashMap<(String, String), StudentGuardian> = HashMap::new();
+    let mut pending: Vec<String> = guardian_ids.iter().cloned().collect();
+
+    while let Some(guardian_id) = pending.pop() {
+        for link in db.get_guardian_links(Some(&guardian_id), None).await? {
+            for other in db.get_guardian_links(None, Some(&link.student_id)).await? {
+                if guardian_ids.insert(other.guardian_id.clone()) {
+                    pending.push(other.guardian_id.clone());
+                }
+                links.insert((other.guardian_id.clone(), other.student_id.clone()), other);
+            }
+        }
+    }
+
+    let mut guardians = Vec::new();
+    for id in &guardian_ids {
+        if let Some(guardian) = db.get_guardian(id).await? {
+            guardians.push(guardian);
+        }
+    }
+
+    let student_ids: BTreeSet<&String> = links.keys().map(|(_, student_id)| student_id).collect();
+    let now = Utc::now();
+    let mut children = Vec::new();
+    for student_id in student_ids {
+        let Some(student) = db.get_student(student_id).await? else { continue };
+        let mut student_links: Vec<StudentGuardian> = links.values().filter(|l| &l.student_id == student_id).cloned().collect();
+        student_links.sort_by(|a, b| b.is_primary.cmp(&a.is_primary).then_with(|| a.guardian_name.cmp(&b.guardian_name)));
+        let balance = db.get_student_balance(student_id, now).await?;
+        children.push(FamilyChild { student, guardians: student_links, balance });
+    }
+    children.sort_by(|a, b| a.student.first_name.cmp(&b.student.first_name));
+
+    Ok(Family {
+        total_balance: children.iter().map(|c| c.balance.balance).sum(),
+        total_overdue: children.iter().map(|c| c.balance.overdue).sum(),
+        guardians,
+        children,
+    })
+}
+
+#[tauri::command]
+pub async fn create_guardian(
+    state: State<'_, AppState>,
+    first_name: String,
+    last_name: String,
+    phone: String,
+    secondary_phone: Option<String>,
+    email: Option<String>,
+    preferred_language: Option<String>,
+    address: Option<String>,
+    notes: Option<String>,
+) -> Result<Guardian, String> {
+    let guardian = Guardian {
+        id: Uuid::new_v4().to_string(),
+        first_name: first_name.trim().to_string(),
+        last_name: last_name.trim().to_string(),
+        phone: phone.trim().to_string(),
+        secondary_phone,
+        email,
+        preferred_language: preferred_language.unwrap_or_else(|| "fr".to_string()),
+        address,
+        notes,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    validate(&guardian)?;
+
+    let db = state.db.lock().await;
+    db.create_guardian(guardian).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_guardian(state: State<'_, AppState>, guardian: Guardian) -> Result<Guardian, String> {
+    validate(&guardian)?;
+    let db = state.db.lock().await;
+    db.update_guardian(guardian).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_guardian(state: State<'_, AppState>, guardian_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_guardian(&guardian_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_guardians(state: State<'_, AppState>, search: Option<String>) -> Result<Vec<Guardian>, String> {
+    let db = state.db.lock().await;
+    let search = search.map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    db.get_guardians(search.as_deref()).await.map_err(db_error)
+}
+
+/// Links a guardian to a student; making them primary copies their name
+/// and phone to the student's parent fields.
+#[tauri::command]
+pub async fn link_guardian(
+    state: State<'_, AppState>,
+    guardian_id: String,
+    student_id: String,
+    relationship: String,
+    is_primary: Option<bool>,
+    can_pick_up: Option<bool>,
+) -> Result<StudentGuardian, String> {
+    if !RELATIONSHIPS.contains(&relationship.as_str()) {
+        return Err(format!("Unknown relationship {}", relationship));
+    }
+
+    let link = StudentGuardian {
+        guardian_id,
+        student_id,
+        relationship,
+        is_primary: is_primary.unwrap_or(false),
+        can_pick_up: can_pick_up.unwrap_or(true),
+        guardian_name: String::new(),
+        guardian_phone: String::new(),
+        student_name: String::new(),
+        created_at: Utc::now(),
+    };
+    let db = state.db.lock().await;
+    db.link_guardian(link).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn unlink_guardian(state: State<'_, AppState>, guardian_id: String, student_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.unlink_guardian(&guardian_id, &student_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_student_guardians(state: State<'_, AppState>, student_id: String) -> Result<Vec<StudentGuardian>, String> {
+    let db = state.db.lock().await;
+    db.get_guardian_links(None, Some(&student_id)).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_family(state: State<'_, AppState>, guardian_id: String) -> Result<Family, String> {
+    let db = state.db.lock().await;
+    if db.get_guardian(&guardian_id).await.map_err(db_error)?.is_none() {
+        return Err(format!("Guardian {} not found", guardian_id));
+    }
+    load_family(&db, vec![guardian_id]).await.map_err(db_error)
+}
+
+/// Family of whoever answers on `phone`, or of the guardians of
+/// `student_id`.
+#[tauri::command]
+pub async fn find_family(
+    state: State<'_, AppState>,
+    phone: Option<String>,
+    student_id: Option<String>,
+) -> Result<Option<Family>, String> {
+    let db = state.db.lock().await;
+    let guardian_ids: Vec<String> = match (phone, student_id) {
+        (Some(phone), _) => db
+            .find_guardians_by_phone(&phone)
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .map(|g| g.id)
+            .collect(),
+        (None, Some(student_id)) => db
+            .get_guardian_links(None, Some(&student_id))
+            .await
+            .map_err(db_error)?
+            .into_iter()
+            .map(|l| l.guardian_id)
+            .collect(),
+        (None, None) => return Err("Give a phone number or a student to look up".to_string()),
+    };
+
+    if guardian_ids.is_empty() {
+        return Ok(None);
+    }
+    load_family(&db, guardian_ids).await.map(Some).map_err(db_error)
+}
@@ -1,3 +1,23 @@
+pub mod attachments;
+pub mod attendance;
+pub mod backup;
+pub mod billing;
+pub mod cash_register;
+pub mod classes;
 pub mod database;
+pub mod expenses;
+pub mod guardians;
+pub mod payroll;
+pub mod printing;
+pub mod receipts;
 pub mod rfid;
+pub mod rollover;
+pub mod rooms;
 pub mod system;
+pub mod timetable;
+
+/// Logs a database failure before handing its message to the frontend.
+pub(crate) fn db_error(e: anyhow::Error) -> String {
+    log::error!(target: "database", "{:#}", e);
+    e.to_string()
+}
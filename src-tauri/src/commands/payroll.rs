@@ -0,0 +1,227 @@
+use std::path::Path;
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, export, payroll, database::Database, receipts::{self, PayslipData}};
+use crate::models::{Expense, PayrollAdjustment, PayrollPayment, PayrollRule, Payslip};
+use super::db_error;
+
+/// Computes the payslip of `teacher_id` for `month` (YYYY-MM) from the
+/// sessions held so far and the revenue collected.
+pub async fn build_payslip(db: &Database, teacher_id: &str, month: &str) -> anyhow::Result<Payslip> {
+    let teacher = db
+        .get_teacher(teacher_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Teacher {} not found", teacher_id))?;
+    let (from, to) = payroll::month_bounds(month)?;
+
+    let rules = db.get_payroll_rules(Some(teacher_id)).await?;
+    let activity = db.get_teacher_activity(teacher_id, from, to, Utc::now()).await?;
+    let adjustments = db.get_payroll_adjustments(teacher_id, month).await?;
+    let payment = db.get_payroll_payment(teacher_id, month).await?;
+
+    Ok(payroll::payslip(&teacher, month, &rules, &activity, adjustments, payment))
+}
+
+/// Adds a pay rule; without `group_id` it covers all of the teacher's groups.
+#[tauri::command]
+pub async fn create_payroll_rule(
+    state: State<'_, AppState>,
+    teacher_id: String,
+    group_id: Option<String>,
+    mode: String,
+    rate: f64,
+) -> Result<PayrollRule, String> {
+    let rule = PayrollRule {
+        id: Uuid::new_v4().to_string(),
+        teacher_id,
+        group_id,
+        mode,
+        rate,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    payroll::validate_rule(&rule).map_err(db_error)?;
+
+    let db = state.db.lock().await;
+    db.create_payroll_rule(rule).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_payroll_rule(state: State<'_, AppState>, rule: PayrollRule) -> Result<PayrollRule, String> {
+    payroll::validate_rule(&rule).map_err(db_error)?;
+    let db = state.db.lock().await;
+    db.update_payroll_rule(rule).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_payroll_rule(state: State<'_, AppState>, rule_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_payroll_rule(&rule_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_payroll_rules(state: State<'_, AppState>, teacher_id: Option<String>) -> Result<Vec<PayrollRule>, String> {
+    let db = state.db.lock().await;
+    db.get_payroll_rules(teacher_id.as_deref()).await.map_err(db_error)
+}
+
+/// Adds a bonus (positive `amount`) or deduction (negative) to a month's pay.
+#[tauri::command]
+pub async fn add_payroll_adjustment(
+    state: State<'_, AppState>,
+    teacher_id: String,
+    month: String,
+    label: String,
+    amount: f64,
+    created_by: Option<String>,
+) -> Result<PayrollAdjustment, String> {
+    payroll::month_bounds(&month).map_err(db_error)?;
+    if label.trim().is_empty() {
+        return Err("An adjustment needs a label".to_string());
+    }
+    if amount == 0.0 {
+        return Err("The adjustment amount cannot be zero".to_string());
+    }
+
+    let adjustment = PayrollAdjustment {
+        id: Uuid::new_v4().to_string(),
+        teacher_id,
+        month,
+        label: label.trim().to_string(),
+        amount,
+        created_by,
+        created_at: Utc::now(),
+    };
+    let db = state.db.lock().await;
+    db.create_payroll_adjustment(adjustment).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_payroll_adjustment(state: State<'_, AppState>, adjustment_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_payroll_adjustment(&adjustment_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_payslip(state: State<'_, AppState>, teacher_id: String, month: String) -> Result<Payslip, String> {
+    let db = state.db.lock().await;
+    build_payslip(&db, &teacher_id, &month).await.map_err(db_error)
+}
+
+/// Payslips of every active teacher for `month`.
+#[tauri::command]
+pub async fn get_payroll(state: State<'_, AppState>, month: String) -> Result<Vec<Payslip>, String> {
+    let db = state.db.lock().await;
+    let mut payslips = Vec::new();
+    for teacher in db.get_teachers(true).await.map_err(db_error)? {
+        payslips.push(build_payslip(&db, &teacher.id, &month).await.map_err(db_error)?);
+    }
+    Ok(payslips)
+}
+
+/// Pays a teacher's net salary for `month` and records it as a "salaries"
+/// expense. A month can only be paid once.
+#[tauri::command]
+pub async fn pay_teacher_salary(
+    state: State<'_, AppState>,
+    teacher_id: String,
+    month: String,
+    payment_method: String,
+    paid_by: Option<String>,
+) -> Result<Payslip, String> {
+    let db = state.db.lock().await;
+    let payslip = build_payslip(&db, &teacher_id, &month).await.map_err(db_error)?;
+    if payslip.payment.is_some() {
+        return Err(format!("The {} salary of {} is already paid", month, payslip.teacher_name));
+    }
+    if payslip.net <= 0.0 {
+        return Err(format!("Nothing to pay to {} for {}", payslip.teacher_name, month));
+    }
+
+    let now = Utc::now();
+    let expense = Expense {
+        id: Uuid::new_v4().to_string(),
+        category: "salaries".to_string(),
+        description: format!("Salaire {} - {}", month, payslip.teacher_name),
+        amount: payslip.net,
+        expense_date: now,
+        payment_method: payment_method.clone(),
+        supplier: Some(payslip.teacher_name.clone()),
+        receipt_path: None,
+        notes: None,
+        created_by: paid_by.clone(),
+        created_at: now,
+        updated_at: now,
+    };
+    let payment = PayrollPayment {
+        id: Uuid::new_v4().to_string(),
+        teacher_id: teacher_id.clone(),
+        month: month.clone(),
+        amount: payslip.net,
+        payment_method,
+        expense_id: expense.id.clone(),
+        paid_by,
+        paid_at: now,
+    };
+    db.record_payroll_payment(payment, expense).await.map_err(db_error)?;
+    log::info!(target: "payroll", "{} salary of {} paid: {:.2}", month, payslip.teacher_name, payslip.net);
+
+    build_payslip(&db, &teacher_id, &month).await.map_err(db_error)
+}
+
+/// Writes a payslip as PDF, JSON, CSV or XLSX depending on the file extension.
+#[tauri::command]
+pub async fn export_payslip(
+    state: State<'_, AppState>,
+    teacher_id: String,
+    month: String,
+    file_path: String,
+) -> Result<String, String> {
+    let (payslip, settings) = {
+        let db = state.db.lock().await;
+        let payslip = build_payslip(&db, &teacher_id, &month).await.map_err(db_error)?;
+        let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+        (payslip, settings)
+    };
+
+    let path = Path::new(&file_path);
+    let format = path.extension().and_then(|e| e.to_str()).unwrap_or("pdf").to_lowercase();
+    match format.as_str() {
+        "pdf" => {
+            let details = payslip
+                .lines
+                .iter()
+                .map(|l| (payroll::line_label(l), l.amount))
+                .chain(payslip.adjustments.iter().map(|a| (a.label.clone(), a.amount)))
+                .collect();
+            let data = PayslipData {
+                school_name: settings.general.school_name,
+                school_address: settings.general.school_address,
+                school_phone: settings.general.school_phone,
+                school_email: settings.general.school_email,
+                currency: settings.general.currency,
+                payslip,
+                details,
+            };
+            let output = path.to_path_buf();
+            tokio::task::spawn_blocking(move || receipts::render_payslip(&data, &output))
+                .await
+                .map_err(|e| e.to_string())?
+                .map_err(db_error)?;
+        }
+        "json" => {
+            let json = serde_json::to_string_pretty(&payslip).map_err(|e| e.to_string())?;
+            tokio::fs::write(path, json).await.map_err(|e| e.to_string())?;
+        }
+        "csv" => {
+            export::write_csv(path, &[export::payslip_sheet(&payslip)], b',').map_err(db_error)?;
+        }
+        "xlsx" => {
+            export::write_xlsx(path, &[export::payslip_sheet(&payslip)]).map_err(db_error)?;
+        }
+        _ => return Err(format!("Unsupported export format: {}", format)),
+    }
+
+    Ok(format!("Exported payslip to {}", file_path))
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use tauri::State;
+use crate::{AppState, billing, database::Database, models::{AppSettings, Payment, PrinterSettings}, thermal::{self, ClosingTicket, Header, ReceiptTicket}};
+use super::db_error;
+
+fn school_header(settings: &AppSettings) -> Header<'_> {
+    Header {
+        school_name: &settings.general.school_name,
+        school_address: &settings.general.school_address,
+        school_phone: &settings.general.school_phone,
+    }
+}
+
+/// Builds the ticket of a payment, ready for `send_ticket`.
+async fn payment_ticket(db: &Database, settings: &AppSettings, payment: &Payment) -> anyhow::Result<Vec<u8>> {
+    let student = db
+        .get_student(&payment.student_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Student {} not found", payment.student_id))?;
+    let receipt_number = payment.receipt_number.clone().unwrap_or_else(|| payment.id.clone());
+    let student_name = format!("{} {}", student.first_name, student.last_name);
+    let details = super::receipts::receipt_details(db, payment).await?;
+
+    Ok(thermal::receipt(&settings.printer, &school_header(settings), &ReceiptTicket {
+        receipt_number: &receipt_number,
+        payment_date: payment.payment_date,
+        student_name: &student_name,
+        academic_level: &student.academic_level,
+        payment_type: &payment.payment_type,
+        payment_method: &payment.payment_method,
+        amount: payment.amount,
+        currency: &settings.general.currency,
+        cashier: payment.cashier.as_deref(),
+        details: &details,
+    }))
+}
+
+/// Writes a ticket to the printer; serial I/O runs off the async runtime.
+async fn send_ticket(printer: &PrinterSettings, bytes: Vec<u8>) -> anyhow::Result<()> {
+    let printer = printer.clone();
+    tokio::task::spawn_blocking(move || thermal::send(&printer, &bytes)).await?
+}
+
+pub async fn print_payment(db: &Database, settings: &AppSettings, payment: &Payment) -> anyhow::Result<()> {
+    let bytes = payment_ticket(db, settings, payment).await?;
+    send_ticket(&settings.printer, bytes).await
+}
+
+#[tauri::command]
+pub async fn print_receipt_ticket(state: State<'_, AppState>, payment_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let payment = db
+        .get_payment(&payment_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Payment {} not found", payment_id))?;
+
+    let bytes = payment_ticket(&db, &settings, &payment).await.map_err(db_error)?;
+    drop(db);
+
+    send_ticket(&settings.printer, bytes).await.map_err(db_error)
+}
+
+/// Prints the totals of `date` (YYYY-MM-DD, today by default) by payment
+/// method, net of the refunds made that day as on the Z-report.
+#[tauri::command]
+pub async fn print_daily_closing(state: State<'_, AppState>, date: Option<String>) -> Result<(), String> {
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(&date, "%Y-%m-%d").map_err(|e| e.to_string())?,
+        None => Local::now().date_naive(),
+    };
+
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let all_payments = db.get_payments().await.map_err(db_error)?;
+    let reversals = db.get_payment_reversals(None).await.map_err(db_error)?;
+    drop(db);
+
+    let on_date = |at: DateTime<Utc>| at.with_timezone(&Local).date_naive() == date;
+    // Cancelled payments stay in, their cancellation is one of the refunds
+    let mut payments: Vec<&Payment> = all_payments.iter().filter(|p| on_date(p.payment_date)).collect();
+    payments.sort_by_key(|p| p.payment_date);
+
+    let collected: Vec<(String, i64, f64)> = payments.iter().map(|p| (p.payment_method.clone(), 1, p.amount)).collect();
+    let refunded: Vec<(String, f64)> = reversals
+        .iter()
+        .filter(|r| on_date(r.created_at))
+        .map(|r| {
+            let method = r.refund_method.clone().or_else(|| {
+                all_payments.iter().find(|p| p.id == r.payment_id).map(|p| p.payment_method.clone())
+            });
+            (method.unwrap_or_default(), r.amount)
+        })
+        .collect();
+    let totals = billing::session_totals(0.0, &collected, &refunded, None)
+        .into_iter()
+        .filter(|t| t.payment_count > 0 || t.refunded > 0.0)
+        .map(|t| (t.payment_method, t.payment_count as usize, t.expected))
+        .collect();
+
+    let bytes = thermal::daily_closing(&settings.printer, &school_header(&settings), &ClosingTicket {
+        date,
+        payment_count: payments.len(),
+        totals,
+        first_receipt: payments.first().and_then(|p| p.receipt_number.as_deref()),
+        last_receipt: payments.last().and_then(|p| p.receipt_number.as_deref()),
+        currency: &settings.general.currency,
+    });
+
+    send_ticket(&settings.printer, bytes).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn print_z_report(state: State<'_, AppState>, session_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let report = db.z_report(&session_id).await.map_err(db_error)?;
+    drop(db);
+
+    let bytes = thermal::z_report(&settings.printer, &school_header(&settings), &report, &settings.general.currency);
+    send_ticket(&settings.printer, bytes).await.map_err(db_error)
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+use tauri::State;
+use crate::{AppState, billing, database::Database, models::{AppSettings, Payment, PaymentReversal}, receipts::{self, CreditNoteData, ReceiptData}};
+use super::db_error;
+
+pub fn receipts_dir(app: &tauri::AppHandle) -> PathBuf {
+    app.path_resolver()
+        .app_data_dir()
+        .map(|dir| dir.join("receipts"))
+        .unwrap_or_else(|| "receipts".into())
+}
+
+/// Dues settled by `payment`, each followed by its discounts and the
+/// amount applied to it.
+pub async fn receipt_details(db: &Database, payment: &Payment) -> anyhow::Result<Vec<(String, f64)>> {
+    let mut details = Vec::new();
+    for (due, allocated) in db.get_payment_allocations(&payment.id).await? {
+        details.push((billing::ledger_description(&due.kind, Some(&due.month), None), due.amount + due.discount_amount));
+        for line in db.get_due_discounts(None, Some(&due.id), None, None).await? {
+            details.push((format!("- {}", line.label), -line.amount));
+        }
+        details.push(("Réglé".to_string(), allocated));
+    }
+    Ok(details)
+}
+
+async fn receipt_data(db: &Database, settings: &AppSettings, payment: &Payment) -> anyhow::Result<ReceiptData> {
+    let student = db
+        .get_student(&payment.student_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Student {} not found", payment.student_id))?;
+    let receipt_number = payment.receipt_number.clone().unwrap_or_else(|| payment.id.clone());
+
+    Ok(ReceiptData {
+        school_name: settings.general.school_name.clone(),
+        school_address: settings.general.school_address.clone(),
+        school_phone: settings.general.school_phone.clone(),
+        school_email: settings.general.school_email.clone(),
+        title: format!("Reçu {}", receipt_number),
+        receipt_number,
+        payment_date: payment.payment_date,
+        student_name: format!("{} {}", student.first_name, student.last_name),
+        academic_level: student.academic_level,
+        amount: payment.amount,
+        currency: settings.general.currency.clone(),
+        payment_type: payment.payment_type.clone(),
+        payment_method: payment.payment_method.clone(),
+        cashier: payment.cashier.clone(),
+        notes: payment.notes.clone(),
+        details: receipt_details(db, payment).await?,
+    })
+}
+
+/// Renders the receipt of `payment` into the receipts folder and returns its path.
+pub async fn write_receipt(db: &Database, dir: PathBuf, payment: &Payment) -> anyhow::Result<PathBuf> {
+    let settings = db.load_settings().await?.unwrap_or_default();
+    let data = receipt_data(db, &settings, payment).await?;
+    let receipt_number = data.receipt_number.clone();
+
+    let path = dir.join(receipts::file_name(&receipt_number));
+    let template = settings.payments.receipt_template;
+    let output = path.clone();
+    tokio::task::spawn_blocking(move || receipts::render_pdf(&data, &template, &output)).await??;
+
+    log::info!(target: "receipts", "Receipt {} written to {}", receipt_number, path.display());
+    Ok(path)
+}
+
+/// Renders the credit note of a cancellation or refund next to the receipts.
+pub async fn write_credit_note(db: &Database, dir: PathBuf, reversal: &PaymentReversal) -> anyhow::Result<PathBuf> {
+    let settings = db.load_settings().await?.unwrap_or_default();
+    let payment = db
+        .get_payment(&reversal.payment_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("Payment {} not found", reversal.payment_id))?;
+
+    let data = CreditNoteData {
+        receipt: receipt_data(db, &settings, &payment).await?,
+        credit_note_number: reversal.credit_note_number.clone(),
+        kind: reversal.kind.clone(),
+        amount: reversal.amount,
+        refund_method: reversal.refund_method.clone(),
+        reason: reversal.reason.clone(),
+        created_by: reversal.created_by.clone(),
+        created_at: reversal.created_at,
+    };
+
+    let path = dir.join(receipts::file_name(&reversal.credit_note_number));
+    let output = path.clone();
+    tokio::task::spawn_blocking(move || receipts::render_credit_note(&data, &output)).await??;
+
+    log::info!(target: "receipts", "Credit note {} written to {}", reversal.credit_note_number, path.display());
+    Ok(path)
+}
+
+/// Generates (or re-generates) the PDF receipt of a recorded payment.
+#[tauri::command]
+pub async fn generate_receipt(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    payment_id: String,
+) -> Result<String, String> {
+    let db = state.db.lock().await;
+    let payment = db
+        .get_payment(&payment_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Payment {} not found", payment_id))?;
+
+    let path = write_receipt(&db, receipts_dir(&app), &payment)
+        .await
+        .map_err(db_error)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Generates (or re-generates) the PDF credit note of a payment reversal.
+#[tauri::command]
+pub async fn generate_credit_note(
+    app: tauri::AppHandle,
+    state: State<'_, AppState>,
+    reversal_id: String,
+) -> Result<String, String> {
+    let db = state.db.lock().await;
+    let reversal = db
+        .get_payment_reversal(&reversal_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Reversal {} not found", reversal_id))?;
+
+    let path = write_credit_note(&db, receipts_dir(&app), &reversal)
+        .await
+        .map_err(db_error)?;
+    Ok(path.to_string_lossy().to_string())
+}
@@ -1,8 +1,9 @@
 use tauri::State;
-use crate::{AppState, models::RfidScanResult, rfid::RfidReader};
+use crate::{AppState, models::{RfidScanResult, RfidStatus}, rfid::RfidReader};
 use std::sync::{Arc, Mutex};
 use chrono::Utc;
 use lazy_static::lazy_static;
+use super::db_error;
 
 // Global RFID reader instance
 lazy_static! {
@@ -35,42 +36,73 @@ pub async fn disconnect_rfid_reader() -> Result<String, String> {
 
 #[tauri::command]
 pub async fn scan_rfid_card(state: State<'_, AppState>) -> Result<RfidScanResult, String> {
-    let mut global_reader = RFID_READER.lock().unwrap();
-    
-    if let Some(ref mut reader) = global_reader.as_mut() {
-        match reader.scan_card() {
-            Ok(card_id) => {
-                // Look up student by RFID card
-                let db = state.db.lock().await;
-                let students = db.get_students().await.map_err(|e| e.to_string())?;
-                let student = students.into_iter().find(|s| s.rfid_card.as_ref() == Some(&card_id));
+    // Release the reader lock before touching the database so the future stays Send.
+    let scan = {
+        let mut global_reader = RFID_READER.lock().unwrap();
+        match global_reader.as_mut() {
+            Some(reader) => reader.scan_card(),
+            None => return Err("RFID reader not connected".to_string()),
+        }
+    };
+
+    match scan {
+        Ok(card_id) => {
+            // Look up student by RFID card
+            let db = state.db.lock().await;
+            let students = db.get_students().await.map_err(db_error)?;
+            let student = students.into_iter().find(|s| s.rfid_card.as_ref() == Some(&card_id));
 
-                Ok(RfidScanResult {
-                    card_id: card_id.clone(),
-                    student,
-                    scan_time: Utc::now(),
-                    success: true,
-                    message: if student.is_some() {
-                        "Student found".to_string()
-                    } else {
-                        "Card not registered".to_string()
-                    },
-                })
-            }
-            Err(e) => Ok(RfidScanResult {
-                card_id: "".to_string(),
-                student: None,
+            let scan = match &student {
+                Some(student) => super::attendance::record_scan(&db, student).await.map_err(db_error)?,
+                None => None,
+            };
+            let message = match (&student, &scan) {
+                (None, _) => "Card not registered".to_string(),
+                (Some(_), None) => "Student found, no session scheduled now".to_string(),
+                (Some(_), Some((session, attendance))) if attendance.check_out_time.is_some() => {
+                    format!("Checked out of {}", session.group_name)
+                }
+                (Some(_), Some((session, attendance))) => format!("Checked in to {} ({})", session.group_name, attendance.status),
+            };
+            let (session, attendance) = scan.unzip();
+
+            Ok(RfidScanResult {
+                card_id: card_id.clone(),
+                message,
+                student,
                 scan_time: Utc::now(),
-                success: false,
-                message: format!("Scan failed: {}", e),
+                success: true,
+                session,
+                attendance,
             })
         }
-    } else {
-        Err("RFID reader not connected".to_string())
+        Err(e) => Ok(RfidScanResult {
+            card_id: "".to_string(),
+            student: None,
+            scan_time: Utc::now(),
+            success: false,
+            message: format!("Scan failed: {}", e),
+            session: None,
+            attendance: None,
+        })
     }
 }
 
 #[tauri::command]
 pub async fn get_available_ports() -> Result<Vec<String>, String> {
-    RfidReader::get_available_ports().map_err(|e| e.to_string())
+    RfidReader::get_available_ports().map_err(db_error)
+}
+
+pub fn reader_status() -> RfidStatus {
+    let global_reader = RFID_READER.lock().unwrap();
+    match global_reader.as_ref() {
+        Some(reader) => RfidStatus {
+            connected: reader.is_connected(),
+            port_name: Some(reader.port_name().to_string()),
+        },
+        None => RfidStatus {
+            connected: false,
+            port_name: None,
+        },
+    }
 }
@@ -0,0 +1,98 @@
+use chrono::Utc;
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, rollover, models::{Promotion, PromotionOverride, YearRollover}};
+use super::billing::new_payment_plan;
+use super::db_error;
+
+/// What closing the current academic year would do to each active student.
+#[tauri::command]
+pub async fn preview_year_rollover(
+    state: State<'_, AppState>,
+    overrides: Option<Vec<PromotionOverride>>,
+) -> Result<Vec<Promotion>, String> {
+    let db = state.db.lock().await;
+    let students = db.get_students().await.map_err(db_error)?;
+    rollover::plan_promotions(&students, &overrides.unwrap_or_default()).map_err(db_error)
+}
+
+/// Closes the current academic year and opens `to_year`. Session balances
+/// are reset or carried over per `session_policy`; with `start_month`, a
+/// new payment plan is created for every continuing student.
+#[tauri::command]
+pub async fn rollover_academic_year(
+    state: State<'_, AppState>,
+    to_year: String,
+    overrides: Option<Vec<PromotionOverride>>,
+    session_policy: String,
+    start_month: Option<String>,
+    installments: Option<u32>,
+    due_day: Option<u32>,
+    created_by: Option<String>,
+) -> Result<YearRollover, String> {
+    let to_year = to_year.trim().to_string();
+    if !matches!(session_policy.as_str(), "reset" | "carry") {
+        return Err(format!("Unknown session policy {}", session_policy));
+    }
+
+    let db = state.db.lock().await;
+    let mut settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let from_year = settings.general.academic_year.clone();
+    if to_year.is_empty() || to_year == from_year {
+        return Err(format!("The new academic year must differ from {}", from_year));
+    }
+
+    let students = db.get_students().await.map_err(db_error)?;
+    let promotions = rollover::plan_promotions(&students, &overrides.unwrap_or_default()).map_err(db_error)?;
+
+    let rollover = YearRollover {
+        id: Uuid::new_v4().to_string(),
+        from_year,
+        to_year: to_year.clone(),
+        session_policy,
+        promotions,
+        sessions_reset: 0,
+        payments_archived: 0,
+        attendance_archived: 0,
+        groups_archived: 0,
+        plans_created: 0,
+        warnings: vec![],
+        created_by,
+        created_at: Utc::now(),
+    };
+
+    settings.general.academic_year = to_year;
+    let mut rollover = db.rollover_year(rollover, &settings).await.map_err(db_error)?;
+    log::info!(
+        target: "rollover",
+        "Academic year {} closed, {} opened for {} student(s)",
+        rollover.from_year,
+        rollover.to_year,
+        rollover.promotions.iter().filter(|p| rollover::continues(p)).count()
+    );
+
+    if let Some(start_month) = start_month {
+        let installments = installments.unwrap_or(10);
+        for promotion in rollover.promotions.iter().filter(|p| rollover::continues(p)) {
+            let student = match db.get_student(&promotion.student_id).await {
+                Ok(Some(student)) => student,
+                Ok(None) => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            // A missing fee for one level should not block everyone else's dues
+            match new_payment_plan(&db, &settings, &student, installments, start_month.clone(), None, due_day.unwrap_or(1), None).await {
+                Ok(_) => rollover.plans_created += 1,
+                Err(e) => rollover.warnings.push(format!("{}: {}", promotion.student_name, e)),
+            }
+        }
+        db.update_year_rollover(&rollover).await.map_err(db_error)?;
+    }
+
+    Ok(rollover)
+}
+
+#[tauri::command]
+pub async fn get_year_rollovers(state: State<'_, AppState>) -> Result<Vec<YearRollover>, String> {
+    let db = state.db.lock().await;
+    db.get_year_rollovers().await.map_err(db_error)
+}
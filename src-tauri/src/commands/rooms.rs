@@ -0,0 +1,71 @@
+use chrono::{Local, Utc};
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, billing, schedule, database::Database, models::{Occupancy, Room}};
+use super::db_error;
+
+fn validate(room: &Room) -> Result<(), String> {
+    if room.name.trim().is_empty() {
+        return Err("A room needs a name".to_string());
+    }
+    if room.capacity <= 0 {
+        return Err("The room capacity must be positive".to_string());
+    }
+    Ok(())
+}
+
+/// Who is inside now: today's check-ins without a check-out, per room.
+pub async fn current_occupancy(db: &Database) -> anyhow::Result<Occupancy> {
+    let since = billing::local_midnight(Local::now().date_naive());
+    let rooms = db.get_rooms(true).await?;
+    let occupants = db.get_occupants(since).await?;
+    Ok(schedule::occupancy(&rooms, occupants, Utc::now()))
+}
+
+#[tauri::command]
+pub async fn create_room(
+    state: State<'_, AppState>,
+    name: String,
+    capacity: i64,
+    notes: Option<String>,
+) -> Result<Room, String> {
+    let room = Room {
+        id: Uuid::new_v4().to_string(),
+        name: name.trim().to_string(),
+        capacity,
+        is_active: true,
+        notes,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    validate(&room)?;
+
+    let db = state.db.lock().await;
+    db.create_room(room).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_room(state: State<'_, AppState>, mut room: Room) -> Result<Room, String> {
+    room.name = room.name.trim().to_string();
+    validate(&room)?;
+    let db = state.db.lock().await;
+    db.update_room(room).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_rooms(state: State<'_, AppState>, active_only: Option<bool>) -> Result<Vec<Room>, String> {
+    let db = state.db.lock().await;
+    db.get_rooms(active_only.unwrap_or(false)).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_room(state: State<'_, AppState>, room_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_room(&room_id).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_occupancy(state: State<'_, AppState>) -> Result<Occupancy, String> {
+    let db = state.db.lock().await;
+    current_occupancy(&db).await.map_err(db_error)
+}
@@ -1,7 +1,15 @@
 use tauri::State;
-use crate::AppState;
-use std::path::Path;
+use crate::{AppState, attachments, billing, cloud_sync, export, import::{self, ImportStrategy}, logging, spreadsheet, database::DATABASE_FILE, models::{ExportOptions, ImportReport, LogEntry, SpreadsheetPreview, Student}, system_monitor::{self, SystemMonitor}};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use lazy_static::lazy_static;
 use tokio::fs;
+use super::db_error;
+
+lazy_static! {
+    static ref SYSTEM_MONITOR: Mutex<SystemMonitor> = Mutex::new(SystemMonitor::new());
+}
 
 #[tauri::command]
 pub async fn get_system_info() -> Result<crate::models::SystemInfo, String> {
@@ -9,26 +17,70 @@ pub async fn get_system_info() -> Result<crate::models::SystemInfo, String> {
 }
 
 pub async fn get_system_info_internal() -> Result<crate::models::SystemInfo, Box<dyn std::error::Error>> {
+    // Sampling may sleep for the first CPU measurement, keep it off the async runtime.
+    let (cpu_usage, memory_usage, process_uptime) = tokio::task::spawn_blocking(|| {
+        let mut monitor = SYSTEM_MONITOR.lock().unwrap();
+        (monitor.cpu_usage(), monitor.memory_usage(), monitor.process_uptime())
+    })
+    .await?;
+
+    let database_size = match fs::metadata(DATABASE_FILE).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+
     Ok(crate::models::SystemInfo {
-        version: "2.0.0".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
         os: std::env::consts::OS.to_string(),
-        cpu_usage: 15.5,
-        memory_usage: 45.2,
-        disk_usage: 23.1,
-        uptime: "2h 34m".to_string(),
+        cpu_usage,
+        memory_usage,
+        disk_usage: system_monitor::disk_usage(Path::new(DATABASE_FILE)),
+        uptime: system_monitor::format_uptime(process_uptime),
+        system_uptime: system_monitor::format_uptime(system_monitor::system_uptime()),
+        database_size,
+        rfid: super::rfid::reader_status(),
     })
 }
 
 #[tauri::command]
-pub async fn save_settings(settings: crate::models::AppSettings) -> Result<bool, String> {
-    // TODO: Implement settings saving
+pub async fn save_settings(
+    state: State<'_, AppState>,
+    settings: crate::models::AppSettings,
+) -> Result<bool, String> {
+    let db = state.db.lock().await;
+    db.save_settings(&settings).await.map_err(db_error)?;
+    logging::configure(&settings.system);
     Ok(true)
 }
 
 #[tauri::command]
-pub async fn load_settings() -> Result<Option<crate::models::AppSettings>, String> {
-    // TODO: Implement settings loading
-    Ok(None)
+pub async fn load_settings(state: State<'_, AppState>) -> Result<Option<crate::models::AppSettings>, String> {
+    let db = state.db.lock().await;
+    db.load_settings().await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_recent_logs(limit: Option<usize>, level: Option<String>) -> Result<Vec<LogEntry>, String> {
+    logging::recent_entries(limit.unwrap_or(200), level.as_deref()).map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn export_logs(file_path: String, limit: Option<usize>) -> Result<String, String> {
+    let entries = logging::recent_entries(limit.unwrap_or(5000), None).map_err(db_error)?;
+
+    let mut content = String::new();
+    for entry in &entries {
+        content.push_str(&format!(
+            "{} {:<5} {}: {}\n",
+            entry.timestamp.to_rfc3339(),
+            entry.level,
+            entry.target,
+            entry.message
+        ));
+    }
+
+    fs::write(&file_path, content).await.map_err(|e| e.to_string())?;
+    Ok(format!("Exported {} log lines to {}", entries.len(), file_path))
 }
 
 #[tauri::command]
@@ -36,24 +88,86 @@ pub async fn export_data(
     state: State<'_, AppState>,
     file_path: String,
     data_type: String,
+    format: Option<String>,
+    options: Option<ExportOptions>,
 ) -> Result<String, String> {
-    let db = state.db.lock().await;
-    
-    match data_type.as_str() {
-        "students" => {
-            let students = db.get_students().await.map_err(|e| e.to_string())?;
-            let json_data = serde_json::to_string_pretty(&students).map_err(|e| e.to_string())?;
-            fs::write(&file_path, json_data).await.map_err(|e| e.to_string())?;
-            Ok(format!("Exported {} students to {}", students.len(), file_path))
-        }
+    let options = options.unwrap_or_default();
+    let format = format.unwrap_or_else(|| {
+        Path::new(&file_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("json")
+            .to_lowercase()
+    });
+
+    let (students, payments, attendance, discounts, reversals, expenses) = {
+        let db = state.db.lock().await;
+        (
+            db.get_students().await.map_err(db_error)?,
+            db.get_payments().await.map_err(db_error)?,
+            db.get_attendance().await.map_err(db_error)?,
+            db.get_due_discounts(None, None, None, None).await.map_err(db_error)?,
+            db.get_payment_reversals(None).await.map_err(db_error)?,
+            db.get_expenses(None, None, None).await.map_err(db_error)?,
+        )
+    };
+    let students_by_id: HashMap<String, Student> = students.iter().map(|s| (s.id.clone(), s.clone())).collect();
+
+    let sheet = match data_type.as_str() {
+        "students" => export::students_sheet(&export::filter_students(students, &options)),
         "payments" => {
-            let payments = db.get_payments().await.map_err(|e| e.to_string())?;
-            let json_data = serde_json::to_string_pretty(&payments).map_err(|e| e.to_string())?;
+            let payments = export::filter_payments(payments, &students_by_id, &options);
+            export::payments_sheet(&payments, &students_by_id)
+        }
+        "attendance" => {
+            let attendance = export::filter_attendance(attendance, &students_by_id, &options);
+            export::attendance_sheet(&attendance, &students_by_id)
+        }
+        "revenue_report" => {
+            let payments = export::filter_payments(payments, &students_by_id, &options);
+            export::revenue_report_sheet(&payments, &students_by_id)
+        }
+        "expenses" => export::expenses_sheet(&export::filter_expenses(expenses, &options)),
+        "profit_report" => {
+            // Months are YYYY-MM, so the date filters are cut to the month
+            let from = options.date_from.as_deref().map(|d| d.get(..7).unwrap_or(d));
+            let to = options.date_to.as_deref().map(|d| d.get(..7).unwrap_or(d));
+            export::profit_report_sheet(&billing::profit_report(&payments, &reversals, &expenses, from, to))
+        }
+        "discounts_report" => export::discounts_report_sheet(&discounts, &options),
+        "attendance_report" => {
+            let attendance = export::filter_attendance(attendance, &students_by_id, &options);
+            export::attendance_report_sheet(&attendance, &students_by_id)
+        }
+        _ => return Err("Unsupported data type".to_string()),
+    };
+
+    let sheet = sheet.select_columns(&options.columns);
+    let row_count = sheet.rows.len();
+
+    match format.as_str() {
+        "json" => {
+            // Built from the sheet so the JSON holds the same columns as the other formats
+            let json_data = serde_json::to_string_pretty(&sheet.to_json()).map_err(|e| e.to_string())?;
             fs::write(&file_path, json_data).await.map_err(|e| e.to_string())?;
-            Ok(format!("Exported {} payments to {}", payments.len(), file_path))
         }
-        _ => Err("Unsupported data type".to_string())
+        "csv" => {
+            let delimiter = options
+                .csv_delimiter
+                .as_ref()
+                .and_then(|d| d.bytes().next())
+                .unwrap_or(b',');
+            export::write_csv(Path::new(&file_path), &[sheet], delimiter).map_err(db_error)?;
+        }
+        "xlsx" => {
+            let sheets = if options.sheet_per_level { sheet.split_by_level() } else { vec![sheet] };
+            export::write_xlsx(Path::new(&file_path), &sheets).map_err(db_error)?;
+        }
+        _ => return Err(format!("Unsupported export format: {}", format)),
     }
+
+    log::info!(target: "commands", "Exported {} {} rows as {} to {}", row_count, data_type, format, file_path);
+    Ok(format!("Exported {} {} to {}", row_count, data_type, file_path))
 }
 
 #[tauri::command]
@@ -61,44 +175,151 @@ pub async fn import_data(
     state: State<'_, AppState>,
     file_path: String,
     data_type: String,
-) -> Result<String, String> {
+    strategy: Option<String>,
+    dry_run: Option<bool>,
+) -> Result<ImportReport, String> {
     if !Path::new(&file_path).exists() {
         return Err("File does not exist".to_string());
     }
 
+    let strategy = ImportStrategy::parse(strategy.as_deref().unwrap_or("skip_existing")).map_err(db_error)?;
+    let dry_run = dry_run.unwrap_or(false);
+
     let file_content = fs::read_to_string(&file_path).await.map_err(|e| e.to_string())?;
-    
-    match data_type.as_str() {
-        "students" => {
-            let students: Vec<crate::models::Student> = serde_json::from_str(&file_content)
-                .map_err(|e| e.to_string())?;
-            
-            // TODO: Implement bulk insert for students
-            Ok(format!("Imported {} students from {}", students.len(), file_path))
+    let rows = import::parse_rows(&file_content).map_err(db_error)?;
+
+    let db = state.db.lock().await;
+    let settings = db.load_settings().await.map_err(db_error)?.unwrap_or_default();
+    let report = match data_type.as_str() {
+        "students" => db.import_students(rows, strategy, dry_run).await,
+        "payments" => {
+            db.import_payments(rows, strategy, dry_run, &settings.general.academic_year, &settings.payments.receipt_prefix)
+                .await
+        }
+        "attendance" => db.import_attendance(rows, strategy, dry_run).await,
+        _ => return Err("Unsupported data type".to_string()),
+    }
+    .map_err(db_error)?;
+
+    log::info!(
+        target: "commands",
+        "Import of {} from {} (dry run: {}): {} inserted, {} updated, {} skipped, {} failed",
+        data_type, file_path, dry_run, report.inserted, report.updated, report.skipped, report.failed
+    );
+    Ok(report)
+}
+
+#[tauri::command]
+pub async fn preview_student_spreadsheet(file_path: String, sheet: Option<String>) -> Result<SpreadsheetPreview, String> {
+    let data = spreadsheet::read(Path::new(&file_path), sheet.as_deref()).map_err(db_error)?;
+
+    Ok(SpreadsheetPreview {
+        suggested_mapping: import::suggest_student_mapping(&data.headers),
+        sample_rows: data.rows.iter().take(10).map(|(_, cells)| cells.clone()).collect(),
+        total_rows: data.rows.len(),
+        sheet_names: data.sheet_names,
+        headers: data.headers,
+    })
+}
+
+#[tauri::command]
+pub async fn import_student_spreadsheet(
+    state: State<'_, AppState>,
+    file_path: String,
+    mapping: HashMap<String, String>,
+    sheet: Option<String>,
+    strategy: Option<String>,
+    dry_run: Option<bool>,
+) -> Result<ImportReport, String> {
+    let strategy = ImportStrategy::parse(strategy.as_deref().unwrap_or("skip_existing")).map_err(db_error)?;
+    let dry_run = dry_run.unwrap_or(false);
+
+    let data = spreadsheet::read(Path::new(&file_path), sheet.as_deref()).map_err(db_error)?;
+    let (rows, lines) = import::student_rows_from_sheet(&data, &mapping).map_err(db_error)?;
+
+    let db = state.db.lock().await;
+    let mut report = db.import_students(rows, strategy, dry_run).await.map_err(db_error)?;
+
+    // Report spreadsheet line numbers rather than positions among data rows.
+    for row in &mut report.rows {
+        if let Some(line) = lines.get(row.row - 1) {
+            row.row = *line;
         }
-        _ => Err("Unsupported data type".to_string())
     }
+
+    log::info!(
+        target: "commands",
+        "Spreadsheet import from {} (dry run: {}): {} inserted, {} updated, {} skipped, {} failed",
+        file_path, dry_run, report.inserted, report.updated, report.skipped, report.failed
+    );
+    Ok(report)
+}
+
+/// Folder of stored attachments next to a backup. Stored files never
+/// change, so every backup in a folder shares it.
+fn backup_attachments_dir(backup_path: &str) -> PathBuf {
+    Path::new(backup_path)
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("attachments")
 }
 
 #[tauri::command]
-pub async fn backup_database(backup_path: String) -> Result<String, String> {
-    let source_path = "centre_educatif.db";
+pub async fn backup_database(app: tauri::AppHandle, state: State<'_, AppState>, backup_path: String) -> Result<String, String> {
+    let source_path = DATABASE_FILE;
     
     if !Path::new(source_path).exists() {
         return Err("Database file not found".to_string());
     }
 
-    fs::copy(source_path, &backup_path).await.map_err(|e| e.to_string())?;
-    Ok(format!("Database backed up to {}", backup_path))
+    let size = fs::copy(source_path, &backup_path).await.map_err(|e| e.to_string())?;
+
+    let store = super::attachments::attachments_dir(&app);
+    let target = backup_attachments_dir(&backup_path);
+    let copied = tokio::task::spawn_blocking(move || attachments::copy_missing(&store, &target))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Database backed up but attachments were not: {}", e))?;
+    log::info!(target: "commands", "Backup to {}: {} new attachment file(s)", backup_path, copied);
+
+    let settings = {
+        let db = state.db.lock().await;
+        db.record_backup(&backup_path, size as i64).await.map_err(db_error)?;
+        db.load_settings().await.map_err(db_error)?
+    };
+
+    // A failed upload does not fail the local backup; the next sync resumes it.
+    match settings.map(|s| s.backup).filter(|b| b.cloud_sync) {
+        Some(backup) => match cloud_sync::upload_backup(&backup, Path::new(&backup_path)).await {
+            Ok(report) if !report.errors.is_empty() => Ok(format!(
+                "Database backed up to {} and synced to {} ({})",
+                backup_path,
+                backup.cloud_provider,
+                report.errors.join("; ")
+            )),
+            Ok(_) => Ok(format!("Database backed up to {} and synced to {}", backup_path, backup.cloud_provider)),
+            Err(e) => Ok(format!("Database backed up to {} (cloud sync failed: {})", backup_path, e)),
+        },
+        None => Ok(format!("Database backed up to {}", backup_path)),
+    }
 }
 
+/// Restores the database, and the attachment files found next to the
+/// backup that are missing locally.
 #[tauri::command]
-pub async fn restore_database(backup_path: String) -> Result<String, String> {
+pub async fn restore_database(app: tauri::AppHandle, backup_path: String) -> Result<String, String> {
     if !Path::new(&backup_path).exists() {
         return Err("Backup file does not exist".to_string());
     }
 
-    let target_path = "centre_educatif.db";
+    let target_path = DATABASE_FILE;
     fs::copy(&backup_path, target_path).await.map_err(|e| e.to_string())?;
+
+    let source = backup_attachments_dir(&backup_path);
+    let store = super::attachments::attachments_dir(&app);
+    tokio::task::spawn_blocking(move || attachments::copy_missing(&source, &store))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("Database restored but attachments were not: {}", e))?;
     Ok(format!("Database restored from {}", backup_path))
 }
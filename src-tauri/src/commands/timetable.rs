@@ -0,0 +1,213 @@
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use tauri::State;
+use uuid::Uuid;
+use crate::{AppState, schedule, models::{ScheduleConflict, SessionOccurrence, TimetableSlot}};
+use super::billing::parse_day;
+use super::db_error;
+
+/// Parses an RFC 3339 date-time or a local `YYYY-MM-DDTHH:MM` as sent by
+/// date-time inputs.
+fn parse_moment(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Ok(date.with_timezone(&Utc));
+    }
+    let local = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M").map_err(|_| format!("Invalid date and time {}", value))?;
+    Ok(schedule::local_datetime(local.date(), local.time()))
+}
+
+/// Adds a weekly slot to a group's timetable. The group's teacher takes it
+/// unless `teacher_id` is given; `starts_on` and `ends_on` are YYYY-MM-DD.
+#[tauri::command]
+pub async fn create_timetable_slot(
+    state: State<'_, AppState>,
+    group_id: String,
+    weekday: u32,
+    start_time: String,
+    end_time: String,
+    room: String,
+    teacher_id: Option<String>,
+    starts_on: Option<String>,
+    ends_on: Option<String>,
+) -> Result<TimetableSlot, String> {
+    let db = state.db.lock().await;
+    let group = db
+        .get_class_group(&group_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Group {} not found", group_id))?;
+
+    let mut slot = TimetableSlot {
+        id: Uuid::new_v4().to_string(),
+        group_id,
+        weekday,
+        start_time,
+        end_time,
+        room: room.trim().to_string(),
+        teacher_id: teacher_id.or(group.teacher_id),
+        starts_on: starts_on.as_deref().map(parse_day).transpose()?,
+        ends_on: ends_on.as_deref().map(parse_day).transpose()?,
+        is_active: true,
+        group_name: group.name,
+        teacher_name: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    schedule::validate_slot(&mut slot).map_err(db_error)?;
+
+    db.create_timetable_slot(slot).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn update_timetable_slot(state: State<'_, AppState>, mut slot: TimetableSlot) -> Result<TimetableSlot, String> {
+    schedule::validate_slot(&mut slot).map_err(db_error)?;
+    let db = state.db.lock().await;
+    db.update_timetable_slot(slot).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn get_timetable_slots(
+    state: State<'_, AppState>,
+    group_id: Option<String>,
+    teacher_id: Option<String>,
+    active_only: Option<bool>,
+) -> Result<Vec<TimetableSlot>, String> {
+    let db = state.db.lock().await;
+    db.get_timetable_slots(group_id.as_deref(), teacher_id.as_deref(), active_only.unwrap_or(true))
+        .await
+        .map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn delete_timetable_slot(state: State<'_, AppState>, slot_id: String) -> Result<(), String> {
+    let db = state.db.lock().await;
+    db.delete_timetable_slot(&slot_id).await.map_err(db_error)
+}
+
+/// Generates the sessions of the week containing `week_of` (YYYY-MM-DD,
+/// today by default) and returns all sessions of that week.
+#[tauri::command]
+pub async fn generate_week_sessions(
+    state: State<'_, AppState>,
+    week_of: Option<String>,
+) -> Result<Vec<SessionOccurrence>, String> {
+    let day = match week_of.as_deref() {
+        Some(day) => parse_day(day)?,
+        None => Local::now().date_naive(),
+    };
+
+    let db = state.db.lock().await;
+    db.generate_week_sessions(schedule::week_start(day)).await.map_err(db_error)
+}
+
+/// Sessions between `from` and `to` (YYYY-MM-DD, inclusive).
+#[tauri::command]
+pub async fn get_session_occurrences(
+    state: State<'_, AppState>,
+    from: Option<String>,
+    to: Option<String>,
+    group_id: Option<String>,
+    teacher_id: Option<String>,
+) -> Result<Vec<SessionOccurrence>, String> {
+    let from = from.as_deref().map(parse_day).transpose()?;
+    let to = to.as_deref().map(parse_day).transpose()?;
+
+    let db = state.db.lock().await;
+    db.get_session_occurrences(from, to, group_id.as_deref(), teacher_id.as_deref())
+        .await
+        .map_err(db_error)
+}
+
+/// Rooms and teachers booked twice between `from` and `to` (YYYY-MM-DD).
+#[tauri::command]
+pub async fn get_schedule_conflicts(
+    state: State<'_, AppState>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<ScheduleConflict>, String> {
+    let from = from.as_deref().map(parse_day).transpose()?;
+    let to = to.as_deref().map(parse_day).transpose()?;
+
+    let db = state.db.lock().await;
+    let occurrences = db.get_session_occurrences(from, to, None, None).await.map_err(db_error)?;
+    Ok(schedule::find_conflicts(&occurrences))
+}
+
+/// Schedules a session outside the timetable, such as a make-up class.
+#[tauri::command]
+pub async fn create_session_occurrence(
+    state: State<'_, AppState>,
+    group_id: String,
+    starts_at: String,
+    ends_at: String,
+    room: String,
+    teacher_id: Option<String>,
+    notes: Option<String>,
+) -> Result<SessionOccurrence, String> {
+    let (starts_at, ends_at) = (parse_moment(&starts_at)?, parse_moment(&ends_at)?);
+    if ends_at <= starts_at {
+        return Err("The session must end after it starts".to_string());
+    }
+    if room.trim().is_empty() {
+        return Err("A session needs a room".to_string());
+    }
+
+    let db = state.db.lock().await;
+    let group = db
+        .get_class_group(&group_id)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| format!("Group {} not found", group_id))?;
+
+    let occurrence = SessionOccurrence {
+        id: Uuid::new_v4().to_string(),
+        slot_id: None,
+        group_id,
+        teacher_id: teacher_id.or(group.teacher_id),
+        room: room.trim().to_string(),
+        occurrence_date: starts_at.with_timezone(&Local).date_naive(),
+        starts_at,
+        ends_at,
+        status: "scheduled".to_string(),
+        original_starts_at: None,
+        cancel_reason: None,
+        notes,
+        group_name: group.name,
+        teacher_name: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    db.create_session_occurrence(occurrence).await.map_err(db_error)
+}
+
+#[tauri::command]
+pub async fn cancel_session_occurrence(
+    state: State<'_, AppState>,
+    occurrence_id: String,
+    reason: Option<String>,
+) -> Result<SessionOccurrence, String> {
+    let db = state.db.lock().await;
+    let occurrence = db
+        .cancel_session_occurrence(&occurrence_id, reason.as_deref())
+        .await
+        .map_err(db_error)?;
+    log::info!(target: "schedule", "Session {} of {} cancelled", occurrence.id, occurrence.group_name);
+    Ok(occurrence)
+}
+
+/// Moves one session to another time, and optionally another room or teacher.
+#[tauri::command]
+pub async fn reschedule_session_occurrence(
+    state: State<'_, AppState>,
+    occurrence_id: String,
+    starts_at: String,
+    ends_at: String,
+    room: Option<String>,
+    teacher_id: Option<String>,
+) -> Result<SessionOccurrence, String> {
+    let (starts_at, ends_at) = (parse_moment(&starts_at)?, parse_moment(&ends_at)?);
+
+    let db = state.db.lock().await;
+    db.reschedule_session_occurrence(&occurrence_id, starts_at, ends_at, room, teacher_id)
+        .await
+        .map_err(db_error)
+}
@@ -1,18 +1,36 @@
-use sqlx::{SqlitePool, Row};
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use sqlx::{SqlitePool, Row, Sqlite, Transaction, sqlite::SqliteRow};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
-use crate::models::{Student, Payment, Attendance, DatabaseStats};
+use std::collections::HashMap;
+use crate::models::{Student, Attachment, Guardian, StudentGuardian, Payment, Attendance, DatabaseStats, TableCount, AppSettings, ReceiptSequenceReport, PaymentPlan, Due, Discount, DueDiscount, CashSession, CashSessionTotal, ZReport, Expense, Teacher, Subject, ClassGroup, Enrollment, Room, Occupant, TimetableSlot, SessionOccurrence, PayrollRule, PayrollAdjustment, PayrollPayment, YearRollover, LedgerEntry, StudentBalance, PaymentReversal, ImportReport};
+use crate::import::{self, ImportStrategy, RowAction, StudentRow, PaymentRow, AttendanceRow};
+use crate::billing;
+use crate::schedule;
+use crate::payroll;
+
+pub const DATABASE_FILE: &str = "centre_educatif.db";
+
+/// Share of free pages above which a VACUUM is recommended.
+const FREELIST_MAINTENANCE_RATIO: f64 = 0.2;
 
 pub struct Database {
     pool: SqlitePool,
 }
 
+/// Who reverses a payment and why, and how its credit note is numbered.
+pub struct ReversalDetails<'a> {
+    pub reason: &'a str,
+    pub created_by: &'a str,
+    pub refund_method: Option<&'a str>,
+    pub academic_year: &'a str,
+    pub prefix: &'a str,
+}
+
 impl Database {
     pub async fn new() -> Result<Self> {
         // Create database file in app data directory
-        let db_path = "centre_educatif.db";
-        let database_url = format!("sqlite:{}", db_path);
+        let database_url = format!("sqlite:{}", DATABASE_FILE);
         
         let pool = SqlitePool::connect(&database_url).await?;
         
@@ -60,6 +78,7 @@ impl Database {
                 status TEXT NOT NULL,
                 notes TEXT,
                 receipt_number TEXT,
+                cashier TEXT,
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL,
                 FOREIGN KEY (student_id) REFERENCES students (id)
@@ -88,179 +107,5238 @@ impl Database {
         .execute(&self.pool)
         .await?;
 
-        // Insert sample data if tables are empty
-        self.insert_sample_data().await?;
-
-        Ok(())
-    }
+        // Create settings table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    async fn insert_sample_data(&self) -> Result<()> {
-        // Check if we already have data
-        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM students")
-            .fetch_one(&self.pool)
-            .await?;
+        // Create receipt number sequences, one counter per academic year
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS receipt_sequences (
+                academic_year TEXT PRIMARY KEY,
+                last_number INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        if count > 0 {
-            return Ok(());
-        }
+        // Create payment plans and the dues they schedule
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payment_plans (
+                id TEXT PRIMARY KEY,
+                student_id TEXT NOT NULL,
+                academic_year TEXT NOT NULL,
+                monthly_fee REAL NOT NULL,
+                installments INTEGER NOT NULL,
+                start_month TEXT NOT NULL,
+                due_day INTEGER NOT NULL,
+                notes TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (student_id) REFERENCES students (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        // Insert sample students
-        let sample_students = vec![
-            ("Ahmed", "Benali", "1ère CEM", "0555123456", "Fatima Benali", "0555123457"),
-            ("Amina", "Khelifi", "2ème CEM", "0555234567", "Omar Khelifi", "0555234568"),
-            ("Youcef", "Mansouri", "3ème CEM", "0555345678", "Aicha Mansouri", "0555345679"),
-            ("Salma", "Boudiaf", "1ère Lycée", "0555456789", "Karim Boudiaf", "0555456790"),
-            ("Riad", "Zerrouki", "2ème Lycée", "0555567890", "Nadia Zerrouki", "0555567891"),
-        ];
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS dues (
+                id TEXT PRIMARY KEY,
+                plan_id TEXT,
+                student_id TEXT NOT NULL,
+                month TEXT NOT NULL,
+                due_date TEXT NOT NULL,
+                amount REAL NOT NULL,
+                paid_amount REAL NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                kind TEXT NOT NULL DEFAULT 'installment',
+                parent_due_id TEXT,
+                waived_by TEXT,
+                waived_at TEXT,
+                waive_reason TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (plan_id) REFERENCES payment_plans (id),
+                FOREIGN KEY (student_id) REFERENCES students (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        for (first_name, last_name, level, phone, parent_name, parent_phone) in sample_students {
-            let id = Uuid::new_v4().to_string();
-            let now = Utc::now().to_rfc3339();
-            
-            sqlx::query(
-                r#"
-                INSERT INTO students (
-                    id, first_name, last_name, academic_level, phone, parent_name, 
-                    parent_phone, enrollment_date, is_active, created_at, updated_at
-                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
-                "#,
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS discounts (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                scope TEXT NOT NULL,
+                target TEXT,
+                kind TEXT NOT NULL,
+                value REAL NOT NULL,
+                min_siblings INTEGER NOT NULL DEFAULT 0,
+                starts_on TEXT,
+                ends_on TEXT,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                notes TEXT,
+                created_at TEXT NOT NULL
             )
-            .bind(&id)
-            .bind(first_name)
-            .bind(last_name)
-            .bind(level)
-            .bind(phone)
-            .bind(parent_name)
-            .bind(parent_phone)
-            .bind(&now)
-            .bind(true)
-            .bind(&now)
-            .bind(&now)
-            .execute(&self.pool)
-            .await?;
-        }
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(())
-    }
+        // Discounts taken off a due; the due amount is already net of them
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS due_discounts (
+                id TEXT PRIMARY KEY,
+                due_id TEXT NOT NULL,
+                discount_id TEXT,
+                label TEXT NOT NULL,
+                amount REAL NOT NULL,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (due_id) REFERENCES dues (id),
+                FOREIGN KEY (discount_id) REFERENCES discounts (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_students(&self) -> Result<Vec<Student>> {
-        let rows = sqlx::query("SELECT * FROM students ORDER BY created_at DESC")
-            .fetch_all(&self.pool)
-            .await?;
+        // Create payment allocations, the share of a payment applied to a due
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payment_allocations (
+                payment_id TEXT NOT NULL,
+                due_id TEXT NOT NULL,
+                amount REAL NOT NULL,
+                PRIMARY KEY (payment_id, due_id),
+                FOREIGN KEY (payment_id) REFERENCES payments (id),
+                FOREIGN KEY (due_id) REFERENCES dues (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        let mut students = Vec::new();
-        for row in rows {
-            let student = Student {
-                id: row.get("id"),
-                first_name: row.get("first_name"),
-                last_name: row.get("last_name"),
-                email: row.get("email"),
-                phone: row.get("phone"),
-                academic_level: row.get("academic_level"),
-                rfid_card: row.get("rfid_card"),
-                parent_name: row.get("parent_name"),
-                parent_phone: row.get("parent_phone"),
-                address: row.get("address"),
-                birth_date: row.get("birth_date"),
-                enrollment_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("enrollment_date"))?.with_timezone(&Utc),
-                is_active: row.get("is_active"),
-                notes: row.get("notes"),
-                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
-                updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
-            };
-            students.push(student);
-        }
+        // Create payment reversals; the reversed payment row is never deleted
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payment_reversals (
+                id TEXT PRIMARY KEY,
+                payment_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                amount REAL NOT NULL,
+                sessions INTEGER NOT NULL DEFAULT 0,
+                refund_method TEXT,
+                reason TEXT NOT NULL,
+                created_by TEXT NOT NULL,
+                credit_note_number TEXT NOT NULL UNIQUE,
+                cash_session_id TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (payment_id) REFERENCES payments (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(students)
-    }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS credit_note_sequences (
+                academic_year TEXT PRIMARY KEY,
+                last_number INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn create_student(&self, student: Student) -> Result<Student> {
-        let now = Utc::now().to_rfc3339();
-        
+        // Cash register sessions and their totals per payment method at closing
         sqlx::query(
             r#"
-            INSERT INTO students (
-                id, first_name, last_name, email, phone, academic_level, rfid_card,
-                parent_name, parent_phone, address, birth_date, enrollment_date,
-                is_active, notes, created_at, updated_at
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            CREATE TABLE IF NOT EXISTS cash_sessions (
+                id TEXT PRIMARY KEY,
+                opened_by TEXT NOT NULL,
+                opened_at TEXT NOT NULL,
+                opening_float REAL NOT NULL,
+                status TEXT NOT NULL,
+                closed_by TEXT,
+                closed_at TEXT,
+                notes TEXT
+            )
             "#,
         )
-        .bind(&student.id)
-        .bind(&student.first_name)
-        .bind(&student.last_name)
-        .bind(&student.email)
-        .bind(&student.phone)
-        .bind(&student.academic_level)
-        .bind(&student.rfid_card)
-        .bind(&student.parent_name)
-        .bind(&student.parent_phone)
-        .bind(&student.address)
-        .bind(&student.birth_date)
-        .bind(student.enrollment_date.to_rfc3339())
-        .bind(student.is_active)
-        .bind(&student.notes)
-        .bind(&now)
-        .bind(&now)
         .execute(&self.pool)
         .await?;
 
-        Ok(student)
-    }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS cash_session_totals (
+                session_id TEXT NOT NULL,
+                payment_method TEXT NOT NULL,
+                payment_count INTEGER NOT NULL,
+                collected REAL NOT NULL,
+                refunded REAL NOT NULL,
+                expected REAL NOT NULL,
+                counted REAL,
+                PRIMARY KEY (session_id, payment_method),
+                FOREIGN KEY (session_id) REFERENCES cash_sessions (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_payments(&self) -> Result<Vec<Payment>> {
-        let rows = sqlx::query("SELECT * FROM payments ORDER BY payment_date DESC")
-            .fetch_all(&self.pool)
-            .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS expenses (
+                id TEXT PRIMARY KEY,
+                category TEXT NOT NULL,
+                description TEXT NOT NULL,
+                amount REAL NOT NULL,
+                expense_date TEXT NOT NULL,
+                payment_method TEXT NOT NULL,
+                supplier TEXT,
+                receipt_path TEXT,
+                notes TEXT,
+                created_by TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        let mut payments = Vec::new();
-        for row in rows {
-            let payment = Payment {
-                id: row.get("id"),
-                student_id: row.get("student_id"),
-                amount: row.get("amount"),
-                payment_type: row.get("payment_type"),
-                payment_method: row.get("payment_method"),
-                payment_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("payment_date"))?.with_timezone(&Utc),
-                due_date: row.get::<Option<String>, _>("due_date")
-                    .map(|d| DateTime::parse_from_rfc3339(&d).ok())
-                    .flatten()
-                    .map(|d| d.with_timezone(&Utc)),
-                status: row.get("status"),
-                notes: row.get("notes"),
-                receipt_number: row.get("receipt_number"),
-                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
-                updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
-            };
-            payments.push(payment);
-        }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS teachers (
+                id TEXT PRIMARY KEY,
+                first_name TEXT NOT NULL,
+                last_name TEXT NOT NULL,
+                phone TEXT,
+                email TEXT,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                notes TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-        Ok(payments)
-    }
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS subjects (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE,
+                code TEXT,
+                description TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_database_stats(&self) -> Result<DatabaseStats> {
-        let total_students: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM students")
-            .fetch_one(&self.pool)
-            .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS class_groups (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                academic_level TEXT NOT NULL,
+                subject_id TEXT NOT NULL,
+                teacher_id TEXT,
+                capacity INTEGER NOT NULL,
+                academic_year TEXT NOT NULL,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                notes TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (subject_id) REFERENCES subjects (id),
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS enrollments (
+                id TEXT PRIMARY KEY,
+                group_id TEXT NOT NULL,
+                student_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                enrolled_at TEXT NOT NULL,
+                withdrawn_at TEXT,
+                notes TEXT,
+                FOREIGN KEY (group_id) REFERENCES class_groups (id),
+                FOREIGN KEY (student_id) REFERENCES students (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS rooms (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+                capacity INTEGER NOT NULL,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                notes TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS timetable_slots (
+                id TEXT PRIMARY KEY,
+                group_id TEXT NOT NULL,
+                weekday INTEGER NOT NULL,
+                start_time TEXT NOT NULL,
+                end_time TEXT NOT NULL,
+                room TEXT NOT NULL,
+                teacher_id TEXT,
+                starts_on TEXT,
+                ends_on TEXT,
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (group_id) REFERENCES class_groups (id),
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS session_occurrences (
+                id TEXT PRIMARY KEY,
+                slot_id TEXT,
+                group_id TEXT NOT NULL,
+                teacher_id TEXT,
+                room TEXT NOT NULL,
+                occurrence_date TEXT NOT NULL,
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                status TEXT NOT NULL,
+                original_starts_at TEXT,
+                cancel_reason TEXT,
+                notes TEXT,
+                attendance_closed_at TEXT,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (slot_id) REFERENCES timetable_slots (id),
+                FOREIGN KEY (group_id) REFERENCES class_groups (id),
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payroll_rules (
+                id TEXT PRIMARY KEY,
+                teacher_id TEXT NOT NULL,
+                group_id TEXT,
+                mode TEXT NOT NULL,
+                rate REAL NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id),
+                FOREIGN KEY (group_id) REFERENCES class_groups (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payroll_adjustments (
+                id TEXT PRIMARY KEY,
+                teacher_id TEXT NOT NULL,
+                month TEXT NOT NULL,
+                label TEXT NOT NULL,
+                amount REAL NOT NULL,
+                created_by TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS payroll_payments (
+                id TEXT PRIMARY KEY,
+                teacher_id TEXT NOT NULL,
+                month TEXT NOT NULL,
+                amount REAL NOT NULL,
+                payment_method TEXT NOT NULL,
+                expense_id TEXT NOT NULL,
+                paid_by TEXT,
+                paid_at TEXT NOT NULL,
+                UNIQUE (teacher_id, month),
+                FOREIGN KEY (teacher_id) REFERENCES teachers (id),
+                FOREIGN KEY (expense_id) REFERENCES expenses (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                student_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                file_name TEXT NOT NULL,
+                content_hash TEXT NOT NULL,
+                extension TEXT NOT NULL,
+                mime_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                has_thumbnail BOOLEAN NOT NULL DEFAULT 0,
+                notes TEXT,
+                uploaded_by TEXT,
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (student_id) REFERENCES students (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS guardians (
+                id TEXT PRIMARY KEY,
+                first_name TEXT NOT NULL,
+                last_name TEXT NOT NULL,
+                phone TEXT NOT NULL,
+                secondary_phone TEXT,
+                email TEXT,
+                preferred_language TEXT NOT NULL DEFAULT 'fr',
+                address TEXT,
+                notes TEXT,
+                phone_key TEXT NOT NULL DEFAULT '',
+                secondary_phone_key TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Phones as `import::phone_key` writes them, to match however they were typed
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_guardians_phone_key ON guardians (phone_key)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_guardians_secondary_phone_key ON guardians (secondary_phone_key)")
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS student_guardians (
+                guardian_id TEXT NOT NULL,
+                student_id TEXT NOT NULL,
+                relationship TEXT NOT NULL,
+                is_primary BOOLEAN NOT NULL DEFAULT 0,
+                can_pick_up BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (guardian_id, student_id),
+                FOREIGN KEY (guardian_id) REFERENCES guardians (id),
+                FOREIGN KEY (student_id) REFERENCES students (id)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS year_rollovers (
+                id TEXT PRIMARY KEY,
+                from_year TEXT NOT NULL UNIQUE,
+                to_year TEXT NOT NULL,
+                report TEXT NOT NULL,
+                created_by TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create backup history table
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS backup_history (
+                id TEXT PRIMARY KEY,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Columns added after the first release
+        self.ensure_column("payments", "cashier", "TEXT").await?;
+        self.ensure_column("payments", "academic_year", "TEXT").await?;
+        self.ensure_column("payments", "receipt_sequence", "INTEGER").await?;
+        self.ensure_column("students", "sessions_remaining", "INTEGER NOT NULL DEFAULT 0").await?;
+        self.ensure_column("payments", "sessions", "INTEGER NOT NULL DEFAULT 0").await?;
+        self.ensure_column("payments", "cash_session_id", "TEXT").await?;
+        self.ensure_column("payments", "group_id", "TEXT REFERENCES class_groups (id)").await?;
+        self.ensure_column("attendance", "group_id", "TEXT REFERENCES class_groups (id)").await?;
+        self.ensure_column("attendance", "occurrence_id", "TEXT REFERENCES session_occurrences (id)").await?;
+        self.ensure_column("attendance", "academic_year", "TEXT").await?;
+        self.ensure_column("students", "photo_id", "TEXT REFERENCES attachments (id)").await?;
+
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_payments_receipt_sequence
+            ON payments (academic_year, receipt_sequence)
+            WHERE receipt_sequence IS NOT NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_enrollments_active
+            ON enrollments (group_id, student_id)
+            WHERE status = 'active'
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // One attendance per student and session
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_attendance_occurrence_student
+            ON attendance (occurrence_id, student_id)
+            WHERE occurrence_id IS NOT NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // One general rule per teacher and one per group
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_payroll_rules_teacher_group
+            ON payroll_rules (teacher_id, COALESCE(group_id, ''))
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Generating a week twice must not duplicate its sessions
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_session_occurrences_slot_date
+            ON session_occurrences (slot_id, occurrence_date)
+            WHERE slot_id IS NOT NULL
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_session_occurrences_starts_at ON session_occurrences (starts_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // A single cash drawer: at most one session open at a time
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_cash_sessions_open
+            ON cash_sessions (status)
+            WHERE status = 'open'
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // At most one late fee per due, so the daily job can run any number of times
+        sqlx::query(
+            r#"
+            CREATE UNIQUE INDEX IF NOT EXISTS idx_dues_late_fee
+            ON dues (parent_due_id)
+            WHERE kind = 'late_fee'
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Insert sample data if tables are empty
+        self.insert_sample_data().await?;
+        self.migrate_parent_guardians().await?;
+
+        Ok(())
+    }
+
+    /// Adds `column` to a table created by an older version of the app.
+    async fn ensure_column(&self, table: &str, column: &str, definition: &str) -> Result<()> {
+        let exists: bool = sqlx::query_scalar(&format!("SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = ?", table))
+            .bind(column)
+            .fetch_one(&self.pool)
+            .await?;
+
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn insert_sample_data(&self) -> Result<()> {
+        // Check if we already have data
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM students")
+            .fetch_one(&self.pool)
+            .await?;
+
+        if count > 0 {
+            return Ok(());
+        }
+
+        // Insert sample students
+        let sample_students = vec![
+            ("Ahmed", "Benali", "1ère CEM", "0555123456", "Fatima Benali", "0555123457"),
+            ("Amina", "Khelifi", "2ème CEM", "0555234567", "Omar Khelifi", "0555234568"),
+            ("Youcef", "Mansouri", "3ème CEM", "0555345678", "Aicha Mansouri", "0555345679"),
+            ("Salma", "Boudiaf", "1ère Lycée", "0555456789", "Karim Boudiaf", "0555456790"),
+            ("Riad", "Zerrouki", "2ème Lycée", "0555567890", "Nadia Zerrouki", "0555567891"),
+        ];
+
+        for (first_name, last_name, level, phone, parent_name, parent_phone) in sample_students {
+            let id = Uuid::new_v4().to_string();
+            let now = Utc::now().to_rfc3339();
+            
+            sqlx::query(
+                r#"
+                INSERT INTO students (
+                    id, first_name, last_name, academic_level, phone, parent_name, 
+                    parent_phone, enrollment_date, is_active, created_at, updated_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(first_name)
+            .bind(last_name)
+            .bind(level)
+            .bind(phone)
+            .bind(parent_name)
+            .bind(parent_phone)
+            .bind(&now)
+            .bind(true)
+            .bind(&now)
+            .bind(&now)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn get_students(&self) -> Result<Vec<Student>> {
+        let rows = sqlx::query("SELECT * FROM students ORDER BY created_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_student).collect()
+    }
+
+    pub async fn get_student(&self, id: &str) -> Result<Option<Student>> {
+        let row = sqlx::query("SELECT * FROM students WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_student).transpose()
+    }
+
+    /// Saves a student, linking their parent as a guardian, shared with
+    /// siblings when the phone matches.
+    pub async fn create_student(&self, student: Student) -> Result<Student> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            INSERT INTO students (
+                id, first_name, last_name, email, phone, academic_level, rfid_card,
+                parent_name, parent_phone, address, birth_date, enrollment_date,
+                is_active, notes, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&student.id)
+        .bind(&student.first_name)
+        .bind(&student.last_name)
+        .bind(&student.email)
+        .bind(&student.phone)
+        .bind(&student.academic_level)
+        .bind(&student.rfid_card)
+        .bind(&student.parent_name)
+        .bind(&student.parent_phone)
+        .bind(&student.address)
+        .bind(&student.birth_date)
+        .bind(student.enrollment_date.to_rfc3339())
+        .bind(student.is_active)
+        .bind(&student.notes)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?;
+        if !student.parent_phone.is_empty() {
+            link_parent_guardian(&mut tx, &student.id, &student.parent_name, &student.parent_phone).await?;
+        }
+
+        tx.commit().await?;
+        Ok(student)
+    }
+
+    pub async fn get_payments(&self) -> Result<Vec<Payment>> {
+        let rows = sqlx::query("SELECT * FROM payments ORDER BY payment_date DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_payment).collect()
+    }
+
+    pub async fn get_payment(&self, id: &str) -> Result<Option<Payment>> {
+        let row = sqlx::query("SELECT * FROM payments WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_payment).transpose()
+    }
+
+    /// Records a payment under the next receipt number of `academic_year`
+    /// and files it in the open cash register session.
+    pub async fn create_payment(
+        &self,
+        mut payment: Payment,
+        academic_year: &str,
+        prefix: &str,
+        due_id: Option<&str>,
+        allow_partial: bool,
+        require_cash_session: bool,
+    ) -> Result<Payment> {
+        let mut tx = self.pool.begin().await?;
+
+        payment.cash_session_id = sqlx::query_scalar("SELECT id FROM cash_sessions WHERE status = 'open'")
+            .fetch_optional(&mut *tx)
+            .await?;
+        if require_cash_session && payment.cash_session_id.is_none() {
+            return Err(anyhow!("No cash register session is open"));
+        }
+
+        let payment = insert_payment(&mut tx, payment, academic_year, prefix, due_id, allow_partial).await?;
+        tx.commit().await?;
+        Ok(payment)
+    }
+
+    /// Saves a plan and its dues, taking `discounts` off each installment
+    /// they cover.
+    pub async fn create_payment_plan(
+        &self,
+        plan: PaymentPlan,
+        schedule: &[(String, NaiveDate)],
+        discounts: &[Discount],
+    ) -> Result<PaymentPlan> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now().to_rfc3339();
+
+        sqlx::query(
+            r#"
+            INSERT INTO payment_plans (
+                id, student_id, academic_year, monthly_fee, installments, start_month,
+                due_day, notes, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&plan.id)
+        .bind(&plan.student_id)
+        .bind(&plan.academic_year)
+        .bind(plan.monthly_fee)
+        .bind(plan.installments)
+        .bind(&plan.start_month)
+        .bind(plan.due_day)
+        .bind(&plan.notes)
+        .bind(plan.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        for (month, due_date) in schedule {
+            let due_id = Uuid::new_v4().to_string();
+            let lines = billing::discount_lines(plan.monthly_fee, *due_date, discounts);
+            let amount = plan.monthly_fee - lines.iter().map(|(_, amount)| amount).sum::<f64>();
+
+            sqlx::query(
+                r#"
+                INSERT INTO dues (
+                    id, plan_id, student_id, month, due_date, amount, paid_amount,
+                    status, created_at, updated_at
+                ) VALUES (?, ?, ?, ?, ?, ?, 0, ?, ?, ?)
+                "#,
+            )
+            .bind(&due_id)
+            .bind(&plan.id)
+            .bind(&plan.student_id)
+            .bind(month)
+            .bind(billing::local_midnight(*due_date).to_rfc3339())
+            .bind(amount)
+            .bind(billing::due_status(amount, 0.0))
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+
+            for (discount, amount) in lines {
+                sqlx::query(
+                    "INSERT INTO due_discounts (id, due_id, discount_id, label, amount, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(&due_id)
+                .bind(&discount.id)
+                .bind(billing::discount_label(discount))
+                .bind(amount)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(plan)
+    }
+
+    pub async fn get_payment_plans(&self, student_id: Option<&str>) -> Result<Vec<PaymentPlan>> {
+        let rows = sqlx::query("SELECT * FROM payment_plans WHERE ?1 IS NULL OR student_id = ?1 ORDER BY created_at DESC")
+            .bind(student_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut plans = Vec::new();
+        for row in rows {
+            plans.push(PaymentPlan {
+                id: row.get("id"),
+                student_id: row.get("student_id"),
+                academic_year: row.get("academic_year"),
+                monthly_fee: row.get("monthly_fee"),
+                installments: row.get("installments"),
+                start_month: row.get("start_month"),
+                due_day: row.get("due_day"),
+                notes: row.get("notes"),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+            });
+        }
+
+        Ok(plans)
+    }
+
+    /// Dues matching every given filter, earliest first. `status` may also be
+    /// "outstanding" for unpaid and partially paid dues; `from` is inclusive
+    /// and `to` exclusive.
+    pub async fn get_dues(
+        &self,
+        student_id: Option<&str>,
+        status: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Due>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE (?1 IS NULL OR d.student_id = ?1)
+              AND (?2 IS NULL OR d.status = ?2 OR (?2 = 'outstanding' AND d.status IN ('unpaid', 'partial')))
+              AND (?3 IS NULL OR d.due_date >= ?3)
+              AND (?4 IS NULL OR d.due_date < ?4)
+            ORDER BY d.due_date, student_name
+            "#,
+            DUE_SELECT,
+        ))
+        .bind(student_id)
+        .bind(status)
+        .bind(from.map(|d| d.to_rfc3339()))
+        .bind(to.map(|d| d.to_rfc3339()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_due).collect()
+    }
+
+    pub async fn get_due(&self, id: &str) -> Result<Option<Due>> {
+        let row = sqlx::query(&format!("{} WHERE d.id = ?", DUE_SELECT))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_due).transpose()
+    }
+
+    /// Dues a payment was applied to, with the amount applied to each.
+    pub async fn get_payment_allocations(&self, payment_id: &str) -> Result<Vec<(Due, f64)>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT a.due_id, a.amount FROM payment_allocations a
+            JOIN dues d ON d.id = a.due_id
+            WHERE a.payment_id = ? AND a.amount > 0
+            ORDER BY d.due_date, d.created_at
+            "#,
+        )
+        .bind(payment_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut allocations = Vec::new();
+        for row in rows {
+            if let Some(due) = self.get_due(&row.get::<String, _>("due_id")).await? {
+                allocations.push((due, row.get("amount")));
+            }
+        }
+        Ok(allocations)
+    }
+
+    pub async fn create_discount(&self, discount: Discount) -> Result<Discount> {
+        sqlx::query(
+            r#"
+            INSERT INTO discounts (
+                id, name, scope, target, kind, value, min_siblings, starts_on, ends_on,
+                is_active, notes, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&discount.id)
+        .bind(&discount.name)
+        .bind(&discount.scope)
+        .bind(&discount.target)
+        .bind(&discount.kind)
+        .bind(discount.value)
+        .bind(discount.min_siblings)
+        .bind(discount.starts_on.map(|d| d.to_string()))
+        .bind(discount.ends_on.map(|d| d.to_string()))
+        .bind(discount.is_active)
+        .bind(&discount.notes)
+        .bind(discount.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(discount)
+    }
+
+    /// Changes a rule for dues generated from now on; existing dues keep
+    /// the discounts they were created with.
+    pub async fn update_discount(&self, discount: Discount) -> Result<Discount> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE discounts SET name = ?, scope = ?, target = ?, kind = ?, value = ?, min_siblings = ?,
+                starts_on = ?, ends_on = ?, is_active = ?, notes = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&discount.name)
+        .bind(&discount.scope)
+        .bind(&discount.target)
+        .bind(&discount.kind)
+        .bind(discount.value)
+        .bind(discount.min_siblings)
+        .bind(discount.starts_on.map(|d| d.to_string()))
+        .bind(discount.ends_on.map(|d| d.to_string()))
+        .bind(discount.is_active)
+        .bind(&discount.notes)
+        .bind(&discount.id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Discount {} not found", discount.id));
+        }
+        Ok(discount)
+    }
+
+    pub async fn get_discounts(&self, active_only: bool) -> Result<Vec<Discount>> {
+        let rows = sqlx::query("SELECT * FROM discounts WHERE ? = 0 OR is_active = 1 ORDER BY created_at")
+            .bind(active_only)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_discount).collect()
+    }
+
+    /// Active students sharing a guardian or the parent phone with
+    /// `student`, the family used by sibling discounts.
+    pub async fn count_family_students(&self, student: &Student) -> Result<i64> {
+        let count = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM students
+            WHERE is_active = 1 AND (
+                (?1 != '' AND parent_phone = ?1)
+                OR id = ?2
+                OR id IN (
+                    SELECT other.student_id FROM student_guardians own
+                    JOIN student_guardians other ON other.guardian_id = own.guardian_id
+                    WHERE own.student_id = ?2
+                )
+            )
+            "#,
+        )
+        .bind(&student.parent_phone)
+        .bind(&student.id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Gives the students of databases created before guardians existed a
+    /// guardian built from `parent_name`/`parent_phone`, shared by students
+    /// whose parent phones match. Runs once; later links are left alone.
+    async fn migrate_parent_guardians(&self) -> Result<()> {
+        let done: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM settings WHERE key = 'migration.parent_guardians'")
+            .fetch_one(&self.pool)
+            .await?;
+        if done > 0 {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query(
+            "SELECT id, parent_name, parent_phone FROM students WHERE parent_phone != '' AND id NOT IN (SELECT student_id FROM student_guardians)",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for row in rows {
+            link_parent_guardian(&mut tx, row.get("id"), row.get("parent_name"), row.get("parent_phone")).await?;
+        }
+
+        sqlx::query("INSERT INTO settings (key, value, updated_at) VALUES ('migration.parent_guardians', 'done', ?)")
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Saves an attachment; a photo becomes the student's current photo.
+    pub async fn create_attachment(&self, attachment: Attachment) -> Result<Attachment> {
+        if self.get_student(&attachment.student_id).await?.is_none() {
+            return Err(anyhow!("Student {} not found", attachment.student_id));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO attachments (
+                id, student_id, kind, file_name, content_hash, extension, mime_type, size,
+                has_thumbnail, notes, uploaded_by, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&attachment.id)
+        .bind(&attachment.student_id)
+        .bind(&attachment.kind)
+        .bind(&attachment.file_name)
+        .bind(&attachment.content_hash)
+        .bind(&attachment.extension)
+        .bind(&attachment.mime_type)
+        .bind(attachment.size)
+        .bind(attachment.has_thumbnail)
+        .bind(&attachment.notes)
+        .bind(&attachment.uploaded_by)
+        .bind(attachment.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        if attachment.kind == "photo" {
+            sqlx::query("UPDATE students SET photo_id = ?, updated_at = ? WHERE id = ?")
+                .bind(&attachment.id)
+                .bind(Utc::now().to_rfc3339())
+                .bind(&attachment.student_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(attachment)
+    }
+
+    pub async fn get_attachment(&self, id: &str) -> Result<Option<Attachment>> {
+        let row = sqlx::query("SELECT * FROM attachments WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_attachment).transpose()
+    }
+
+    /// Attachments of a student, newest first, optionally of one kind.
+    pub async fn get_attachments(&self, student_id: &str, kind: Option<&str>) -> Result<Vec<Attachment>> {
+        let rows = sqlx::query(
+            "SELECT * FROM attachments WHERE student_id = ?1 AND (?2 IS NULL OR kind = ?2) ORDER BY created_at DESC",
+        )
+        .bind(student_id)
+        .bind(kind)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_attachment).collect()
+    }
+
+    /// Deletes an attachment and returns it with whether its file is still
+    /// used by another attachment. A deleted photo falls back to the
+    /// student's previous one.
+    pub async fn delete_attachment(&self, id: &str) -> Result<(Attachment, bool)> {
+        let attachment = self
+            .get_attachment(id)
+            .await?
+            .ok_or_else(|| anyhow!("Attachment {} not found", id))?;
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE students SET
+                photo_id = (
+                    SELECT id FROM attachments
+                    WHERE student_id = ?1 AND kind = 'photo' AND id != ?2
+                    ORDER BY created_at DESC LIMIT 1
+                ),
+                updated_at = ?3
+            WHERE id = ?1 AND photo_id = ?2
+            "#,
+        )
+        .bind(&attachment.student_id)
+        .bind(id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM attachments WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let shared: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attachments WHERE content_hash = ?")
+            .bind(&attachment.content_hash)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok((attachment, shared > 0))
+    }
+
+    pub async fn create_guardian(&self, guardian: Guardian) -> Result<Guardian> {
+        sqlx::query(
+            r#"
+            INSERT INTO guardians (
+                id, first_name, last_name, phone, secondary_phone, email, preferred_language,
+                address, notes, phone_key, secondary_phone_key, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&guardian.id)
+        .bind(&guardian.first_name)
+        .bind(&guardian.last_name)
+        .bind(&guardian.phone)
+        .bind(&guardian.secondary_phone)
+        .bind(&guardian.email)
+        .bind(&guardian.preferred_language)
+        .bind(&guardian.address)
+        .bind(&guardian.notes)
+        .bind(import::phone_key(&guardian.phone))
+        .bind(guardian.secondary_phone.as_deref().map(import::phone_key).unwrap_or_default())
+        .bind(guardian.created_at.to_rfc3339())
+        .bind(guardian.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(guardian)
+    }
+
+    /// Saves a guardian and refreshes the parent fields of the students
+    /// they are the primary guardian of.
+    pub async fn update_guardian(&self, guardian: Guardian) -> Result<Guardian> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE guardians SET
+                first_name = ?, last_name = ?, phone = ?, secondary_phone = ?, email = ?,
+                preferred_language = ?, address = ?, notes = ?, phone_key = ?, secondary_phone_key = ?,
+                updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&guardian.first_name)
+        .bind(&guardian.last_name)
+        .bind(&guardian.phone)
+        .bind(&guardian.secondary_phone)
+        .bind(&guardian.email)
+        .bind(&guardian.preferred_language)
+        .bind(&guardian.address)
+        .bind(&guardian.notes)
+        .bind(import::phone_key(&guardian.phone))
+        .bind(guardian.secondary_phone.as_deref().map(import::phone_key).unwrap_or_default())
+        .bind(now.to_rfc3339())
+        .bind(&guardian.id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Err(anyhow!("Guardian {} not found", guardian.id));
+        }
+
+        sync_parent_fields(&mut tx, &guardian.id).await?;
+        tx.commit().await?;
+
+        Ok(Guardian { updated_at: now, ..guardian })
+    }
+
+    pub async fn get_guardian(&self, id: &str) -> Result<Option<Guardian>> {
+        let row = sqlx::query("SELECT * FROM guardians WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_guardian).transpose()
+    }
+
+    /// Guardians whose name, phone or email contains `search`, by name.
+    pub async fn get_guardians(&self, search: Option<&str>) -> Result<Vec<Guardian>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM guardians
+            WHERE ?1 IS NULL
+                OR (first_name || ' ' || last_name) LIKE '%' || ?1 || '%'
+                OR phone LIKE '%' || ?1 || '%'
+                OR secondary_phone LIKE '%' || ?1 || '%'
+                OR email LIKE '%' || ?1 || '%'
+            ORDER BY last_name, first_name
+            "#,
+        )
+        .bind(search)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_guardian).collect()
+    }
+
+    /// Guardians reachable on `phone`, however it is written.
+    pub async fn find_guardians_by_phone(&self, phone: &str) -> Result<Vec<Guardian>> {
+        let key = import::phone_key(phone);
+        if key.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let rows = sqlx::query("SELECT * FROM guardians WHERE phone_key = ?1 OR secondary_phone_key = ?1 ORDER BY last_name, first_name")
+            .bind(key)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_guardian).collect()
+    }
+
+    /// Deletes a guardian along with their links to students.
+    pub async fn delete_guardian(&self, id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM student_guardians WHERE guardian_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let affected = sqlx::query("DELETE FROM guardians WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if affected == 0 {
+            return Err(anyhow!("Guardian {} not found", id));
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Links a guardian to a student, or updates the existing link. A new
+    /// primary guardian replaces the previous one.
+    pub async fn link_guardian(&self, link: StudentGuardian) -> Result<StudentGuardian> {
+        if self.get_guardian(&link.guardian_id).await?.is_none() {
+            return Err(anyhow!("Guardian {} not found", link.guardian_id));
+        }
+        if self.get_student(&link.student_id).await?.is_none() {
+            return Err(anyhow!("Student {} not found", link.student_id));
+        }
+
+        let mut tx = self.pool.begin().await?;
+        if link.is_primary {
+            sqlx::query("UPDATE student_guardians SET is_primary = 0 WHERE student_id = ? AND guardian_id != ?")
+                .bind(&link.student_id)
+                .bind(&link.guardian_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO student_guardians (guardian_id, student_id, relationship, is_primary, can_pick_up, created_at)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT (guardian_id, student_id) DO UPDATE SET
+                relationship = excluded.relationship,
+                is_primary = excluded.is_primary,
+                can_pick_up = excluded.can_pick_up
+            "#,
+        )
+        .bind(&link.guardian_id)
+        .bind(&link.student_id)
+        .bind(&link.relationship)
+        .bind(link.is_primary)
+        .bind(link.can_pick_up)
+        .bind(link.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        sync_parent_fields(&mut tx, &link.guardian_id).await?;
+        tx.commit().await?;
+
+        self.get_guardian_links(Some(&link.guardian_id), Some(&link.student_id))
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("Guardian link not found"))
+    }
+
+    pub async fn unlink_guardian(&self, guardian_id: &str, student_id: &str) -> Result<()> {
+        let affected = sqlx::query("DELETE FROM student_guardians WHERE guardian_id = ? AND student_id = ?")
+            .bind(guardian_id)
+            .bind(student_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if affected == 0 {
+            return Err(anyhow!("Guardian {} is not linked to student {}", guardian_id, student_id));
+        }
+        Ok(())
+    }
+
+    /// Guardian links matching every given filter, primary guardians first.
+    pub async fn get_guardian_links(&self, guardian_id: Option<&str>, student_id: Option<&str>) -> Result<Vec<StudentGuardian>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                sg.*,
+                g.first_name || ' ' || g.last_name AS guardian_name,
+                g.phone AS guardian_phone,
+                s.first_name || ' ' || s.last_name AS student_name
+            FROM student_guardians sg
+            JOIN guardians g ON g.id = sg.guardian_id
+            JOIN students s ON s.id = sg.student_id
+            WHERE (?1 IS NULL OR sg.guardian_id = ?1) AND (?2 IS NULL OR sg.student_id = ?2)
+            ORDER BY sg.is_primary DESC, student_name, guardian_name
+            "#,
+        )
+        .bind(guardian_id)
+        .bind(student_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_guardian_link).collect()
+    }
+
+    /// Discount lines of dues matching every given filter, by due date.
+    pub async fn get_due_discounts(
+        &self,
+        student_id: Option<&str>,
+        due_id: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<DueDiscount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT x.id, x.due_id, x.discount_id, x.label, x.amount, d.student_id, d.month, d.due_date,
+                s.first_name || ' ' || s.last_name AS student_name, s.academic_level
+            FROM due_discounts x
+            JOIN dues d ON d.id = x.due_id
+            JOIN students s ON s.id = d.student_id
+            WHERE (?1 IS NULL OR d.student_id = ?1)
+              AND (?2 IS NULL OR x.due_id = ?2)
+              AND (?3 IS NULL OR d.due_date >= ?3)
+              AND (?4 IS NULL OR d.due_date < ?4)
+            ORDER BY d.due_date, student_name, x.created_at
+            "#,
+        )
+        .bind(student_id)
+        .bind(due_id)
+        .bind(from.map(|d| d.to_rfc3339()))
+        .bind(to.map(|d| d.to_rfc3339()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut lines = Vec::new();
+        for row in rows {
+            lines.push(DueDiscount {
+                id: row.get("id"),
+                due_id: row.get("due_id"),
+                discount_id: row.get("discount_id"),
+                student_id: row.get("student_id"),
+                student_name: row.get("student_name"),
+                academic_level: row.get("academic_level"),
+                month: row.get("month"),
+                due_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("due_date"))?.with_timezone(&Utc),
+                label: row.get("label"),
+                amount: row.get("amount"),
+            });
+        }
+        Ok(lines)
+    }
+
+    /// Adds a late fee of `amount` to every unpaid or partially paid
+    /// installment whose due date is older than `after_days` days, and
+    /// returns the fees created by this run. Installments that already carry
+    /// a late fee, even a waived one, are left alone.
+    pub async fn apply_late_fees(&self, amount: f64, after_days: i64, now: DateTime<Utc>) -> Result<Vec<Due>> {
+        let cutoff = now - chrono::Duration::days(after_days.max(0));
+        let created_at = now.to_rfc3339();
+
+        let overdue = sqlx::query(
+            r#"
+            SELECT d.id, d.plan_id, d.student_id, d.month
+            FROM dues d
+            WHERE d.kind = 'installment'
+              AND d.status IN ('unpaid', 'partial')
+              AND d.due_date < ?
+              AND NOT EXISTS (SELECT 1 FROM dues f WHERE f.kind = 'late_fee' AND f.parent_due_id = d.id)
+            "#,
+        )
+        .bind(cutoff.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tx = self.pool.begin().await?;
+        let mut created = Vec::new();
+        for row in overdue {
+            let id = Uuid::new_v4().to_string();
+            let inserted = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO dues (
+                    id, plan_id, kind, parent_due_id, student_id, month, due_date, amount,
+                    paid_amount, status, created_at, updated_at
+                ) VALUES (?, ?, 'late_fee', ?, ?, ?, ?, ?, 0, 'unpaid', ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(row.get::<Option<String>, _>("plan_id"))
+            .bind(row.get::<String, _>("id"))
+            .bind(row.get::<String, _>("student_id"))
+            .bind(row.get::<String, _>("month"))
+            .bind(&created_at)
+            .bind(amount)
+            .bind(&created_at)
+            .bind(&created_at)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+            if inserted > 0 {
+                created.push(id);
+            }
+        }
+        tx.commit().await?;
+
+        let mut fees = Vec::new();
+        for id in created {
+            if let Some(due) = self.get_due(&id).await? {
+                fees.push(due);
+            }
+        }
+        Ok(fees)
+    }
+
+    /// Account lines of a student dated up to `to`, oldest first, with the
+    /// running balance. Dues count from their due date, so scheduled
+    /// installments only appear once they fall due.
+    pub async fn get_ledger(&self, student_id: &str, to: DateTime<Utc>) -> Result<Vec<LedgerEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM (
+            SELECT due_date AS date, kind AS entry_type, month AS reference, id AS due_id, NULL AS payment_id,
+                amount + (SELECT COALESCE(SUM(x.amount), 0.0) FROM due_discounts x WHERE x.due_id = dues.id) AS debit,
+                0.0 AS credit, NULL AS detail
+            FROM dues WHERE student_id = ?1 AND due_date <= ?2
+            UNION ALL
+            SELECT d.due_date, 'discount', d.month, d.id, NULL, 0.0, x.amount, x.label
+            FROM due_discounts x JOIN dues d ON d.id = x.due_id
+            WHERE d.student_id = ?1 AND d.due_date <= ?2
+            UNION ALL
+            SELECT waived_at, 'waiver', month, id, NULL, 0.0, amount, waive_reason
+            FROM dues WHERE student_id = ?1 AND status = 'waived' AND waived_at <= ?2
+            UNION ALL
+            SELECT payment_date, 'payment', receipt_number, NULL, id, 0.0, amount, payment_type
+            FROM payments WHERE student_id = ?1 AND status != 'unpaid' AND payment_date <= ?2
+            UNION ALL
+            SELECT r.created_at, r.kind, r.credit_note_number, NULL, r.payment_id, r.amount, 0.0, r.reason
+            FROM payment_reversals r JOIN payments p ON p.id = r.payment_id
+            WHERE p.student_id = ?1 AND r.created_at <= ?2
+            )
+            -- A due's discounts follow its charge
+            ORDER BY date, entry_type = 'discount', entry_type
+            "#,
+        )
+        .bind(student_id)
+        .bind(to.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut balance = 0.0;
+        let mut entries = Vec::new();
+        for row in rows {
+            let entry_type: String = row.get("entry_type");
+            let reference: Option<String> = row.get("reference");
+            let detail: Option<String> = row.get("detail");
+            let debit: f64 = row.get("debit");
+            let credit: f64 = row.get("credit");
+            balance += debit - credit;
+
+            entries.push(LedgerEntry {
+                date: DateTime::parse_from_rfc3339(&row.get::<String, _>("date"))?.with_timezone(&Utc),
+                description: billing::ledger_description(&entry_type, reference.as_deref(), detail.as_deref()),
+                entry_type,
+                reference,
+                due_id: row.get("due_id"),
+                payment_id: row.get("payment_id"),
+                debit,
+                credit,
+                balance,
+            });
+        }
+
+        Ok(entries)
+    }
+
+    pub async fn get_student_balance(&self, student_id: &str, now: DateTime<Utc>) -> Result<StudentBalance> {
+        let ledger = self.get_ledger(student_id, now).await?;
+
+        let row = sqlx::query(
+            r#"
+            SELECT
+                COALESCE(SUM(CASE WHEN due_date < ?2 THEN amount - paid_amount END), 0.0) AS overdue,
+                COALESCE(SUM(CASE WHEN due_date > ?2 THEN amount - paid_amount END), 0.0) AS upcoming
+            FROM dues WHERE student_id = ?1 AND status IN ('unpaid', 'partial')
+            "#,
+        )
+        .bind(student_id)
+        .bind(now.to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+
+        let allocated: f64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(a.amount), 0.0) FROM payment_allocations a
+            JOIN payments p ON p.id = a.payment_id
+            WHERE p.student_id = ?
+            "#,
+        )
+        .bind(student_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let sum = |entry_types: &[&str], value: fn(&LedgerEntry) -> f64| -> f64 {
+            ledger.iter().filter(|e| entry_types.contains(&e.entry_type.as_str())).map(value).sum()
+        };
+        let total_paid = sum(&["payment"], |e| e.credit);
+        let total_reversed = sum(&["cancellation", "refund"], |e| e.debit);
+
+        Ok(StudentBalance {
+            student_id: student_id.to_string(),
+            total_charged: sum(&["installment", "late_fee"], |e| e.debit),
+            total_paid,
+            total_reversed,
+            total_discounts: sum(&["discount"], |e| e.credit),
+            total_credited: ledger.iter().map(|e| e.credit).sum::<f64>() - total_paid,
+            balance: ledger.last().map(|e| e.balance).unwrap_or(0.0),
+            overdue: row.get("overdue"),
+            upcoming: row.get("upcoming"),
+            unallocated: (total_paid - total_reversed - allocated).max(0.0),
+        })
+    }
+
+    /// Cancels an unpaid late fee, keeping who waived it and why.
+    pub async fn waive_late_fee(&self, id: &str, reason: &str, waived_by: &str) -> Result<Due> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE dues SET status = 'waived', waived_by = ?, waived_at = ?, waive_reason = ?, updated_at = ?
+            WHERE id = ? AND kind = 'late_fee' AND status = 'unpaid'
+            "#,
+        )
+        .bind(waived_by)
+        .bind(Utc::now().to_rfc3339())
+        .bind(reason)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Due {} is not an unpaid late fee", id));
+        }
+
+        self.get_due(id).await?.ok_or_else(|| anyhow!("Due {} not found", id))
+    }
+
+    /// Reverses `amount` of a payment (everything still reversible for a
+    /// cancellation or when omitted) and takes back `sessions` (by default
+    /// the payment's sessions in proportion to the amount). Money not applied
+    /// to any due is taken back first, then the most recent dues reopen.
+    pub async fn reverse_payment(
+        &self,
+        payment_id: &str,
+        kind: &str,
+        amount: Option<f64>,
+        sessions: Option<i64>,
+        details: ReversalDetails<'_>,
+    ) -> Result<PaymentReversal> {
+        let mut tx = self.pool.begin().await?;
+
+        let payment = sqlx::query("SELECT student_id, amount, sessions, status FROM payments WHERE id = ?")
+            .bind(payment_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| anyhow!("Payment {} not found", payment_id))?;
+        let status: String = payment.get("status");
+        if status == "cancelled" {
+            return Err(anyhow!("Payment {} is already cancelled", payment_id));
+        }
+
+        let previous = sqlx::query("SELECT COALESCE(SUM(amount), 0.0) AS amount, COALESCE(SUM(sessions), 0) AS sessions FROM payment_reversals WHERE payment_id = ?")
+            .bind(payment_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let paid: f64 = payment.get("amount");
+        let paid_sessions: i64 = payment.get("sessions");
+        let reversible = paid - previous.get::<f64, _>("amount");
+        let reversible_sessions = paid_sessions - previous.get::<i64, _>("sessions");
+
+        let amount = match (kind, amount) {
+            ("cancellation", _) | (_, None) => reversible,
+            (_, Some(amount)) => amount,
+        };
+        if amount <= 0.0 || amount > reversible + billing::AMOUNT_EPSILON {
+            return Err(anyhow!("At most {:.2} of payment {} can be reversed", reversible, payment_id));
+        }
+        let sessions = match (kind, sessions) {
+            ("cancellation", _) => reversible_sessions,
+            (_, Some(sessions)) => sessions.clamp(0, reversible_sessions),
+            (_, None) if paid > 0.0 => ((paid_sessions as f64) * amount / paid).floor() as i64,
+            (_, None) => 0,
+        }
+        .min(reversible_sessions);
+
+        // Reopen dues, most recent first, for what the unapplied part does not cover
+        let allocated: f64 = sqlx::query_scalar("SELECT COALESCE(SUM(amount), 0.0) FROM payment_allocations WHERE payment_id = ?")
+            .bind(payment_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        let mut to_release = amount - (reversible - allocated).max(0.0);
+
+        let allocations = sqlx::query(
+            r#"
+            SELECT a.due_id, a.amount AS allocated, d.amount, d.paid_amount FROM payment_allocations a
+            JOIN dues d ON d.id = a.due_id
+            WHERE a.payment_id = ?
+            ORDER BY d.due_date DESC, d.created_at DESC
+            "#,
+        )
+        .bind(payment_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for row in allocations {
+            if to_release <= billing::AMOUNT_EPSILON {
+                break;
+            }
+            let due_id: String = row.get("due_id");
+            let released = to_release.min(row.get("allocated"));
+            let paid_amount = (row.get::<f64, _>("paid_amount") - released).max(0.0);
+
+            sqlx::query("UPDATE payment_allocations SET amount = amount - ? WHERE payment_id = ? AND due_id = ?")
+                .bind(released)
+                .bind(payment_id)
+                .bind(&due_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE dues SET paid_amount = ?, status = ?, updated_at = ? WHERE id = ?")
+                .bind(paid_amount)
+                .bind(billing::due_status(row.get("amount"), paid_amount))
+                .bind(Utc::now().to_rfc3339())
+                .bind(&due_id)
+                .execute(&mut *tx)
+                .await?;
+
+            to_release -= released;
+        }
+
+        let new_status = if kind == "cancellation" {
+            "cancelled"
+        } else if amount + billing::AMOUNT_EPSILON >= reversible {
+            "refunded"
+        } else {
+            status.as_str()
+        };
+        sqlx::query("UPDATE payments SET status = ?, updated_at = ? WHERE id = ?")
+            .bind(new_status)
+            .bind(Utc::now().to_rfc3339())
+            .bind(payment_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if sessions != 0 {
+            add_sessions(&mut tx, &payment.get::<String, _>("student_id"), -sessions).await?;
+        }
+
+        let sequence = next_sequence(&mut tx, "credit_note_sequences", details.academic_year).await?;
+        let reversal = PaymentReversal {
+            id: Uuid::new_v4().to_string(),
+            payment_id: payment_id.to_string(),
+            kind: kind.to_string(),
+            amount,
+            sessions,
+            refund_method: details.refund_method.map(|m| m.to_string()),
+            reason: details.reason.to_string(),
+            created_by: details.created_by.to_string(),
+            credit_note_number: receipt_number(&format!("{}AV-", details.prefix), details.academic_year, sequence),
+            created_at: Utc::now(),
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO payment_reversals (
+                id, payment_id, kind, amount, sessions, refund_method, reason, created_by,
+                credit_note_number, created_at, cash_session_id
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, (SELECT id FROM cash_sessions WHERE status = 'open'))
+            "#,
+        )
+        .bind(&reversal.id)
+        .bind(&reversal.payment_id)
+        .bind(&reversal.kind)
+        .bind(reversal.amount)
+        .bind(reversal.sessions)
+        .bind(&reversal.refund_method)
+        .bind(&reversal.reason)
+        .bind(&reversal.created_by)
+        .bind(&reversal.credit_note_number)
+        .bind(reversal.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(reversal)
+    }
+
+    /// Changes the description of a payment. Amounts go through refunds;
+    /// payments of a closed cash session cannot be changed at all.
+    pub async fn update_payment(&self, payment: Payment) -> Result<Payment> {
+        let locked: Option<bool> = sqlx::query_scalar(
+            r#"
+            SELECT c.status IS 'closed' FROM payments p LEFT JOIN cash_sessions c ON c.id = p.cash_session_id
+            WHERE p.id = ?
+            "#,
+        )
+        .bind(&payment.id)
+        .fetch_optional(&self.pool)
+        .await?;
+        match locked {
+            None => return Err(anyhow!("Payment {} not found", payment.id)),
+            Some(true) => return Err(anyhow!("Payment {} belongs to a closed cash session", payment.id)),
+            Some(false) => {}
+        }
+
+        sqlx::query("UPDATE payments SET payment_type = ?, payment_method = ?, due_date = ?, notes = ?, updated_at = ? WHERE id = ?")
+            .bind(&payment.payment_type)
+            .bind(&payment.payment_method)
+            .bind(payment.due_date.map(|d| d.to_rfc3339()))
+            .bind(&payment.notes)
+            .bind(Utc::now().to_rfc3339())
+            .bind(&payment.id)
+            .execute(&self.pool)
+            .await?;
+
+        self.get_payment(&payment.id).await?.ok_or_else(|| anyhow!("Payment {} not found", payment.id))
+    }
+
+    pub async fn create_expense(&self, expense: Expense) -> Result<Expense> {
+        let mut tx = self.pool.begin().await?;
+        insert_expense(&mut tx, &expense).await?;
+        tx.commit().await?;
+
+        Ok(expense)
+    }
+
+    pub async fn update_expense(&self, mut expense: Expense) -> Result<Expense> {
+        expense.updated_at = Utc::now();
+        let updated = sqlx::query(
+            r#"
+            UPDATE expenses SET category = ?, description = ?, amount = ?, expense_date = ?, payment_method = ?,
+                supplier = ?, receipt_path = ?, notes = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&expense.category)
+        .bind(&expense.description)
+        .bind(expense.amount)
+        .bind(expense.expense_date.to_rfc3339())
+        .bind(&expense.payment_method)
+        .bind(&expense.supplier)
+        .bind(&expense.receipt_path)
+        .bind(&expense.notes)
+        .bind(expense.updated_at.to_rfc3339())
+        .bind(&expense.id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Expense {} not found", expense.id));
+        }
+        Ok(expense)
+    }
+
+    /// Deletes an expense. Deleting a salary expense also undoes the salary
+    /// payment so the month can be paid again.
+    pub async fn delete_expense(&self, id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM payroll_payments WHERE expense_id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM expenses WHERE id = ?")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        if deleted == 0 {
+            return Err(anyhow!("Expense {} not found", id));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn get_expense(&self, id: &str) -> Result<Option<Expense>> {
+        let row = sqlx::query("SELECT * FROM expenses WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_expense).transpose()
+    }
+
+    /// Expenses matching every given filter, most recent first; `from` is
+    /// inclusive and `to` exclusive.
+    pub async fn get_expenses(
+        &self,
+        category: Option<&str>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Expense>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT * FROM expenses
+            WHERE (?1 IS NULL OR category = ?1)
+              AND (?2 IS NULL OR expense_date >= ?2)
+              AND (?3 IS NULL OR expense_date < ?3)
+            ORDER BY expense_date DESC
+            "#,
+        )
+        .bind(category)
+        .bind(from.map(|d| d.to_rfc3339()))
+        .bind(to.map(|d| d.to_rfc3339()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_expense).collect()
+    }
+
+    pub async fn open_cash_session(&self, session: CashSession) -> Result<CashSession> {
+        if let Some(open) = self.get_open_cash_session().await? {
+            return Err(anyhow!("A cash session opened by {} is already open", open.opened_by));
+        }
+
+        sqlx::query(
+            "INSERT INTO cash_sessions (id, opened_by, opened_at, opening_float, status, notes) VALUES (?, ?, ?, ?, 'open', ?)",
+        )
+        .bind(&session.id)
+        .bind(&session.opened_by)
+        .bind(session.opened_at.to_rfc3339())
+        .bind(session.opening_float)
+        .bind(&session.notes)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    pub async fn get_open_cash_session(&self) -> Result<Option<CashSession>> {
+        let row = sqlx::query("SELECT * FROM cash_sessions WHERE status = 'open'")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_cash_session).transpose()
+    }
+
+    pub async fn get_cash_sessions(&self) -> Result<Vec<CashSession>> {
+        let rows = sqlx::query("SELECT * FROM cash_sessions ORDER BY opened_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_cash_session).collect()
+    }
+
+    /// Closes the open session `id` with the amounts counted per payment
+    /// method, storing expected and counted totals for the Z-report.
+    pub async fn close_cash_session(
+        &self,
+        id: &str,
+        closed_by: &str,
+        counted: &HashMap<String, f64>,
+        notes: Option<&str>,
+    ) -> Result<ZReport> {
+        let mut tx = self.pool.begin().await?;
+
+        let opening_float: f64 = sqlx::query_scalar("SELECT opening_float FROM cash_sessions WHERE id = ? AND status = 'open'")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| anyhow!("Cash session {} is not open", id))?;
+
+        let (collected, refunded) = session_movements(&mut tx, id).await?;
+        for total in billing::session_totals(opening_float, &collected, &refunded, Some(counted)) {
+            sqlx::query(
+                r#"
+                INSERT INTO cash_session_totals (
+                    session_id, payment_method, payment_count, collected, refunded, expected, counted
+                ) VALUES (?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(id)
+            .bind(&total.payment_method)
+            .bind(total.payment_count)
+            .bind(total.collected)
+            .bind(total.refunded)
+            .bind(total.expected)
+            .bind(total.counted)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            "UPDATE cash_sessions SET status = 'closed', closed_by = ?, closed_at = ?, notes = COALESCE(?, notes) WHERE id = ?",
+        )
+        .bind(closed_by)
+        .bind(Utc::now().to_rfc3339())
+        .bind(notes)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.z_report(id).await
+    }
+
+    /// Summary of a session: the totals stored at closing, or the running
+    /// totals without counts while it is still open.
+    pub async fn z_report(&self, id: &str) -> Result<ZReport> {
+        let session = sqlx::query("SELECT * FROM cash_sessions WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(row_to_cash_session)
+            .transpose()?
+            .ok_or_else(|| anyhow!("Cash session {} not found", id))?;
+
+        let totals = if session.status == "closed" {
+            let rows = sqlx::query("SELECT * FROM cash_session_totals WHERE session_id = ? ORDER BY payment_method != 'cash', payment_method")
+                .bind(id)
+                .fetch_all(&self.pool)
+                .await?;
+            rows.iter()
+                .map(|row| {
+                    let expected: f64 = row.get("expected");
+                    let counted: Option<f64> = row.get("counted");
+                    CashSessionTotal {
+                        payment_method: row.get("payment_method"),
+                        payment_count: row.get("payment_count"),
+                        collected: row.get("collected"),
+                        refunded: row.get("refunded"),
+                        expected,
+                        counted,
+                        difference: counted.map(|c| c - expected),
+                    }
+                })
+                .collect()
+        } else {
+            let mut tx = self.pool.begin().await?;
+            let (collected, refunded) = session_movements(&mut tx, id).await?;
+            tx.commit().await?;
+            billing::session_totals(session.opening_float, &collected, &refunded, None)
+        };
+
+        let row = sqlx::query(
+            r#"
+            SELECT COUNT(*) AS payment_count, MIN(receipt_number) AS first_receipt, MAX(receipt_number) AS last_receipt,
+                (SELECT COUNT(*) FROM payment_reversals WHERE cash_session_id = ?1) AS reversal_count
+            FROM payments WHERE cash_session_id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ZReport {
+            session,
+            totals,
+            payment_count: row.get("payment_count"),
+            reversal_count: row.get("reversal_count"),
+            first_receipt: row.get("first_receipt"),
+            last_receipt: row.get("last_receipt"),
+        })
+    }
+
+    pub async fn get_payment_reversals(&self, payment_id: Option<&str>) -> Result<Vec<PaymentReversal>> {
+        let rows = sqlx::query("SELECT * FROM payment_reversals WHERE ?1 IS NULL OR payment_id = ?1 ORDER BY created_at DESC")
+            .bind(payment_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_reversal).collect()
+    }
+
+    pub async fn get_payment_reversal(&self, id: &str) -> Result<Option<PaymentReversal>> {
+        let row = sqlx::query("SELECT * FROM payment_reversals WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_reversal).transpose()
+    }
+
+    /// Lists, per academic year, the receipt numbers that were allocated but
+    /// are not carried by any payment.
+    pub async fn receipt_sequence_report(&self, academic_year: Option<&str>) -> Result<Vec<ReceiptSequenceReport>> {
+        let sequences = sqlx::query("SELECT academic_year, last_number FROM receipt_sequences ORDER BY academic_year")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut reports = Vec::new();
+        for row in sequences {
+            let year: String = row.get("academic_year");
+            if academic_year.is_some_and(|wanted| wanted != year) {
+                continue;
+            }
+            let last_number: i64 = row.get("last_number");
+
+            let numbers: Vec<(i64, Option<String>)> = sqlx::query_as(
+                "SELECT receipt_sequence, receipt_number FROM payments WHERE academic_year = ? AND receipt_sequence IS NOT NULL ORDER BY receipt_sequence",
+            )
+            .bind(&year)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let mut missing = Vec::new();
+            let mut expected = 1;
+            for (number, _) in &numbers {
+                missing.extend(expected..*number);
+                expected = number + 1;
+            }
+            missing.extend(expected..=last_number);
+
+            reports.push(ReceiptSequenceReport {
+                academic_year: year,
+                last_number,
+                issued: numbers.len() as i64,
+                missing,
+                first_receipt: numbers.first().and_then(|(_, r)| r.clone()),
+                last_receipt: numbers.last().and_then(|(_, r)| r.clone()),
+            });
+        }
+
+        Ok(reports)
+    }
+
+    /// Records attendance, for a course only if the student is enrolled in it.
+    pub async fn create_attendance(&self, attendance: Attendance) -> Result<Attendance> {
+        if let Some(group_id) = &attendance.group_id {
+            let enrolled: bool = sqlx::query_scalar(
+                "SELECT COUNT(*) > 0 FROM enrollments WHERE group_id = ? AND student_id = ? AND status = 'active'",
+            )
+            .bind(group_id)
+            .bind(&attendance.student_id)
+            .fetch_one(&self.pool)
+            .await?;
+            if !enrolled {
+                return Err(anyhow!("Student {} is not enrolled in group {}", attendance.student_id, group_id));
+            }
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO attendance (
+                id, student_id, date, status, check_in_time, check_out_time, notes, group_id, occurrence_id, created_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&attendance.id)
+        .bind(&attendance.student_id)
+        .bind(attendance.date.to_rfc3339())
+        .bind(&attendance.status)
+        .bind(attendance.check_in_time.map(|d| d.to_rfc3339()))
+        .bind(attendance.check_out_time.map(|d| d.to_rfc3339()))
+        .bind(&attendance.notes)
+        .bind(&attendance.group_id)
+        .bind(&attendance.occurrence_id)
+        .bind(attendance.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(attendance)
+    }
+
+    /// Scheduled sessions of the groups `student_id` is enrolled in that
+    /// end after `from` and start by `until`, plus `checked_in` even when
+    /// it has ended.
+    pub async fn get_student_sessions(
+        &self,
+        student_id: &str,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+        checked_in: Option<&str>,
+    ) -> Result<Vec<SessionOccurrence>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE o.status = 'scheduled' AND (o.ends_at > ?2 OR o.id = ?4) AND o.starts_at <= ?3
+              AND o.group_id IN (SELECT group_id FROM enrollments WHERE student_id = ?1 AND status = 'active')
+            ORDER BY o.starts_at
+            "#,
+            OCCURRENCE_SELECT,
+        ))
+        .bind(student_id)
+        .bind(from.to_rfc3339())
+        .bind(until.to_rfc3339())
+        .bind(checked_in)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_session_occurrence).collect()
+    }
+
+    /// The session `student_id` last checked in to since `since` and has
+    /// not checked out of.
+    pub async fn get_open_check_in(&self, student_id: &str, since: DateTime<Utc>) -> Result<Option<String>> {
+        let occurrence_id = sqlx::query_scalar(
+            r#"
+            SELECT occurrence_id FROM attendance
+            WHERE student_id = ? AND occurrence_id IS NOT NULL AND check_in_time >= ? AND check_out_time IS NULL
+            ORDER BY check_in_time DESC LIMIT 1
+            "#,
+        )
+        .bind(student_id)
+        .bind(since.to_rfc3339())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(occurrence_id)
+    }
+
+    /// Records a card scan for `occurrence`: the first scan checks the
+    /// student in as `status`, the next one checks them out.
+    pub async fn record_session_scan(
+        &self,
+        student_id: &str,
+        occurrence: &SessionOccurrence,
+        status: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Attendance> {
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query("SELECT * FROM attendance WHERE occurrence_id = ? AND student_id = ?")
+            .bind(&occurrence.id)
+            .bind(student_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .as_ref()
+            .map(row_to_attendance)
+            .transpose()?;
+
+        let attendance = match existing {
+            Some(mut attendance) => {
+                let rescan = attendance
+                    .check_in_time
+                    .is_some_and(|t| now - t < chrono::Duration::minutes(schedule::RESCAN_MINUTES));
+                if attendance.check_in_time.is_some() && attendance.check_out_time.is_none() && !rescan {
+                    sqlx::query("UPDATE attendance SET check_out_time = ? WHERE id = ?")
+                        .bind(now.to_rfc3339())
+                        .bind(&attendance.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    attendance.check_out_time = Some(now);
+                }
+                attendance
+            }
+            None => {
+                let attendance = Attendance {
+                    id: Uuid::new_v4().to_string(),
+                    student_id: student_id.to_string(),
+                    date: occurrence.starts_at,
+                    status: status.to_string(),
+                    check_in_time: Some(now),
+                    check_out_time: None,
+                    notes: None,
+                    group_id: Some(occurrence.group_id.clone()),
+                    occurrence_id: Some(occurrence.id.clone()),
+                    created_at: now,
+                };
+                sqlx::query(
+                    r#"
+                    INSERT INTO attendance (
+                        id, student_id, date, status, check_in_time, notes, group_id, occurrence_id, created_at
+                    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&attendance.id)
+                .bind(&attendance.student_id)
+                .bind(attendance.date.to_rfc3339())
+                .bind(&attendance.status)
+                .bind(now.to_rfc3339())
+                .bind(&attendance.notes)
+                .bind(&attendance.group_id)
+                .bind(&attendance.occurrence_id)
+                .bind(attendance.created_at.to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+                attendance
+            }
+        };
+
+        tx.commit().await?;
+        Ok(attendance)
+    }
+
+    /// Marks absent the students enrolled in sessions ended by `now` who
+    /// never scanned, once per session. Cancelled sessions are skipped.
+    pub async fn mark_absentees(&self, now: DateTime<Utc>) -> Result<Vec<Attendance>> {
+        let mut tx = self.pool.begin().await?;
+        let sessions = sqlx::query(
+            r#"
+            SELECT id, group_id, starts_at, ends_at FROM session_occurrences
+            WHERE status = 'scheduled' AND ends_at <= ? AND attendance_closed_at IS NULL
+            "#,
+        )
+        .bind(now.to_rfc3339())
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut absences = Vec::new();
+        for session in sessions {
+            let occurrence_id: String = session.get("id");
+            let group_id: String = session.get("group_id");
+            let starts_at: String = session.get("starts_at");
+
+            // Students enrolled while the session ran, even if they left the group since
+            let students: Vec<String> = sqlx::query_scalar(
+                r#"
+                SELECT DISTINCT e.student_id FROM enrollments e
+                WHERE e.group_id = ?1 AND e.enrolled_at < ?3
+                  AND (e.withdrawn_at IS NULL OR e.withdrawn_at > ?2)
+                  AND NOT EXISTS (
+                      SELECT 1 FROM attendance a WHERE a.occurrence_id = ?4 AND a.student_id = e.student_id
+                  )
+                "#,
+            )
+            .bind(&group_id)
+            .bind(&starts_at)
+            .bind(session.get::<String, _>("ends_at"))
+            .bind(&occurrence_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+            for student_id in students {
+                let attendance = Attendance {
+                    id: Uuid::new_v4().to_string(),
+                    student_id,
+                    date: DateTime::parse_from_rfc3339(&starts_at)?.with_timezone(&Utc),
+                    status: "absent".to_string(),
+                    check_in_time: None,
+                    check_out_time: None,
+                    notes: None,
+                    group_id: Some(group_id.clone()),
+                    occurrence_id: Some(occurrence_id.clone()),
+                    created_at: now,
+                };
+                sqlx::query(
+                    "INSERT INTO attendance (id, student_id, date, status, group_id, occurrence_id, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&attendance.id)
+                .bind(&attendance.student_id)
+                .bind(attendance.date.to_rfc3339())
+                .bind(&attendance.status)
+                .bind(&attendance.group_id)
+                .bind(&attendance.occurrence_id)
+                .bind(now.to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+                absences.push(attendance);
+            }
+
+            sqlx::query("UPDATE session_occurrences SET attendance_closed_at = ? WHERE id = ?")
+                .bind(now.to_rfc3339())
+                .bind(&occurrence_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(absences)
+    }
+
+    pub async fn get_session_attendance(&self, occurrence_id: &str) -> Result<Vec<Attendance>> {
+        let rows = sqlx::query("SELECT * FROM attendance WHERE occurrence_id = ? ORDER BY check_in_time")
+            .bind(occurrence_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_attendance).collect()
+    }
+
+    pub async fn create_teacher(&self, teacher: Teacher) -> Result<Teacher> {
+        sqlx::query(
+            r#"
+            INSERT INTO teachers (id, first_name, last_name, phone, email, is_active, notes, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&teacher.id)
+        .bind(&teacher.first_name)
+        .bind(&teacher.last_name)
+        .bind(&teacher.phone)
+        .bind(&teacher.email)
+        .bind(teacher.is_active)
+        .bind(&teacher.notes)
+        .bind(teacher.created_at.to_rfc3339())
+        .bind(teacher.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(teacher)
+    }
+
+    pub async fn update_teacher(&self, mut teacher: Teacher) -> Result<Teacher> {
+        teacher.updated_at = Utc::now();
+        let updated = sqlx::query(
+            "UPDATE teachers SET first_name = ?, last_name = ?, phone = ?, email = ?, is_active = ?, notes = ?, updated_at = ? WHERE id = ?",
+        )
+        .bind(&teacher.first_name)
+        .bind(&teacher.last_name)
+        .bind(&teacher.phone)
+        .bind(&teacher.email)
+        .bind(teacher.is_active)
+        .bind(&teacher.notes)
+        .bind(teacher.updated_at.to_rfc3339())
+        .bind(&teacher.id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Teacher {} not found", teacher.id));
+        }
+        Ok(teacher)
+    }
+
+    pub async fn get_teachers(&self, active_only: bool) -> Result<Vec<Teacher>> {
+        let rows = sqlx::query("SELECT * FROM teachers WHERE ? = 0 OR is_active = 1 ORDER BY last_name, first_name")
+            .bind(active_only)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_teacher).collect()
+    }
+
+    pub async fn get_teacher(&self, id: &str) -> Result<Option<Teacher>> {
+        let row = sqlx::query("SELECT * FROM teachers WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_teacher).transpose()
+    }
+
+    /// Deletes a teacher who never had a group; others should be deactivated.
+    pub async fn delete_teacher(&self, id: &str) -> Result<()> {
+        let groups: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM class_groups WHERE teacher_id = ?")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        if groups > 0 {
+            return Err(anyhow!("Teacher {} is assigned to {} group(s); deactivate them instead", id, groups));
+        }
+
+        let deleted = sqlx::query("DELETE FROM teachers WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(anyhow!("Teacher {} not found", id));
+        }
+        Ok(())
+    }
+
+    pub async fn create_subject(&self, subject: Subject) -> Result<Subject> {
+        sqlx::query("INSERT INTO subjects (id, name, code, description, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&subject.id)
+            .bind(&subject.name)
+            .bind(&subject.code)
+            .bind(&subject.description)
+            .bind(subject.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(subject)
+    }
+
+    pub async fn update_subject(&self, subject: Subject) -> Result<Subject> {
+        let updated = sqlx::query("UPDATE subjects SET name = ?, code = ?, description = ? WHERE id = ?")
+            .bind(&subject.name)
+            .bind(&subject.code)
+            .bind(&subject.description)
+            .bind(&subject.id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Subject {} not found", subject.id));
+        }
+        Ok(subject)
+    }
+
+    pub async fn get_subjects(&self) -> Result<Vec<Subject>> {
+        let rows = sqlx::query("SELECT * FROM subjects ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut subjects = Vec::new();
+        for row in rows {
+            subjects.push(Subject {
+                id: row.get("id"),
+                name: row.get("name"),
+                code: row.get("code"),
+                description: row.get("description"),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+            });
+        }
+        Ok(subjects)
+    }
+
+    pub async fn delete_subject(&self, id: &str) -> Result<()> {
+        let groups: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM class_groups WHERE subject_id = ?")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        if groups > 0 {
+            return Err(anyhow!("Subject {} is taught in {} group(s)", id, groups));
+        }
+
+        let deleted = sqlx::query("DELETE FROM subjects WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(anyhow!("Subject {} not found", id));
+        }
+        Ok(())
+    }
+
+    pub async fn create_class_group(&self, group: ClassGroup) -> Result<ClassGroup> {
+        sqlx::query(
+            r#"
+            INSERT INTO class_groups (
+                id, name, academic_level, subject_id, teacher_id, capacity, academic_year,
+                is_active, notes, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&group.id)
+        .bind(&group.name)
+        .bind(&group.academic_level)
+        .bind(&group.subject_id)
+        .bind(&group.teacher_id)
+        .bind(group.capacity)
+        .bind(&group.academic_year)
+        .bind(group.is_active)
+        .bind(&group.notes)
+        .bind(group.created_at.to_rfc3339())
+        .bind(group.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        self.get_class_group(&group.id).await?.ok_or_else(|| anyhow!("Group {} not found", group.id))
+    }
+
+    /// Saves a group; the capacity cannot go below the students enrolled.
+    pub async fn update_class_group(&self, group: ClassGroup) -> Result<ClassGroup> {
+        let enrolled: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM enrollments WHERE group_id = ? AND status = 'active'")
+            .bind(&group.id)
+            .fetch_one(&self.pool)
+            .await?;
+        if group.capacity < enrolled {
+            return Err(anyhow!("Group {} already has {} students enrolled", group.name, enrolled));
+        }
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE class_groups SET name = ?, academic_level = ?, subject_id = ?, teacher_id = ?, capacity = ?,
+                academic_year = ?, is_active = ?, notes = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&group.name)
+        .bind(&group.academic_level)
+        .bind(&group.subject_id)
+        .bind(&group.teacher_id)
+        .bind(group.capacity)
+        .bind(&group.academic_year)
+        .bind(group.is_active)
+        .bind(&group.notes)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&group.id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Group {} not found", group.id));
+        }
+        self.get_class_group(&group.id).await?.ok_or_else(|| anyhow!("Group {} not found", group.id))
+    }
+
+    pub async fn get_class_group(&self, id: &str) -> Result<Option<ClassGroup>> {
+        let row = sqlx::query(&format!("{} WHERE g.id = ?", GROUP_SELECT))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_class_group).transpose()
+    }
+
+    pub async fn get_class_groups(
+        &self,
+        academic_level: Option<&str>,
+        teacher_id: Option<&str>,
+        active_only: bool,
+    ) -> Result<Vec<ClassGroup>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE (?1 IS NULL OR g.academic_level = ?1)
+              AND (?2 IS NULL OR g.teacher_id = ?2)
+              AND (?3 = 0 OR g.is_active = 1)
+            ORDER BY g.academic_level, subject_name, g.name
+            "#,
+            GROUP_SELECT,
+        ))
+        .bind(academic_level)
+        .bind(teacher_id)
+        .bind(active_only)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_class_group).collect()
+    }
+
+    /// Deletes a group nobody was ever enrolled in or paid for.
+    pub async fn delete_class_group(&self, id: &str) -> Result<()> {
+        let used: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM enrollments WHERE group_id = ?1)
+                OR EXISTS (SELECT 1 FROM payments WHERE group_id = ?1)
+                OR EXISTS (SELECT 1 FROM attendance WHERE group_id = ?1)
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        if used {
+            return Err(anyhow!("Group {} has enrollments or records; deactivate it instead", id));
+        }
+
+        let deleted = sqlx::query("DELETE FROM class_groups WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(anyhow!("Group {} not found", id));
+        }
+        Ok(())
+    }
+
+    /// Enrolls a student in an active group that still has room.
+    pub async fn enroll_student(&self, enrollment: Enrollment) -> Result<Enrollment> {
+        let mut tx = self.pool.begin().await?;
+
+        let group = sqlx::query(
+            r#"
+            SELECT g.name, g.capacity, g.is_active,
+                (SELECT COUNT(*) FROM enrollments e WHERE e.group_id = g.id AND e.status = 'active') AS enrolled
+            FROM class_groups g WHERE g.id = ?
+            "#,
+        )
+        .bind(&enrollment.group_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow!("Group {} not found", enrollment.group_id))?;
+        let name: String = group.get("name");
+        if !group.get::<bool, _>("is_active") {
+            return Err(anyhow!("Group {} is not active", name));
+        }
+        if group.get::<i64, _>("enrolled") >= group.get::<i64, _>("capacity") {
+            return Err(anyhow!("Group {} is full", name));
+        }
+
+        // The smallest registered room the group meets in caps it too
+        let room = sqlx::query(
+            r#"
+            SELECT r.name, r.capacity FROM timetable_slots s
+            JOIN rooms r ON LOWER(TRIM(r.name)) = LOWER(TRIM(s.room))
+            WHERE s.group_id = ? AND s.is_active = 1 AND r.is_active = 1
+            ORDER BY r.capacity
+            LIMIT 1
+            "#,
+        )
+        .bind(&enrollment.group_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(room) = room {
+            let capacity: i64 = room.get("capacity");
+            if group.get::<i64, _>("enrolled") >= capacity {
+                return Err(anyhow!("{} holds only {} students", room.get::<String, _>("name"), capacity));
+            }
+        }
+
+        let already: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM enrollments WHERE group_id = ? AND student_id = ? AND status = 'active'",
+        )
+        .bind(&enrollment.group_id)
+        .bind(&enrollment.student_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if already {
+            return Err(anyhow!("The student is already enrolled in {}", name));
+        }
+
+        sqlx::query(
+            "INSERT INTO enrollments (id, group_id, student_id, status, enrolled_at, notes) VALUES (?, ?, ?, 'active', ?, ?)",
+        )
+        .bind(&enrollment.id)
+        .bind(&enrollment.group_id)
+        .bind(&enrollment.student_id)
+        .bind(enrollment.enrolled_at.to_rfc3339())
+        .bind(&enrollment.notes)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        self.get_enrollment(&enrollment.id).await?.ok_or_else(|| anyhow!("Enrollment {} not found", enrollment.id))
+    }
+
+    /// Ends an enrollment, keeping it for history.
+    pub async fn withdraw_enrollment(&self, id: &str) -> Result<Enrollment> {
+        let updated = sqlx::query("UPDATE enrollments SET status = 'withdrawn', withdrawn_at = ? WHERE id = ? AND status = 'active'")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Enrollment {} is not active", id));
+        }
+        self.get_enrollment(id).await?.ok_or_else(|| anyhow!("Enrollment {} not found", id))
+    }
+
+    pub async fn get_enrollment(&self, id: &str) -> Result<Option<Enrollment>> {
+        let row = sqlx::query(&format!("{} WHERE e.id = ?", ENROLLMENT_SELECT))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_enrollment).transpose()
+    }
+
+    pub async fn get_enrollments(
+        &self,
+        group_id: Option<&str>,
+        student_id: Option<&str>,
+        active_only: bool,
+    ) -> Result<Vec<Enrollment>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE (?1 IS NULL OR e.group_id = ?1)
+              AND (?2 IS NULL OR e.student_id = ?2)
+              AND (?3 = 0 OR e.status = 'active')
+            ORDER BY group_name, student_name
+            "#,
+            ENROLLMENT_SELECT,
+        ))
+        .bind(group_id)
+        .bind(student_id)
+        .bind(active_only)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_enrollment).collect()
+    }
+
+    pub async fn create_room(&self, room: Room) -> Result<Room> {
+        if self.get_room_by_name(&room.name).await?.is_some() {
+            return Err(anyhow!("A room named {} already exists", room.name));
+        }
+
+        sqlx::query(
+            "INSERT INTO rooms (id, name, capacity, is_active, notes, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&room.id)
+        .bind(&room.name)
+        .bind(room.capacity)
+        .bind(room.is_active)
+        .bind(&room.notes)
+        .bind(room.created_at.to_rfc3339())
+        .bind(room.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(room)
+    }
+
+    /// Saves a room. A new name is carried over to its timetable slots and
+    /// to sessions still to come.
+    pub async fn update_room(&self, mut room: Room) -> Result<Room> {
+        let previous = self
+            .get_rooms(false)
+            .await?
+            .into_iter()
+            .find(|r| r.id == room.id)
+            .ok_or_else(|| anyhow!("Room {} not found", room.id))?;
+        if let Some(other) = self.get_room_by_name(&room.name).await? {
+            if other.id != room.id {
+                return Err(anyhow!("A room named {} already exists", room.name));
+            }
+        }
+        room.updated_at = Utc::now();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE rooms SET name = ?, capacity = ?, is_active = ?, notes = ?, updated_at = ? WHERE id = ?")
+            .bind(&room.name)
+            .bind(room.capacity)
+            .bind(room.is_active)
+            .bind(&room.notes)
+            .bind(room.updated_at.to_rfc3339())
+            .bind(&room.id)
+            .execute(&mut *tx)
+            .await?;
+        if previous.name != room.name {
+            sqlx::query("UPDATE timetable_slots SET room = ?1 WHERE LOWER(TRIM(room)) = LOWER(TRIM(?2))")
+                .bind(&room.name)
+                .bind(&previous.name)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE session_occurrences SET room = ?1 WHERE LOWER(TRIM(room)) = LOWER(TRIM(?2)) AND starts_at > ?3")
+                .bind(&room.name)
+                .bind(&previous.name)
+                .bind(Utc::now().to_rfc3339())
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+
+        Ok(room)
+    }
+
+    pub async fn get_rooms(&self, active_only: bool) -> Result<Vec<Room>> {
+        let rows = sqlx::query("SELECT * FROM rooms WHERE ? = 0 OR is_active = 1 ORDER BY name")
+            .bind(active_only)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_room).collect()
+    }
+
+    pub async fn get_room_by_name(&self, name: &str) -> Result<Option<Room>> {
+        let row = sqlx::query("SELECT * FROM rooms WHERE LOWER(TRIM(name)) = LOWER(TRIM(?))")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_room).transpose()
+    }
+
+    /// Deletes a room no active timetable slot uses.
+    pub async fn delete_room(&self, id: &str) -> Result<()> {
+        let used: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM timetable_slots s JOIN rooms r ON LOWER(TRIM(r.name)) = LOWER(TRIM(s.room))
+            WHERE r.id = ? AND s.is_active = 1
+            "#,
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        if used > 0 {
+            return Err(anyhow!("The room is used by {} timetable slot(s)", used));
+        }
+
+        let deleted = sqlx::query("DELETE FROM rooms WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Err(anyhow!("Room {} not found", id));
+        }
+        Ok(())
+    }
+
+    /// Refuses to put a group in a registered room smaller than its
+    /// enrollment. Rooms that are not registered are not limited.
+    async fn ensure_room_fits(&self, group_id: &str, room: &str) -> Result<()> {
+        let Some(room) = self.get_room_by_name(room).await?.filter(|r| r.is_active) else {
+            return Ok(());
+        };
+        let enrolled: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM enrollments WHERE group_id = ? AND status = 'active'")
+            .bind(group_id)
+            .fetch_one(&self.pool)
+            .await?;
+        if enrolled > room.capacity {
+            return Err(anyhow!("{} holds only {} students, the group has {}", room.name, room.capacity, enrolled));
+        }
+        Ok(())
+    }
+
+    /// Students checked in since `since` who have not checked out, with the
+    /// room of the session they scanned for.
+    pub async fn get_occupants(&self, since: DateTime<Utc>) -> Result<Vec<Occupant>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT a.student_id, s.first_name || ' ' || s.last_name AS student_name, g.name AS group_name,
+                o.room, a.check_in_time
+            FROM attendance a
+            JOIN students s ON s.id = a.student_id
+            LEFT JOIN session_occurrences o ON o.id = a.occurrence_id
+            LEFT JOIN class_groups g ON g.id = a.group_id
+            WHERE a.check_in_time >= ? AND a.check_out_time IS NULL
+            ORDER BY o.room, student_name
+            "#,
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut occupants = Vec::new();
+        for row in rows {
+            occupants.push(Occupant {
+                student_id: row.get("student_id"),
+                student_name: row.get("student_name"),
+                group_name: row.get("group_name"),
+                room: row.get("room"),
+                check_in_time: DateTime::parse_from_rfc3339(&row.get::<String, _>("check_in_time"))?.with_timezone(&Utc),
+            });
+        }
+        Ok(occupants)
+    }
+
+    /// Active slots of active groups sharing the room or the teacher of
+    /// `slot` at an overlapping time of the same weekday.
+    pub async fn find_slot_conflicts(&self, slot: &TimetableSlot) -> Result<Vec<TimetableSlot>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE s.is_active = 1 AND g.is_active = 1 AND s.id != ?1 AND s.weekday = ?2
+              AND s.start_time < ?4 AND s.end_time > ?3
+              AND (LOWER(TRIM(s.room)) = LOWER(TRIM(?5)) OR s.teacher_id = ?6)
+              AND (s.ends_on IS NULL OR ?7 IS NULL OR s.ends_on >= ?7)
+              AND (s.starts_on IS NULL OR ?8 IS NULL OR s.starts_on <= ?8)
+            ORDER BY s.start_time
+            "#,
+            SLOT_SELECT,
+        ))
+        .bind(&slot.id)
+        .bind(slot.weekday)
+        .bind(&slot.start_time)
+        .bind(&slot.end_time)
+        .bind(&slot.room)
+        .bind(&slot.teacher_id)
+        .bind(slot.starts_on.map(|d| d.to_string()))
+        .bind(slot.ends_on.map(|d| d.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_timetable_slot).collect()
+    }
+
+    async fn ensure_slot_is_free(&self, slot: &TimetableSlot) -> Result<()> {
+        if !slot.is_active {
+            return Ok(());
+        }
+        if let Some(other) = self.find_slot_conflicts(slot).await?.first() {
+            return Err(anyhow!(
+                "{} {}-{} is already taken by {} in {}",
+                weekday_name(other.weekday),
+                other.start_time,
+                other.end_time,
+                other.group_name,
+                other.room
+            ));
+        }
+        Ok(())
+    }
+
+    pub async fn create_timetable_slot(&self, slot: TimetableSlot) -> Result<TimetableSlot> {
+        self.ensure_slot_is_free(&slot).await?;
+        self.ensure_room_fits(&slot.group_id, &slot.room).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO timetable_slots (
+                id, group_id, weekday, start_time, end_time, room, teacher_id, starts_on, ends_on,
+                is_active, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&slot.id)
+        .bind(&slot.group_id)
+        .bind(slot.weekday)
+        .bind(&slot.start_time)
+        .bind(&slot.end_time)
+        .bind(&slot.room)
+        .bind(&slot.teacher_id)
+        .bind(slot.starts_on.map(|d| d.to_string()))
+        .bind(slot.ends_on.map(|d| d.to_string()))
+        .bind(slot.is_active)
+        .bind(slot.created_at.to_rfc3339())
+        .bind(slot.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        self.get_timetable_slot(&slot.id).await?.ok_or_else(|| anyhow!("Timetable slot {} not found", slot.id))
+    }
+
+    /// Saves a slot. Sessions already generated keep their time; regenerate
+    /// or reschedule them to follow the change.
+    pub async fn update_timetable_slot(&self, slot: TimetableSlot) -> Result<TimetableSlot> {
+        self.ensure_slot_is_free(&slot).await?;
+        self.ensure_room_fits(&slot.group_id, &slot.room).await?;
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE timetable_slots SET group_id = ?, weekday = ?, start_time = ?, end_time = ?, room = ?,
+                teacher_id = ?, starts_on = ?, ends_on = ?, is_active = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(&slot.group_id)
+        .bind(slot.weekday)
+        .bind(&slot.start_time)
+        .bind(&slot.end_time)
+        .bind(&slot.room)
+        .bind(&slot.teacher_id)
+        .bind(slot.starts_on.map(|d| d.to_string()))
+        .bind(slot.ends_on.map(|d| d.to_string()))
+        .bind(slot.is_active)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&slot.id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Timetable slot {} not found", slot.id));
+        }
+        self.get_timetable_slot(&slot.id).await?.ok_or_else(|| anyhow!("Timetable slot {} not found", slot.id))
+    }
+
+    pub async fn get_timetable_slot(&self, id: &str) -> Result<Option<TimetableSlot>> {
+        let row = sqlx::query(&format!("{} WHERE s.id = ?", SLOT_SELECT))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_timetable_slot).transpose()
+    }
+
+    pub async fn get_timetable_slots(
+        &self,
+        group_id: Option<&str>,
+        teacher_id: Option<&str>,
+        active_only: bool,
+    ) -> Result<Vec<TimetableSlot>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE (?1 IS NULL OR s.group_id = ?1)
+              AND (?2 IS NULL OR s.teacher_id = ?2)
+              AND (?3 = 0 OR (s.is_active = 1 AND g.is_active = 1))
+            ORDER BY s.weekday, s.start_time, s.room
+            "#,
+            SLOT_SELECT,
+        ))
+        .bind(group_id)
+        .bind(teacher_id)
+        .bind(active_only)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_timetable_slot).collect()
+    }
+
+    /// Removes a slot and its sessions still to come. A slot with past
+    /// sessions is deactivated instead so their history is kept.
+    pub async fn delete_timetable_slot(&self, id: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM session_occurrences WHERE slot_id = ? AND starts_at > ?")
+            .bind(id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        let past: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM session_occurrences WHERE slot_id = ?")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let affected = if past > 0 {
+            sqlx::query("UPDATE timetable_slots SET is_active = 0, updated_at = ? WHERE id = ?")
+                .bind(Utc::now().to_rfc3339())
+                .bind(id)
+                .execute(&mut *tx)
+                .await?
+        } else {
+            sqlx::query("DELETE FROM timetable_slots WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?
+        }
+        .rows_affected();
+        if affected == 0 {
+            return Err(anyhow!("Timetable slot {} not found", id));
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Creates the sessions of the week starting on `monday` from the
+    /// timetable. Sessions already generated are left as they are, so a
+    /// week can be generated again after adding slots.
+    pub async fn generate_week_sessions(&self, monday: NaiveDate) -> Result<Vec<SessionOccurrence>> {
+        let slots = self.get_timetable_slots(None, None, true).await?;
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+        let mut created = 0;
+
+        for slot in &slots {
+            let Some((date, starts_at, ends_at)) = schedule::slot_occurrence(slot, monday)? else {
+                continue;
+            };
+            created += sqlx::query(
+                r#"
+                INSERT INTO session_occurrences (
+                    id, slot_id, group_id, teacher_id, room, occurrence_date, starts_at, ends_at,
+                    status, created_at, updated_at
+                ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'scheduled', ?, ?)
+                ON CONFLICT (slot_id, occurrence_date) WHERE slot_id IS NOT NULL DO NOTHING
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(&slot.id)
+            .bind(&slot.group_id)
+            .bind(&slot.teacher_id)
+            .bind(&slot.room)
+            .bind(date.to_string())
+            .bind(starts_at.to_rfc3339())
+            .bind(ends_at.to_rfc3339())
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+
+        tx.commit().await?;
+        log::info!(target: "schedule", "{} session(s) generated for the week of {}", created, monday);
+
+        let sunday = monday + chrono::Days::new(6);
+        self.get_session_occurrences(Some(monday), Some(sunday), None, None).await
+    }
+
+    /// Sessions starting between `from` and `to` (local days, inclusive).
+    pub async fn get_session_occurrences(
+        &self,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+        group_id: Option<&str>,
+        teacher_id: Option<&str>,
+    ) -> Result<Vec<SessionOccurrence>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE (?1 IS NULL OR o.starts_at >= ?1)
+              AND (?2 IS NULL OR o.starts_at < ?2)
+              AND (?3 IS NULL OR o.group_id = ?3)
+              AND (?4 IS NULL OR o.teacher_id = ?4)
+            ORDER BY o.starts_at, o.room
+            "#,
+            OCCURRENCE_SELECT,
+        ))
+        .bind(from.map(|d| billing::local_midnight(d).to_rfc3339()))
+        .bind(to.map(|d| billing::local_midnight(d + chrono::Days::new(1)).to_rfc3339()))
+        .bind(group_id)
+        .bind(teacher_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_session_occurrence).collect()
+    }
+
+    pub async fn get_session_occurrence(&self, id: &str) -> Result<Option<SessionOccurrence>> {
+        let row = sqlx::query(&format!("{} WHERE o.id = ?", OCCURRENCE_SELECT))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(row_to_session_occurrence).transpose()
+    }
+
+    /// Scheduled sessions other than `occurrence` using its room or teacher
+    /// while it runs.
+    pub async fn find_session_conflicts(&self, occurrence: &SessionOccurrence) -> Result<Vec<SessionOccurrence>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            {}
+            WHERE o.status = 'scheduled' AND o.id != ?1
+              AND o.starts_at < ?3 AND o.ends_at > ?2
+              AND (LOWER(TRIM(o.room)) = LOWER(TRIM(?4)) OR o.teacher_id = ?5)
+            ORDER BY o.starts_at
+            "#,
+            OCCURRENCE_SELECT,
+        ))
+        .bind(&occurrence.id)
+        .bind(occurrence.starts_at.to_rfc3339())
+        .bind(occurrence.ends_at.to_rfc3339())
+        .bind(&occurrence.room)
+        .bind(&occurrence.teacher_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(row_to_session_occurrence).collect()
+    }
+
+    async fn ensure_session_is_free(&self, occurrence: &SessionOccurrence) -> Result<()> {
+        if let Some(other) = self.find_session_conflicts(occurrence).await?.first() {
+            return Err(anyhow!(
+                "{} is already booked by {} from {} to {}",
+                other.room,
+                other.group_name,
+                other.starts_at.with_timezone(&chrono::Local).format("%d/%m/%Y %H:%M"),
+                other.ends_at.with_timezone(&chrono::Local).format("%H:%M")
+            ));
+        }
+        Ok(())
+    }
+
+    /// Adds a session outside the timetable, e.g. a make-up class.
+    pub async fn create_session_occurrence(&self, occurrence: SessionOccurrence) -> Result<SessionOccurrence> {
+        self.ensure_session_is_free(&occurrence).await?;
+        self.ensure_room_fits(&occurrence.group_id, &occurrence.room).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO session_occurrences (
+                id, slot_id, group_id, teacher_id, room, occurrence_date, starts_at, ends_at,
+                status, notes, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, 'scheduled', ?, ?, ?)
+            "#,
+        )
+        .bind(&occurrence.id)
+        .bind(&occurrence.slot_id)
+        .bind(&occurrence.group_id)
+        .bind(&occurrence.teacher_id)
+        .bind(&occurrence.room)
+        .bind(occurrence.occurrence_date.to_string())
+        .bind(occurrence.starts_at.to_rfc3339())
+        .bind(occurrence.ends_at.to_rfc3339())
+        .bind(&occurrence.notes)
+        .bind(occurrence.created_at.to_rfc3339())
+        .bind(occurrence.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        self.get_session_occurrence(&occurrence.id).await?.ok_or_else(|| anyhow!("Session {} not found", occurrence.id))
+    }
+
+    pub async fn cancel_session_occurrence(&self, id: &str, reason: Option<&str>) -> Result<SessionOccurrence> {
+        let updated = sqlx::query(
+            "UPDATE session_occurrences SET status = 'cancelled', cancel_reason = ?, updated_at = ? WHERE id = ? AND status = 'scheduled'",
+        )
+        .bind(reason)
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Session {} is not scheduled", id));
+        }
+        self.get_session_occurrence(id).await?.ok_or_else(|| anyhow!("Session {} not found", id))
+    }
+
+    /// Moves one session, keeping its first start time. The new time, room
+    /// and teacher must be free.
+    pub async fn reschedule_session_occurrence(
+        &self,
+        id: &str,
+        starts_at: DateTime<Utc>,
+        ends_at: DateTime<Utc>,
+        room: Option<String>,
+        teacher_id: Option<String>,
+    ) -> Result<SessionOccurrence> {
+        let mut occurrence = self.get_session_occurrence(id).await?.ok_or_else(|| anyhow!("Session {} not found", id))?;
+        if occurrence.status != "scheduled" {
+            return Err(anyhow!("Session {} is {}", id, occurrence.status));
+        }
+        if ends_at <= starts_at {
+            return Err(anyhow!("The session must end after it starts"));
+        }
+
+        let original_starts_at = occurrence.original_starts_at.unwrap_or(occurrence.starts_at);
+        occurrence.starts_at = starts_at;
+        occurrence.ends_at = ends_at;
+        if let Some(room) = room.filter(|r| !r.trim().is_empty()) {
+            occurrence.room = room;
+        }
+        if teacher_id.is_some() {
+            occurrence.teacher_id = teacher_id;
+        }
+        self.ensure_session_is_free(&occurrence).await?;
+        self.ensure_room_fits(&occurrence.group_id, &occurrence.room).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE session_occurrences SET starts_at = ?, ends_at = ?, room = ?, teacher_id = ?,
+                original_starts_at = ?, updated_at = ?
+            WHERE id = ?
+            "#,
+        )
+        .bind(starts_at.to_rfc3339())
+        .bind(ends_at.to_rfc3339())
+        .bind(&occurrence.room)
+        .bind(&occurrence.teacher_id)
+        .bind(original_starts_at.to_rfc3339())
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        self.get_session_occurrence(id).await?.ok_or_else(|| anyhow!("Session {} not found", id))
+    }
+
+    pub async fn create_payroll_rule(&self, rule: PayrollRule) -> Result<PayrollRule> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM payroll_rules WHERE teacher_id = ? AND COALESCE(group_id, '') = COALESCE(?, '')",
+        )
+        .bind(&rule.teacher_id)
+        .bind(&rule.group_id)
+        .fetch_one(&self.pool)
+        .await?;
+        if exists {
+            return Err(anyhow!("This teacher already has a payroll rule for this group"));
+        }
+
+        sqlx::query(
+            "INSERT INTO payroll_rules (id, teacher_id, group_id, mode, rate, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&rule.id)
+        .bind(&rule.teacher_id)
+        .bind(&rule.group_id)
+        .bind(&rule.mode)
+        .bind(rule.rate)
+        .bind(rule.created_at.to_rfc3339())
+        .bind(rule.updated_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    pub async fn update_payroll_rule(&self, mut rule: PayrollRule) -> Result<PayrollRule> {
+        rule.updated_at = Utc::now();
+        let updated = sqlx::query("UPDATE payroll_rules SET group_id = ?, mode = ?, rate = ?, updated_at = ? WHERE id = ?")
+            .bind(&rule.group_id)
+            .bind(&rule.mode)
+            .bind(rule.rate)
+            .bind(rule.updated_at.to_rfc3339())
+            .bind(&rule.id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if updated == 0 {
+            return Err(anyhow!("Payroll rule {} not found", rule.id));
+        }
+        Ok(rule)
+    }
+
+    pub async fn delete_payroll_rule(&self, id: &str) -> Result<()> {
+        let deleted = sqlx::query("DELETE FROM payroll_rules WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if deleted == 0 {
+            return Err(anyhow!("Payroll rule {} not found", id));
+        }
+        Ok(())
+    }
+
+    pub async fn get_payroll_rules(&self, teacher_id: Option<&str>) -> Result<Vec<PayrollRule>> {
+        let rows = sqlx::query("SELECT * FROM payroll_rules WHERE ?1 IS NULL OR teacher_id = ?1 ORDER BY teacher_id, group_id")
+            .bind(teacher_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut rules = Vec::new();
+        for row in rows {
+            rules.push(PayrollRule {
+                id: row.get("id"),
+                teacher_id: row.get("teacher_id"),
+                group_id: row.get("group_id"),
+                mode: row.get("mode"),
+                rate: row.get("rate"),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+                updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+            });
+        }
+        Ok(rules)
+    }
+
+    async fn ensure_payroll_open(&self, teacher_id: &str, month: &str) -> Result<()> {
+        if self.get_payroll_payment(teacher_id, month).await?.is_some() {
+            return Err(anyhow!("The {} salary is already paid", month));
+        }
+        Ok(())
+    }
+
+    pub async fn create_payroll_adjustment(&self, adjustment: PayrollAdjustment) -> Result<PayrollAdjustment> {
+        self.ensure_payroll_open(&adjustment.teacher_id, &adjustment.month).await?;
+
+        sqlx::query(
+            "INSERT INTO payroll_adjustments (id, teacher_id, month, label, amount, created_by, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&adjustment.id)
+        .bind(&adjustment.teacher_id)
+        .bind(&adjustment.month)
+        .bind(&adjustment.label)
+        .bind(adjustment.amount)
+        .bind(&adjustment.created_by)
+        .bind(adjustment.created_at.to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(adjustment)
+    }
+
+    pub async fn delete_payroll_adjustment(&self, id: &str) -> Result<()> {
+        let row = sqlx::query("SELECT teacher_id, month FROM payroll_adjustments WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| anyhow!("Payroll adjustment {} not found", id))?;
+        self.ensure_payroll_open(&row.get::<String, _>("teacher_id"), &row.get::<String, _>("month")).await?;
+
+        sqlx::query("DELETE FROM payroll_adjustments WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_payroll_adjustments(&self, teacher_id: &str, month: &str) -> Result<Vec<PayrollAdjustment>> {
+        let rows = sqlx::query("SELECT * FROM payroll_adjustments WHERE teacher_id = ? AND month = ? ORDER BY created_at")
+            .bind(teacher_id)
+            .bind(month)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut adjustments = Vec::new();
+        for row in rows {
+            adjustments.push(PayrollAdjustment {
+                id: row.get("id"),
+                teacher_id: row.get("teacher_id"),
+                month: row.get("month"),
+                label: row.get("label"),
+                amount: row.get("amount"),
+                created_by: row.get("created_by"),
+                created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+            });
+        }
+        Ok(adjustments)
+    }
+
+    /// Per group, the sessions `teacher_id` held between `from` and `to`
+    /// (ended by `now`) and their share of the group's collected revenue,
+    /// net of refunds made in the period. A group taught by several
+    /// teachers in the period splits its revenue by sessions held.
+    pub async fn get_teacher_activity(
+        &self,
+        teacher_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<payroll::GroupActivity>> {
+        let rows = sqlx::query(
+            r#"
+            WITH held AS (
+                SELECT group_id, COUNT(*) AS sessions FROM session_occurrences
+                WHERE teacher_id = ?1 AND status = 'scheduled' AND starts_at >= ?2 AND starts_at < ?3 AND ends_at <= ?4
+                GROUP BY group_id
+            ),
+            group_held AS (
+                SELECT group_id, COUNT(*) AS sessions FROM session_occurrences
+                WHERE group_id IN (SELECT group_id FROM held)
+                    AND status = 'scheduled' AND starts_at >= ?2 AND starts_at < ?3 AND ends_at <= ?4
+                GROUP BY group_id
+            ),
+            collected AS (
+                SELECT group_id, SUM(amount) AS amount FROM payments
+                WHERE group_id IN (SELECT group_id FROM held)
+                    AND status != 'unpaid' AND payment_date >= ?2 AND payment_date < ?3
+                GROUP BY group_id
+            ),
+            refunded AS (
+                SELECT p.group_id, SUM(r.amount) AS amount FROM payment_reversals r
+                JOIN payments p ON p.id = r.payment_id
+                WHERE p.group_id IN (SELECT group_id FROM held) AND r.created_at >= ?2 AND r.created_at < ?3
+                GROUP BY p.group_id
+            )
+            SELECT g.id, g.name, h.sessions,
+                (COALESCE(c.amount, 0.0) - COALESCE(r.amount, 0.0)) * h.sessions / gh.sessions AS revenue
+            FROM held h
+            JOIN group_held gh ON gh.group_id = h.group_id
+            JOIN class_groups g ON g.id = h.group_id
+            LEFT JOIN collected c ON c.group_id = h.group_id
+            LEFT JOIN refunded r ON r.group_id = h.group_id
+            ORDER BY g.name
+            "#,
+        )
+        .bind(teacher_id)
+        .bind(from.to_rfc3339())
+        .bind(to.to_rfc3339())
+        .bind(now.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| payroll::GroupActivity {
+                group_id: row.get("id"),
+                group_name: row.get("name"),
+                sessions: row.get("sessions"),
+                revenue: row.get("revenue"),
+            })
+            .collect())
+    }
+
+    pub async fn get_payroll_payment(&self, teacher_id: &str, month: &str) -> Result<Option<PayrollPayment>> {
+        let row = sqlx::query("SELECT * FROM payroll_payments WHERE teacher_id = ? AND month = ?")
+            .bind(teacher_id)
+            .bind(month)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| -> Result<PayrollPayment> {
+            Ok(PayrollPayment {
+                id: row.get("id"),
+                teacher_id: row.get("teacher_id"),
+                month: row.get("month"),
+                amount: row.get("amount"),
+                payment_method: row.get("payment_method"),
+                expense_id: row.get("expense_id"),
+                paid_by: row.get("paid_by"),
+                paid_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("paid_at"))?.with_timezone(&Utc),
+            })
+        })
+        .transpose()
+    }
+
+    /// Records a salary payment together with its expense.
+    pub async fn record_payroll_payment(&self, payment: PayrollPayment, expense: Expense) -> Result<PayrollPayment> {
+        self.ensure_payroll_open(&payment.teacher_id, &payment.month).await?;
+
+        let mut tx = self.pool.begin().await?;
+        insert_expense(&mut tx, &expense).await?;
+        sqlx::query(
+            r#"
+            INSERT INTO payroll_payments (id, teacher_id, month, amount, payment_method, expense_id, paid_by, paid_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&payment.id)
+        .bind(&payment.teacher_id)
+        .bind(&payment.month)
+        .bind(payment.amount)
+        .bind(&payment.payment_method)
+        .bind(&expense.id)
+        .bind(&payment.paid_by)
+        .bind(payment.paid_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(payment)
+    }
+
+    pub async fn get_year_rollovers(&self) -> Result<Vec<YearRollover>> {
+        let reports: Vec<String> = sqlx::query_scalar("SELECT report FROM year_rollovers ORDER BY created_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        reports.iter().map(|r| Ok(serde_json::from_str(r)?)).collect()
+    }
+
+    /// Closes `rollover.from_year` in one transaction: files its undated
+    /// payments and attendance under it, retires its class groups, applies
+    /// the promotions and resets session balances unless they carry over.
+    /// Fills in the counts of `rollover`, records it and saves `settings`,
+    /// which hold the new academic year.
+    pub async fn rollover_year(&self, mut rollover: YearRollover, settings: &AppSettings) -> Result<YearRollover> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now().to_rfc3339();
+
+        let done: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM year_rollovers WHERE from_year = ?")
+            .bind(&rollover.from_year)
+            .fetch_one(&mut *tx)
+            .await?;
+        if done > 0 {
+            return Err(anyhow!("The {} academic year is already closed", rollover.from_year));
+        }
+
+        sqlx::query("UPDATE payments SET academic_year = ? WHERE academic_year IS NULL")
+            .bind(&rollover.from_year)
+            .execute(&mut *tx)
+            .await?;
+        rollover.payments_archived = sqlx::query_scalar("SELECT COUNT(*) FROM payments WHERE academic_year = ?")
+            .bind(&rollover.from_year)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        rollover.attendance_archived = sqlx::query("UPDATE attendance SET academic_year = ? WHERE academic_year IS NULL")
+            .bind(&rollover.from_year)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() as i64;
+
+        // Groups of the closed year stop, along with their timetable
+        sqlx::query(
+            "DELETE FROM session_occurrences WHERE starts_at > ? AND group_id IN (SELECT id FROM class_groups WHERE academic_year = ?)",
+        )
+        .bind(&now)
+        .bind(&rollover.from_year)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE timetable_slots SET is_active = 0, updated_at = ? WHERE is_active = 1 AND group_id IN (SELECT id FROM class_groups WHERE academic_year = ?)",
+        )
+        .bind(&now)
+        .bind(&rollover.from_year)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE enrollments SET status = 'withdrawn', withdrawn_at = ? WHERE status = 'active' AND group_id IN (SELECT id FROM class_groups WHERE academic_year = ?)",
+        )
+        .bind(&now)
+        .bind(&rollover.from_year)
+        .execute(&mut *tx)
+        .await?;
+        rollover.groups_archived = sqlx::query("UPDATE class_groups SET is_active = 0, updated_at = ? WHERE is_active = 1 AND academic_year = ?")
+            .bind(&now)
+            .bind(&rollover.from_year)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() as i64;
+
+        for promotion in &rollover.promotions {
+            match promotion.outcome.as_str() {
+                "promote" => {
+                    sqlx::query("UPDATE students SET academic_level = ?, updated_at = ? WHERE id = ?")
+                        .bind(&promotion.to_level)
+                        .bind(&now)
+                        .bind(&promotion.student_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                "leave" | "graduate" => {
+                    sqlx::query("UPDATE students SET is_active = 0, updated_at = ? WHERE id = ?")
+                        .bind(&now)
+                        .bind(&promotion.student_id)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                _ => {}
+            }
+        }
+
+        // Students who left keep their balance until it is refunded
+        if rollover.session_policy == "reset" {
+            rollover.sessions_reset = sqlx::query(
+                "UPDATE students SET sessions_remaining = 0, updated_at = ? WHERE is_active = 1 AND sessions_remaining != 0",
+            )
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected() as i64;
+        }
+
+        sqlx::query(
+            "INSERT INTO year_rollovers (id, from_year, to_year, report, created_by, created_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&rollover.id)
+        .bind(&rollover.from_year)
+        .bind(&rollover.to_year)
+        .bind(serde_json::to_string(&rollover)?)
+        .bind(&rollover.created_by)
+        .bind(rollover.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        write_settings(&mut tx, settings).await?;
+
+        tx.commit().await?;
+        Ok(rollover)
+    }
+
+    /// Rewrites the stored report of a rollover, e.g. once the new year's
+    /// plans are created.
+    pub async fn update_year_rollover(&self, rollover: &YearRollover) -> Result<()> {
+        sqlx::query("UPDATE year_rollovers SET report = ? WHERE id = ?")
+            .bind(serde_json::to_string(rollover)?)
+            .bind(&rollover.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_attendance(&self) -> Result<Vec<Attendance>> {
+        let rows = sqlx::query("SELECT * FROM attendance ORDER BY date DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(row_to_attendance).collect()
+    }
+
+    pub async fn get_database_stats(&self) -> Result<DatabaseStats> {
+        let total_students: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM students")
+            .fetch_one(&self.pool)
+            .await?;
 
         let active_students: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM students WHERE is_active = 1")
             .fetch_one(&self.pool)
             .await?;
 
-        let total_payments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM payments")
+        let total_payments: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM payments")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let total_attendance: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attendance")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+            .fetch_one(&self.pool)
+            .await?;
+        let freelist_count: i64 = sqlx::query_scalar("PRAGMA freelist_count")
             .fetch_one(&self.pool)
             .await?;
 
-        let total_attendance: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM attendance")
+        let mut wal_path = DATABASE_FILE.to_string();
+        wal_path.push_str("-wal");
+        let wal_size = tokio::fs::metadata(&wal_path)
+            .await
+            .map(|m| m.len() as i64)
+            .unwrap_or(0);
+
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut table_counts = Vec::new();
+        for table in tables {
+            let row_count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")))
+                .fetch_one(&self.pool)
+                .await?;
+            table_counts.push(TableCount { table, row_count });
+        }
+
+        let last_backup_time = sqlx::query_scalar::<_, Option<String>>("SELECT MAX(created_at) FROM backup_history")
             .fetch_one(&self.pool)
+            .await?
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc));
+
+        // The full check reads every index; the dashboard only needs the quick one
+        let integrity_rows: Vec<String> = sqlx::query_scalar("PRAGMA quick_check")
+            .fetch_all(&self.pool)
             .await?;
+        let integrity_check = integrity_rows.join("\n");
+
+        let freelist_ratio = if page_count > 0 {
+            freelist_count as f64 / page_count as f64
+        } else {
+            0.0
+        };
 
         Ok(DatabaseStats {
             total_students,
             active_students,
             total_payments,
             total_attendance,
-            database_size: 0, // TODO: Calculate actual database size
+            database_size: page_count * page_size + wal_size,
+            wal_size,
+            table_counts,
+            last_backup_time,
+            maintenance_recommended: integrity_check != "ok" || freelist_ratio > FREELIST_MAINTENANCE_RATIO,
+            integrity_check,
+            freelist_ratio,
+        })
+    }
+
+    /// Full `PRAGMA integrity_check`, also verifying index contents. Slow on
+    /// a large database, so only run on request.
+    pub async fn integrity_check(&self) -> Result<String> {
+        let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.join("\n"))
+    }
+
+    /// Rebuilds the database file to reclaim free pages and refreshes the
+    /// query planner statistics.
+    pub async fn optimize(&self) -> Result<()> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        sqlx::query("ANALYZE").execute(&self.pool).await?;
+
+        Ok(())
+    }
+
+    pub async fn record_backup(&self, path: &str, size: i64) -> Result<()> {
+        sqlx::query("INSERT INTO backup_history (id, path, size, created_at) VALUES (?, ?, ?, ?)")
+            .bind(Uuid::new_v4().to_string())
+            .bind(path)
+            .bind(size)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn load_settings(&self) -> Result<Option<AppSettings>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = 'app'")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match value {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn save_settings(&self, settings: &AppSettings) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        write_settings(&mut tx, settings).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    pub async fn import_students(&self, rows: Vec<serde_json::Value>, strategy: ImportStrategy, dry_run: bool) -> Result<ImportReport> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = ImportReport::new("students", dry_run, rows.len());
+        let mut seen_names: HashMap<(String, String, String), usize> = HashMap::new();
+
+        let prepared = import::prepare_rows(
+            rows,
+            |row: StudentRow, result| {
+                let has_id = row.id.as_ref().is_some_and(|id| !id.is_empty());
+                let student = import::student_from_row(row, result);
+                let name_key = (student.first_name.to_lowercase(), student.last_name.to_lowercase(), import::phone_key(&student.parent_phone));
+                if let Some(first_row) = seen_names.insert(name_key, result.row) {
+                    result.duplicate = true;
+                    result.warnings.push(format!("Same name and parent phone as row {}", first_row));
+                }
+                (student, has_id)
+            },
+            |(student, _)| &student.id,
+        );
+
+        for (mut result, record) in prepared {
+            let Some((mut student, has_id)) = record else {
+                report.push(result);
+                continue;
+            };
+            if !result.errors.is_empty() {
+                report.push(result);
+                continue;
+            }
+
+            let namesake: Option<String> = sqlx::query_scalar(
+                r#"
+                SELECT id FROM students
+                WHERE id != ? AND lower(first_name) = lower(?) AND lower(last_name) = lower(?)
+                  AND substr(replace(replace(replace(replace(parent_phone, ' ', ''), '-', ''), '.', ''), '/', ''), -9) = ?
+                LIMIT 1
+                "#,
+            )
+            .bind(&student.id)
+            .bind(&student.first_name)
+            .bind(&student.last_name)
+            .bind(import::phone_key(&student.parent_phone))
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(existing_id) = namesake {
+                result.duplicate = true;
+                if has_id {
+                    result.warnings.push(format!("Same name and parent phone as existing student {}", existing_id));
+                } else {
+                    // Rows without an id are matched to the existing student,
+                    // so re-importing the same spreadsheet does not duplicate it.
+                    result.warnings.push(format!("Matched existing student {} by name and parent phone", existing_id));
+                    student.id = existing_id;
+                }
+            }
+            result.id = Some(student.id.clone());
+
+            let exists = record_exists(&mut tx, "students", &student.id).await?;
+            let outcome = match result.resolve(strategy, exists, "A student with this id already exists") {
+                RowAction::Skip => Ok(()),
+                RowAction::Update => {
+                    let previous_phone: String = sqlx::query_scalar("SELECT parent_phone FROM students WHERE id = ?")
+                        .bind(&student.id)
+                        .fetch_one(&mut *tx)
+                        .await?;
+                    let updated = sqlx::query(
+                        r#"
+                        UPDATE students SET
+                            first_name = ?, last_name = ?, email = ?, phone = ?, academic_level = ?,
+                            rfid_card = ?, parent_name = ?, parent_phone = ?, address = ?, birth_date = ?,
+                            enrollment_date = ?, is_active = ?, notes = ?, updated_at = ?
+                        WHERE id = ?
+                        "#,
+                    )
+                    .bind(&student.first_name)
+                    .bind(&student.last_name)
+                    .bind(&student.email)
+                    .bind(&student.phone)
+                    .bind(&student.academic_level)
+                    .bind(&student.rfid_card)
+                    .bind(&student.parent_name)
+                    .bind(&student.parent_phone)
+                    .bind(&student.address)
+                    .bind(&student.birth_date)
+                    .bind(student.enrollment_date.to_rfc3339())
+                    .bind(student.is_active)
+                    .bind(&student.notes)
+                    .bind(student.updated_at.to_rfc3339())
+                    .bind(&student.id)
+                    .execute(&mut *tx)
+                    .await;
+                    match updated {
+                        Ok(_) => relink_parent_guardian(&mut tx, &student.id, &previous_phone, &student.parent_name, &student.parent_phone).await,
+                        Err(e) => Err(e.into()),
+                    }
+                }
+                RowAction::Insert => {
+                    let inserted = sqlx::query(
+                        r#"
+                        INSERT INTO students (
+                            id, first_name, last_name, email, phone, academic_level, rfid_card,
+                            parent_name, parent_phone, address, birth_date, enrollment_date,
+                            is_active, notes, created_at, updated_at
+                        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#,
+                    )
+                    .bind(&student.id)
+                    .bind(&student.first_name)
+                    .bind(&student.last_name)
+                    .bind(&student.email)
+                    .bind(&student.phone)
+                    .bind(&student.academic_level)
+                    .bind(&student.rfid_card)
+                    .bind(&student.parent_name)
+                    .bind(&student.parent_phone)
+                    .bind(&student.address)
+                    .bind(&student.birth_date)
+                    .bind(student.enrollment_date.to_rfc3339())
+                    .bind(student.is_active)
+                    .bind(&student.notes)
+                    .bind(student.created_at.to_rfc3339())
+                    .bind(student.updated_at.to_rfc3339())
+                    .execute(&mut *tx)
+                    .await;
+                    match inserted {
+                        Ok(_) if !student.parent_phone.is_empty() => {
+                            link_parent_guardian(&mut tx, &student.id, &student.parent_name, &student.parent_phone).await
+                        }
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.into()),
+                    }
+                }
+            };
+            report.push_outcome(result, outcome);
+        }
+
+        report.finish(tx).await
+    }
+
+    /// New payments are recorded like ones taken at the desk, under the
+    /// next receipt number of `academic_year` and settling open dues, but
+    /// outside any cash register session. Payments already allocated to
+    /// dues keep their student and amount: those change through refunds.
+    pub async fn import_payments(
+        &self,
+        rows: Vec<serde_json::Value>,
+        strategy: ImportStrategy,
+        dry_run: bool,
+        academic_year: &str,
+        prefix: &str,
+    ) -> Result<ImportReport> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = ImportReport::new("payments", dry_run, rows.len());
+        let prepared = import::prepare_rows::<PaymentRow, _>(rows, import::payment_from_row, |p| &p.id);
+
+        for (mut result, record) in prepared {
+            let Some(mut payment) = record else {
+                report.push(result);
+                continue;
+            };
+            if !record_exists(&mut tx, "students", &payment.student_id).await? {
+                result.errors.push(format!("Unknown student: {}", payment.student_id));
+            }
+            if !result.errors.is_empty() {
+                report.push(result);
+                continue;
+            }
+
+            let stored = sqlx::query(
+                r#"
+                SELECT p.student_id, p.amount, c.status IS 'closed' AS locked,
+                    EXISTS (SELECT 1 FROM payment_allocations a WHERE a.payment_id = p.id)
+                    OR EXISTS (SELECT 1 FROM payment_reversals r WHERE r.payment_id = p.id) AS settled
+                FROM payments p LEFT JOIN cash_sessions c ON c.id = p.cash_session_id
+                WHERE p.id = ?
+                "#,
+            )
+            .bind(&payment.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let outcome = match result.resolve(strategy, stored.is_some(), "A payment with this id already exists") {
+                RowAction::Skip => Ok(()),
+                RowAction::Update => {
+                    let stored = stored.as_ref().expect("resolved as existing");
+                    let settled: bool = stored.get("settled");
+                    if stored.get::<bool, _>("locked") {
+                        result.skip("The payment belongs to a closed cash session and cannot be changed");
+                        Ok(())
+                    } else if settled
+                        && (stored.get::<String, _>("student_id") != payment.student_id
+                            || (stored.get::<f64, _>("amount") - payment.amount).abs() > billing::AMOUNT_EPSILON)
+                    {
+                        Err(anyhow!("The payment is already allocated to dues; its student and amount change through refunds"))
+                    } else {
+                        if let Some(receipt_number) = &payment.receipt_number {
+                            let taken: bool = sqlx::query_scalar("SELECT COUNT(*) > 0 FROM payments WHERE receipt_number = ? AND id != ?")
+                                .bind(receipt_number)
+                                .bind(&payment.id)
+                                .fetch_one(&mut *tx)
+                                .await?;
+                            if taken {
+                                result.duplicate = true;
+                                result.warnings.push(format!("Receipt number {} is already used by another payment", receipt_number));
+                            }
+                        }
+                        sqlx::query(
+                            r#"
+                            UPDATE payments SET
+                                student_id = ?, amount = ?, payment_type = ?, payment_method = ?, payment_date = ?,
+                                due_date = ?, notes = ?,
+                                -- the status of an allocated payment follows its dues
+                                status = CASE WHEN ? THEN status ELSE ? END,
+                                -- numbers allocated by the app are never rewritten
+                                receipt_number = CASE WHEN receipt_sequence IS NULL THEN ? ELSE receipt_number END,
+                                updated_at = ?
+                            WHERE id = ?
+                            "#,
+                        )
+                        .bind(&payment.student_id)
+                        .bind(payment.amount)
+                        .bind(&payment.payment_type)
+                        .bind(&payment.payment_method)
+                        .bind(payment.payment_date.to_rfc3339())
+                        .bind(payment.due_date.map(|d| d.to_rfc3339()))
+                        .bind(&payment.notes)
+                        .bind(settled)
+                        .bind(&payment.status)
+                        .bind(&payment.receipt_number)
+                        .bind(payment.updated_at.to_rfc3339())
+                        .bind(&payment.id)
+                        .execute(&mut *tx)
+                        .await
+                        .map(|_| ())
+                        .map_err(anyhow::Error::from)
+                    }
+                }
+                RowAction::Insert if payment.status == "unpaid" => {
+                    result.skip("Unpaid payments are not imported; record them as dues");
+                    Ok(())
+                }
+                RowAction::Insert => {
+                    if let Some(original) = payment.receipt_number.take() {
+                        result.warnings.push(format!("Receipt number {} is replaced by the next number of {}", original, academic_year));
+                        let note = format!("Reçu d'origine : {}", original);
+                        payment.notes = Some(match payment.notes.take() {
+                            Some(notes) => format!("{}\n{}", notes, note),
+                            None => note,
+                        });
+                    }
+                    insert_payment(&mut tx, payment, academic_year, prefix, None, true).await.map(|_| ())
+                }
+            };
+            report.push_outcome(result, outcome);
+        }
+
+        report.finish(tx).await
+    }
+
+    pub async fn import_attendance(&self, rows: Vec<serde_json::Value>, strategy: ImportStrategy, dry_run: bool) -> Result<ImportReport> {
+        let mut tx = self.pool.begin().await?;
+        let mut report = ImportReport::new("attendance", dry_run, rows.len());
+        let prepared = import::prepare_rows::<AttendanceRow, _>(rows, import::attendance_from_row, |a| &a.id);
+
+        for (mut result, record) in prepared {
+            let Some(attendance) = record else {
+                report.push(result);
+                continue;
+            };
+            if !record_exists(&mut tx, "students", &attendance.student_id).await? {
+                result.errors.push(format!("Unknown student: {}", attendance.student_id));
+            }
+            if !result.errors.is_empty() {
+                report.push(result);
+                continue;
+            }
+
+            let same_day: bool = sqlx::query_scalar(
+                "SELECT COUNT(*) > 0 FROM attendance WHERE student_id = ? AND substr(date, 1, 10) = ? AND id != ?",
+            )
+            .bind(&attendance.student_id)
+            .bind(attendance.date.format("%Y-%m-%d").to_string())
+            .bind(&attendance.id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if same_day {
+                result.duplicate = true;
+                result.warnings.push("The student already has an attendance record on this day".to_string());
+            }
+
+            let exists = record_exists(&mut tx, "attendance", &attendance.id).await?;
+            let outcome = match result.resolve(strategy, exists, "An attendance record with this id already exists") {
+                RowAction::Skip => Ok(()),
+                RowAction::Update => sqlx::query(
+                    r#"
+                    UPDATE attendance SET
+                        student_id = ?, date = ?, status = ?, check_in_time = ?, check_out_time = ?, notes = ?
+                    WHERE id = ?
+                    "#,
+                )
+                .bind(&attendance.student_id)
+                .bind(attendance.date.to_rfc3339())
+                .bind(&attendance.status)
+                .bind(attendance.check_in_time.map(|d| d.to_rfc3339()))
+                .bind(attendance.check_out_time.map(|d| d.to_rfc3339()))
+                .bind(&attendance.notes)
+                .bind(&attendance.id)
+                .execute(&mut *tx)
+                .await
+                .map(|_| ()),
+                RowAction::Insert => sqlx::query(
+                    r#"
+                    INSERT INTO attendance (
+                        id, student_id, date, status, check_in_time, check_out_time, notes, created_at
+                    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(&attendance.id)
+                .bind(&attendance.student_id)
+                .bind(attendance.date.to_rfc3339())
+                .bind(&attendance.status)
+                .bind(attendance.check_in_time.map(|d| d.to_rfc3339()))
+                .bind(attendance.check_out_time.map(|d| d.to_rfc3339()))
+                .bind(&attendance.notes)
+                .bind(attendance.created_at.to_rfc3339())
+                .execute(&mut *tx)
+                .await
+                .map(|_| ()),
+            };
+            report.push_outcome(result, outcome.map_err(anyhow::Error::from));
+        }
+
+        report.finish(tx).await
+    }
+}
+
+/// Dues with their student and latest payment, completed by a WHERE clause.
+const DUE_SELECT: &str = r#"
+    SELECT d.*, s.first_name || ' ' || s.last_name AS student_name, s.academic_level,
+        (SELECT COALESCE(SUM(x.amount), 0.0) FROM due_discounts x WHERE x.due_id = d.id) AS discount_amount,
+        (SELECT p.payment_date FROM payment_allocations a JOIN payments p ON p.id = a.payment_id
+         WHERE a.due_id = d.id ORDER BY p.payment_date DESC LIMIT 1) AS last_payment_date,
+        (SELECT p.payment_method FROM payment_allocations a JOIN payments p ON p.id = a.payment_id
+         WHERE a.due_id = d.id ORDER BY p.payment_date DESC LIMIT 1) AS last_payment_method
+    FROM dues d
+    JOIN students s ON s.id = d.student_id
+"#;
+
+const SLOT_SELECT: &str = r#"
+    SELECT s.*, g.name AS group_name, t.first_name || ' ' || t.last_name AS teacher_name
+    FROM timetable_slots s
+    JOIN class_groups g ON g.id = s.group_id
+    LEFT JOIN teachers t ON t.id = s.teacher_id
+"#;
+
+const OCCURRENCE_SELECT: &str = r#"
+    SELECT o.*, g.name AS group_name, t.first_name || ' ' || t.last_name AS teacher_name
+    FROM session_occurrences o
+    JOIN class_groups g ON g.id = o.group_id
+    LEFT JOIN teachers t ON t.id = o.teacher_id
+"#;
+
+/// Class groups with their subject, teacher and head count, completed by a WHERE clause.
+const GROUP_SELECT: &str = r#"
+    SELECT g.*, sub.name AS subject_name, t.first_name || ' ' || t.last_name AS teacher_name,
+        (SELECT COUNT(*) FROM enrollments e WHERE e.group_id = g.id AND e.status = 'active') AS enrolled_count
+    FROM class_groups g
+    JOIN subjects sub ON sub.id = g.subject_id
+    LEFT JOIN teachers t ON t.id = g.teacher_id
+"#;
+
+const ENROLLMENT_SELECT: &str = r#"
+    SELECT e.*, s.first_name || ' ' || s.last_name AS student_name, g.name AS group_name
+    FROM enrollments e
+    JOIN students s ON s.id = e.student_id
+    JOIN class_groups g ON g.id = e.group_id
+"#;
+
+/// Stores a payment under the next receipt number of `academic_year`.
+/// The counter is bumped in the same transaction as the insert, so a
+/// number is only consumed by a stored payment and is never handed out
+/// twice. The amount settles the student's outstanding dues oldest first,
+/// starting with `due_id` when given; any excess stays as credit.
+async fn insert_payment(
+    tx: &mut Transaction<'_, Sqlite>,
+    mut payment: Payment,
+    academic_year: &str,
+    prefix: &str,
+    due_id: Option<&str>,
+    allow_partial: bool,
+) -> Result<Payment> {
+    if let Some(due_id) = due_id {
+        let owner: Option<String> = sqlx::query_scalar("SELECT student_id FROM dues WHERE id = ?")
+            .bind(due_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+        match owner {
+            None => return Err(anyhow!("Due {} not found", due_id)),
+            Some(owner) if owner != payment.student_id => {
+                return Err(anyhow!("Due {} belongs to another student", due_id));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let rows = sqlx::query(
+        r#"
+        SELECT id, month, due_date, amount, paid_amount FROM dues
+        WHERE student_id = ? AND status IN ('unpaid', 'partial')
+        ORDER BY id = ? DESC, due_date, created_at
+        "#,
+    )
+    .bind(&payment.student_id)
+    .bind(due_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let mut outstanding = Vec::new();
+    for row in &rows {
+        outstanding.push(billing::OpenDue {
+            id: row.get("id"),
+            month: row.get("month"),
+            due_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("due_date"))?.with_timezone(&Utc),
+            amount: row.get("amount"),
+            paid_amount: row.get("paid_amount"),
+        });
+    }
+    let allocations = billing::allocate(payment.amount, &outstanding, allow_partial)?;
+
+    payment.status = if allocations.iter().any(|a| a.status == "partial") { "partial" } else { "paid" }.to_string();
+    if payment.due_date.is_none() {
+        payment.due_date = allocations.first().map(|a| a.due.due_date);
+    }
+
+    let sequence = next_sequence(tx, "receipt_sequences", academic_year).await?;
+    payment.receipt_number = Some(receipt_number(prefix, academic_year, sequence));
+
+    sqlx::query(
+        r#"
+        INSERT INTO payments (
+            id, student_id, amount, payment_type, payment_method, payment_date,
+            due_date, status, notes, receipt_number, cashier, academic_year,
+            receipt_sequence, sessions, cash_session_id, group_id, created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&payment.id)
+    .bind(&payment.student_id)
+    .bind(payment.amount)
+    .bind(&payment.payment_type)
+    .bind(&payment.payment_method)
+    .bind(payment.payment_date.to_rfc3339())
+    .bind(payment.due_date.map(|d| d.to_rfc3339()))
+    .bind(&payment.status)
+    .bind(&payment.notes)
+    .bind(&payment.receipt_number)
+    .bind(&payment.cashier)
+    .bind(academic_year)
+    .bind(sequence)
+    .bind(payment.sessions)
+    .bind(&payment.cash_session_id)
+    .bind(&payment.group_id)
+    .bind(payment.created_at.to_rfc3339())
+    .bind(payment.updated_at.to_rfc3339())
+    .execute(&mut **tx)
+    .await?;
+
+    for allocation in &allocations {
+        apply_to_due(tx, &payment.id, allocation).await?;
+    }
+
+    if payment.sessions != 0 {
+        add_sessions(tx, &payment.student_id, payment.sessions).await?;
+    }
+
+    Ok(payment)
+}
+
+/// Records the share of a payment applied to a due and stores the due's
+/// new paid amount and status.
+async fn apply_to_due(tx: &mut Transaction<'_, Sqlite>, payment_id: &str, allocation: &billing::Allocation<'_>) -> Result<()> {
+    sqlx::query("INSERT INTO payment_allocations (payment_id, due_id, amount) VALUES (?, ?, ?)")
+        .bind(payment_id)
+        .bind(&allocation.due.id)
+        .bind(allocation.amount)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query("UPDATE dues SET paid_amount = paid_amount + ?, status = ?, updated_at = ? WHERE id = ?")
+        .bind(allocation.amount)
+        .bind(allocation.status)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&allocation.due.id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn record_exists(tx: &mut Transaction<'_, Sqlite>, table: &str, id: &str) -> Result<bool> {
+    let exists = sqlx::query_scalar(&format!("SELECT COUNT(*) > 0 FROM {} WHERE id = ?", table))
+        .bind(id)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok(exists)
+}
+
+/// Bumps and returns the counter of `academic_year` in a sequence table.
+async fn next_sequence(tx: &mut Transaction<'_, Sqlite>, table: &str, academic_year: &str) -> Result<i64> {
+    let sequence = sqlx::query_scalar(&format!(
+        r#"
+        INSERT INTO {} (academic_year, last_number) VALUES (?, 1)
+        ON CONFLICT (academic_year) DO UPDATE SET last_number = last_number + 1
+        RETURNING last_number
+        "#,
+        table
+    ))
+    .bind(academic_year)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(sequence)
+}
+
+/// Links `student_id` to the guardian answering on `parent_phone`,
+/// creating one from `parent_name` when none does, as primary guardian.
+async fn link_parent_guardian(tx: &mut Transaction<'_, Sqlite>, student_id: &str, parent_name: &str, parent_phone: &str) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+    let key = import::phone_key(parent_phone);
+
+    let existing: Option<String> = if key.is_empty() {
+        None
+    } else {
+        sqlx::query_scalar("SELECT id FROM guardians WHERE phone_key = ? ORDER BY created_at LIMIT 1")
+            .bind(&key)
+            .fetch_optional(&mut **tx)
+            .await?
+    };
+
+    let guardian_id = match existing {
+        Some(id) => id,
+        None => {
+            let id = Uuid::new_v4().to_string();
+            let (first_name, last_name) = parent_name.trim().split_once(' ').unwrap_or((parent_name.trim(), ""));
+            sqlx::query(
+                r#"
+                INSERT INTO guardians (id, first_name, last_name, phone, phone_key, preferred_language, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, 'fr', ?, ?)
+                "#,
+            )
+            .bind(&id)
+            .bind(first_name)
+            .bind(last_name.trim())
+            .bind(parent_phone)
+            .bind(&key)
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut **tx)
+            .await?;
+            id
+        }
+    };
+
+    sqlx::query("UPDATE student_guardians SET is_primary = 0 WHERE student_id = ? AND guardian_id != ?")
+        .bind(student_id)
+        .bind(&guardian_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO student_guardians (guardian_id, student_id, relationship, is_primary, can_pick_up, created_at)
+        VALUES (?, ?, 'other', 1, 1, ?)
+        ON CONFLICT (guardian_id, student_id) DO UPDATE SET is_primary = 1
+        "#,
+    )
+    .bind(&guardian_id)
+    .bind(student_id)
+    .bind(&now)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Moves a student whose parent phone changed from `previous_phone` over
+/// to the guardian answering on the new one.
+async fn relink_parent_guardian(tx: &mut Transaction<'_, Sqlite>, student_id: &str, previous_phone: &str, parent_name: &str, parent_phone: &str) -> Result<()> {
+    let previous_key = import::phone_key(previous_phone);
+    if previous_key == import::phone_key(parent_phone) {
+        return Ok(());
+    }
+
+    if !previous_key.is_empty() {
+        sqlx::query("DELETE FROM student_guardians WHERE student_id = ? AND guardian_id IN (SELECT id FROM guardians WHERE phone_key = ?)")
+            .bind(student_id)
+            .bind(&previous_key)
+            .execute(&mut **tx)
+            .await?;
+    }
+    if !parent_phone.is_empty() {
+        link_parent_guardian(tx, student_id, parent_name, parent_phone).await?;
+    }
+    Ok(())
+}
+
+async fn write_settings(tx: &mut Transaction<'_, Sqlite>, settings: &AppSettings) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO settings (key, value, updated_at) VALUES ('app', ?, ?)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+        "#,
+    )
+    .bind(serde_json::to_string(settings)?)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn add_sessions(tx: &mut Transaction<'_, Sqlite>, student_id: &str, sessions: i64) -> Result<()> {
+    sqlx::query("UPDATE students SET sessions_remaining = sessions_remaining + ?, updated_at = ? WHERE id = ?")
+        .bind(sessions)
+        .bind(Utc::now().to_rfc3339())
+        .bind(student_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+/// `2023-2024` with prefix `REC-` and sequence 123 gives `REC-2023-000123`.
+pub fn receipt_number(prefix: &str, academic_year: &str, sequence: i64) -> String {
+    let year = academic_year.split(['-', '/']).next().unwrap_or(academic_year).trim();
+    format!("{}{}-{:06}", prefix, year, sequence)
+}
+
+fn row_to_student(row: &SqliteRow) -> Result<Student> {
+    Ok(Student {
+        id: row.get("id"),
+        first_name: row.get("first_name"),
+        last_name: row.get("last_name"),
+        email: row.get("email"),
+        phone: row.get("phone"),
+        academic_level: row.get("academic_level"),
+        rfid_card: row.get("rfid_card"),
+        parent_name: row.get("parent_name"),
+        parent_phone: row.get("parent_phone"),
+        address: row.get("address"),
+        birth_date: row.get("birth_date"),
+        enrollment_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("enrollment_date"))?.with_timezone(&Utc),
+        is_active: row.get("is_active"),
+        notes: row.get("notes"),
+        sessions_remaining: row.get("sessions_remaining"),
+        photo_id: row.get("photo_id"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_payment(row: &SqliteRow) -> Result<Payment> {
+    Ok(Payment {
+        id: row.get("id"),
+        student_id: row.get("student_id"),
+        amount: row.get("amount"),
+        payment_type: row.get("payment_type"),
+        payment_method: row.get("payment_method"),
+        payment_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("payment_date"))?.with_timezone(&Utc),
+        due_date: row.get::<Option<String>, _>("due_date")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        status: row.get("status"),
+        notes: row.get("notes"),
+        receipt_number: row.get("receipt_number"),
+        cashier: row.get("cashier"),
+        sessions: row.get("sessions"),
+        cash_session_id: row.get("cash_session_id"),
+        group_id: row.get("group_id"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_due(row: &SqliteRow) -> Result<Due> {
+    Ok(Due {
+        id: row.get("id"),
+        plan_id: row.get("plan_id"),
+        kind: row.get("kind"),
+        parent_due_id: row.get("parent_due_id"),
+        student_id: row.get("student_id"),
+        student_name: row.get("student_name"),
+        academic_level: row.get("academic_level"),
+        month: row.get("month"),
+        due_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("due_date"))?.with_timezone(&Utc),
+        amount: row.get("amount"),
+        discount_amount: row.get("discount_amount"),
+        paid_amount: row.get("paid_amount"),
+        status: row.get("status"),
+        last_payment_date: row.get::<Option<String>, _>("last_payment_date")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        last_payment_method: row.get("last_payment_method"),
+        waived_by: row.get("waived_by"),
+        waived_at: row.get::<Option<String>, _>("waived_at")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        waive_reason: row.get("waive_reason"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+/// Payments (method, count, amount) and refunds (method, amount) of a cash
+/// session. Refunds without a method give money back the way it came.
+async fn session_movements(tx: &mut Transaction<'_, Sqlite>, session_id: &str) -> Result<(Vec<(String, i64, f64)>, Vec<(String, f64)>)> {
+    let collected = sqlx::query(
+        "SELECT payment_method, COUNT(*) AS count, SUM(amount) AS amount FROM payments WHERE cash_session_id = ? GROUP BY payment_method",
+    )
+    .bind(session_id)
+    .fetch_all(&mut **tx)
+    .await?
+    .iter()
+    .map(|row| (row.get("payment_method"), row.get("count"), row.get("amount")))
+    .collect();
+
+    let refunded = sqlx::query(
+        r#"
+        SELECT COALESCE(r.refund_method, p.payment_method) AS method, SUM(r.amount) AS amount
+        FROM payment_reversals r JOIN payments p ON p.id = r.payment_id
+        WHERE r.cash_session_id = ?
+        GROUP BY method
+        "#,
+    )
+    .bind(session_id)
+    .fetch_all(&mut **tx)
+    .await?
+    .iter()
+    .map(|row| (row.get("method"), row.get("amount")))
+    .collect();
+
+    Ok((collected, refunded))
+}
+
+/// Inserts an expense as part of a larger transaction.
+async fn insert_expense(tx: &mut Transaction<'_, Sqlite>, expense: &Expense) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO expenses (
+            id, category, description, amount, expense_date, payment_method, supplier,
+            receipt_path, notes, created_by, created_at, updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&expense.id)
+    .bind(&expense.category)
+    .bind(&expense.description)
+    .bind(expense.amount)
+    .bind(expense.expense_date.to_rfc3339())
+    .bind(&expense.payment_method)
+    .bind(&expense.supplier)
+    .bind(&expense.receipt_path)
+    .bind(&expense.notes)
+    .bind(&expense.created_by)
+    .bind(expense.created_at.to_rfc3339())
+    .bind(expense.updated_at.to_rfc3339())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn row_to_expense(row: &SqliteRow) -> Result<Expense> {
+    Ok(Expense {
+        id: row.get("id"),
+        category: row.get("category"),
+        description: row.get("description"),
+        amount: row.get("amount"),
+        expense_date: DateTime::parse_from_rfc3339(&row.get::<String, _>("expense_date"))?.with_timezone(&Utc),
+        payment_method: row.get("payment_method"),
+        supplier: row.get("supplier"),
+        receipt_path: row.get("receipt_path"),
+        notes: row.get("notes"),
+        created_by: row.get("created_by"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_attendance(row: &SqliteRow) -> Result<Attendance> {
+    Ok(Attendance {
+        id: row.get("id"),
+        student_id: row.get("student_id"),
+        date: DateTime::parse_from_rfc3339(&row.get::<String, _>("date"))?.with_timezone(&Utc),
+        status: row.get("status"),
+        check_in_time: row.get::<Option<String>, _>("check_in_time")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        check_out_time: row.get::<Option<String>, _>("check_out_time")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        notes: row.get("notes"),
+        group_id: row.get("group_id"),
+        occurrence_id: row.get("occurrence_id"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_teacher(row: &SqliteRow) -> Result<Teacher> {
+    Ok(Teacher {
+        id: row.get("id"),
+        first_name: row.get("first_name"),
+        last_name: row.get("last_name"),
+        phone: row.get("phone"),
+        email: row.get("email"),
+        is_active: row.get("is_active"),
+        notes: row.get("notes"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_attachment(row: &SqliteRow) -> Result<Attachment> {
+    Ok(Attachment {
+        id: row.get("id"),
+        student_id: row.get("student_id"),
+        kind: row.get("kind"),
+        file_name: row.get("file_name"),
+        content_hash: row.get("content_hash"),
+        extension: row.get("extension"),
+        mime_type: row.get("mime_type"),
+        size: row.get("size"),
+        has_thumbnail: row.get("has_thumbnail"),
+        notes: row.get("notes"),
+        uploaded_by: row.get("uploaded_by"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_guardian(row: &SqliteRow) -> Result<Guardian> {
+    Ok(Guardian {
+        id: row.get("id"),
+        first_name: row.get("first_name"),
+        last_name: row.get("last_name"),
+        phone: row.get("phone"),
+        secondary_phone: row.get("secondary_phone"),
+        email: row.get("email"),
+        preferred_language: row.get("preferred_language"),
+        address: row.get("address"),
+        notes: row.get("notes"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_guardian_link(row: &SqliteRow) -> Result<StudentGuardian> {
+    Ok(StudentGuardian {
+        guardian_id: row.get("guardian_id"),
+        student_id: row.get("student_id"),
+        relationship: row.get("relationship"),
+        is_primary: row.get("is_primary"),
+        can_pick_up: row.get("can_pick_up"),
+        guardian_name: row.get::<String, _>("guardian_name").trim().to_string(),
+        guardian_phone: row.get("guardian_phone"),
+        student_name: row.get("student_name"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+/// Copies the name and phone of `guardian_id` to the students they are the
+/// primary guardian of, which sibling discounts and receipts still read.
+async fn sync_parent_fields(tx: &mut Transaction<'_, Sqlite>, guardian_id: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE students SET
+            parent_name = (SELECT trim(first_name || ' ' || last_name) FROM guardians WHERE id = ?1),
+            parent_phone = (SELECT phone FROM guardians WHERE id = ?1),
+            updated_at = ?2
+        WHERE id IN (SELECT student_id FROM student_guardians WHERE guardian_id = ?1 AND is_primary = 1)
+        "#,
+    )
+    .bind(guardian_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn row_to_class_group(row: &SqliteRow) -> Result<ClassGroup> {
+    Ok(ClassGroup {
+        id: row.get("id"),
+        name: row.get("name"),
+        academic_level: row.get("academic_level"),
+        subject_id: row.get("subject_id"),
+        teacher_id: row.get("teacher_id"),
+        capacity: row.get("capacity"),
+        academic_year: row.get("academic_year"),
+        is_active: row.get("is_active"),
+        notes: row.get("notes"),
+        subject_name: row.get("subject_name"),
+        teacher_name: row.get("teacher_name"),
+        enrolled_count: row.get("enrolled_count"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_enrollment(row: &SqliteRow) -> Result<Enrollment> {
+    Ok(Enrollment {
+        id: row.get("id"),
+        group_id: row.get("group_id"),
+        student_id: row.get("student_id"),
+        student_name: row.get("student_name"),
+        group_name: row.get("group_name"),
+        status: row.get("status"),
+        enrolled_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("enrolled_at"))?.with_timezone(&Utc),
+        withdrawn_at: row.get::<Option<String>, _>("withdrawn_at")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        notes: row.get("notes"),
+    })
+}
+
+fn row_to_room(row: &SqliteRow) -> Result<Room> {
+    Ok(Room {
+        id: row.get("id"),
+        name: row.get("name"),
+        capacity: row.get("capacity"),
+        is_active: row.get("is_active"),
+        notes: row.get("notes"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn weekday_name(weekday: u32) -> &'static str {
+    match weekday {
+        1 => "Lundi",
+        2 => "Mardi",
+        3 => "Mercredi",
+        4 => "Jeudi",
+        5 => "Vendredi",
+        6 => "Samedi",
+        _ => "Dimanche",
+    }
+}
+
+fn row_to_timetable_slot(row: &SqliteRow) -> Result<TimetableSlot> {
+    let date = |column: &str| {
+        row.get::<Option<String>, _>(column)
+            .and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok())
+    };
+
+    Ok(TimetableSlot {
+        id: row.get("id"),
+        group_id: row.get("group_id"),
+        weekday: row.get("weekday"),
+        start_time: row.get("start_time"),
+        end_time: row.get("end_time"),
+        room: row.get("room"),
+        teacher_id: row.get("teacher_id"),
+        starts_on: date("starts_on"),
+        ends_on: date("ends_on"),
+        is_active: row.get("is_active"),
+        group_name: row.get("group_name"),
+        teacher_name: row.get("teacher_name"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+        updated_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("updated_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_session_occurrence(row: &SqliteRow) -> Result<SessionOccurrence> {
+    let time = |column: &str| -> Result<DateTime<Utc>> {
+        Ok(DateTime::parse_from_rfc3339(&row.get::<String, _>(column))?.with_timezone(&Utc))
+    };
+
+    Ok(SessionOccurrence {
+        id: row.get("id"),
+        slot_id: row.get("slot_id"),
+        group_id: row.get("group_id"),
+        teacher_id: row.get("teacher_id"),
+        room: row.get("room"),
+        occurrence_date: NaiveDate::parse_from_str(&row.get::<String, _>("occurrence_date"), "%Y-%m-%d")?,
+        starts_at: time("starts_at")?,
+        ends_at: time("ends_at")?,
+        status: row.get("status"),
+        original_starts_at: row.get::<Option<String>, _>("original_starts_at")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        cancel_reason: row.get("cancel_reason"),
+        notes: row.get("notes"),
+        group_name: row.get("group_name"),
+        teacher_name: row.get("teacher_name"),
+        created_at: time("created_at")?,
+        updated_at: time("updated_at")?,
+    })
+}
+
+fn row_to_cash_session(row: &SqliteRow) -> Result<CashSession> {
+    Ok(CashSession {
+        id: row.get("id"),
+        opened_by: row.get("opened_by"),
+        opened_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("opened_at"))?.with_timezone(&Utc),
+        opening_float: row.get("opening_float"),
+        status: row.get("status"),
+        closed_by: row.get("closed_by"),
+        closed_at: row.get::<Option<String>, _>("closed_at")
+            .and_then(|d| DateTime::parse_from_rfc3339(&d).ok())
+            .map(|d| d.with_timezone(&Utc)),
+        notes: row.get("notes"),
+    })
+}
+
+fn row_to_discount(row: &SqliteRow) -> Result<Discount> {
+    let date = |column: &str| {
+        row.get::<Option<String>, _>(column)
+            .and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok())
+    };
+
+    Ok(Discount {
+        id: row.get("id"),
+        name: row.get("name"),
+        scope: row.get("scope"),
+        target: row.get("target"),
+        kind: row.get("kind"),
+        value: row.get("value"),
+        min_siblings: row.get("min_siblings"),
+        starts_on: date("starts_on"),
+        ends_on: date("ends_on"),
+        is_active: row.get("is_active"),
+        notes: row.get("notes"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+fn row_to_reversal(row: &SqliteRow) -> Result<PaymentReversal> {
+    Ok(PaymentReversal {
+        id: row.get("id"),
+        payment_id: row.get("payment_id"),
+        kind: row.get("kind"),
+        amount: row.get("amount"),
+        sessions: row.get("sessions"),
+        refund_method: row.get("refund_method"),
+        reason: row.get("reason"),
+        created_by: row.get("created_by"),
+        credit_note_number: row.get("credit_note_number"),
+        created_at: DateTime::parse_from_rfc3339(&row.get::<String, _>("created_at"))?.with_timezone(&Utc),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    const YEAR: &str = "2026-2027";
+
+    /// A fresh schema in memory; one connection that never closes, since
+    /// each connection to `sqlite::memory:` opens its own database.
+    async fn test_db() -> Database {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let db = Database { pool };
+        db.initialize_tables().await.unwrap();
+        db
+    }
+
+    async fn add_student(db: &Database, id: &str) {
+        let now = Utc::now();
+        db.create_student(Student {
+            id: id.to_string(),
+            first_name: "Amine".to_string(),
+            last_name: id.to_string(),
+            email: None,
+            phone: None,
+            academic_level: "1ère CEM".to_string(),
+            rfid_card: None,
+            parent_name: "Parent".to_string(),
+            parent_phone: String::new(),
+            address: None,
+            birth_date: None,
+            enrollment_date: now,
+            is_active: true,
+            notes: None,
+            sessions_remaining: 0,
+            photo_id: None,
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .unwrap();
+    }
+
+    /// Monthly dues of 1000 from September 2026, due on the 5th.
+    async fn add_plan(db: &Database, student_id: &str, installments: u32) {
+        let plan = PaymentPlan {
+            id: Uuid::new_v4().to_string(),
+            student_id: student_id.to_string(),
+            academic_year: YEAR.to_string(),
+            monthly_fee: 1000.0,
+            installments: installments as i64,
+            start_month: "2026-09".to_string(),
+            due_day: 5,
+            notes: None,
+            created_at: Utc::now(),
+        };
+        let schedule = billing::installment_dates("2026-09", installments, 5).unwrap();
+        db.create_payment_plan(plan, &schedule, &[]).await.unwrap();
+    }
+
+    async fn pay(db: &Database, student_id: &str, amount: f64, allow_partial: bool) -> Result<Payment> {
+        let now = Utc::now();
+        let payment = Payment {
+            id: Uuid::new_v4().to_string(),
+            student_id: student_id.to_string(),
+            amount,
+            payment_type: "Mensualité".to_string(),
+            payment_method: "cash".to_string(),
+            payment_date: now,
+            due_date: None,
+            status: "paid".to_string(),
+            notes: None,
+            receipt_number: None,
+            cashier: None,
+            sessions: 0,
+            cash_session_id: None,
+            group_id: None,
+            created_at: now,
+            updated_at: now,
+        };
+        db.create_payment(payment, YEAR, "REC-", None, allow_partial, false).await
+    }
+
+    async fn refund(db: &Database, payment_id: &str, kind: &str, amount: Option<f64>) -> Result<PaymentReversal> {
+        let details = ReversalDetails {
+            reason: "Départ",
+            created_by: "admin",
+            refund_method: Some("cash"),
+            academic_year: YEAR,
+            prefix: "REC-",
+        };
+        db.reverse_payment(payment_id, kind, amount, None, details).await
+    }
+
+    /// (month, paid amount, status) of the student's installments.
+    async fn installments(db: &Database, student_id: &str) -> Vec<(String, f64, String)> {
+        db.get_dues(Some(student_id), None, None, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.kind == "installment")
+            .map(|d| (d.month, d.paid_amount, d.status))
+            .collect()
+    }
+
+    fn due(month: &str, paid_amount: f64, status: &str) -> (String, f64, String) {
+        (month.to_string(), paid_amount, status.to_string())
+    }
+
+    #[test]
+    fn receipt_numbers_use_the_first_year() {
+        assert_eq!(receipt_number("REC-", "2023-2024", 123), "REC-2023-000123");
+        assert_eq!(receipt_number("", "2023/2024", 1), "2023-000001");
+    }
+
+    #[tokio::test]
+    async fn payments_settle_the_oldest_dues_first() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 3).await;
+
+        let first = pay(&db, "s1", 1500.0, true).await.unwrap();
+        assert_eq!(first.status, "partial");
+        assert_eq!(first.receipt_number.as_deref(), Some("REC-2026-000001"));
+        assert_eq!(
+            installments(&db, "s1").await,
+            [due("2026-09", 1000.0, "paid"), due("2026-10", 500.0, "partial"), due("2026-11", 0.0, "unpaid")]
+        );
+
+        // The excess over what is owed stays as credit
+        let second = pay(&db, "s1", 2500.0, true).await.unwrap();
+        assert_eq!(second.status, "paid");
+        assert_eq!(
+            installments(&db, "s1").await,
+            [due("2026-09", 1000.0, "paid"), due("2026-10", 1000.0, "paid"), due("2026-11", 1000.0, "paid")]
+        );
+        let allocated: f64 = sqlx::query_scalar("SELECT SUM(amount) FROM payment_allocations WHERE payment_id = ?")
+            .bind(&second.id)
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(allocated, 1500.0);
+    }
+
+    #[tokio::test]
+    async fn rejected_partial_payment_consumes_no_receipt_number() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 2).await;
+
+        assert!(pay(&db, "s1", 600.0, false).await.is_err());
+        let payment = pay(&db, "s1", 1000.0, false).await.unwrap();
+        assert_eq!(payment.receipt_number.as_deref(), Some("REC-2026-000001"));
+
+        let report = db.receipt_sequence_report(Some(YEAR)).await.unwrap();
+        assert_eq!((report[0].last_number, report[0].issued, report[0].missing.len()), (1, 1, 0));
+    }
+
+    #[tokio::test]
+    async fn receipt_sequence_report_lists_numbers_without_a_payment() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        let mut ids = Vec::new();
+        for _ in 0..4 {
+            ids.push(pay(&db, "s1", 1000.0, true).await.unwrap().id);
+        }
+
+        sqlx::query("DELETE FROM payments WHERE id IN (?, ?)")
+            .bind(&ids[1])
+            .bind(&ids[3])
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let report = db.receipt_sequence_report(None).await.unwrap();
+        assert_eq!(report.len(), 1);
+        let report = &report[0];
+        assert_eq!((report.academic_year.as_str(), report.last_number, report.issued), (YEAR, 4, 2));
+        assert_eq!(report.missing, [2, 4]);
+        assert_eq!(report.first_receipt.as_deref(), Some("REC-2026-000001"));
+        assert_eq!(report.last_receipt.as_deref(), Some("REC-2026-000003"));
+    }
+
+    #[tokio::test]
+    async fn late_fees_are_charged_once_per_due() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 3).await;
+        pay(&db, "s1", 1000.0, true).await.unwrap();
+
+        // September is paid, October is overdue and November is not yet late
+        let now = Utc.with_ymd_and_hms(2026, 11, 10, 12, 0, 0).unwrap();
+        let fees = db.apply_late_fees(200.0, 10, now).await.unwrap();
+        assert_eq!(fees.iter().map(|f| (f.month.as_str(), f.amount)).collect::<Vec<_>>(), [("2026-10", 200.0)]);
+
+        assert!(db.apply_late_fees(200.0, 10, now).await.unwrap().is_empty());
+        let later = Utc.with_ymd_and_hms(2026, 12, 10, 12, 0, 0).unwrap();
+        let fees = db.apply_late_fees(200.0, 10, later).await.unwrap();
+        assert_eq!(fees.iter().map(|f| f.month.as_str()).collect::<Vec<_>>(), ["2026-11"]);
+
+        let charged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM dues WHERE kind = 'late_fee'")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(charged, 2);
+    }
+
+    #[tokio::test]
+    async fn partial_refund_reopens_the_most_recent_due() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 2).await;
+        let payment = pay(&db, "s1", 2000.0, true).await.unwrap();
+
+        let reversal = refund(&db, &payment.id, "refund", Some(600.0)).await.unwrap();
+        assert_eq!(reversal.credit_note_number, "REC-AV-2026-000001");
+        assert_eq!(installments(&db, "s1").await, [due("2026-09", 1000.0, "paid"), due("2026-10", 400.0, "partial")]);
+        assert_eq!(db.get_payment(&payment.id).await.unwrap().unwrap().status, "paid");
+
+        // Refunding the rest reopens every due and marks the payment refunded
+        refund(&db, &payment.id, "refund", None).await.unwrap();
+        assert_eq!(installments(&db, "s1").await, [due("2026-09", 0.0, "unpaid"), due("2026-10", 0.0, "unpaid")]);
+        assert_eq!(db.get_payment(&payment.id).await.unwrap().unwrap().status, "refunded");
+        assert!(refund(&db, &payment.id, "refund", Some(1.0)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refund_takes_back_credit_before_reopening_dues() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 1).await;
+        let payment = pay(&db, "s1", 1500.0, true).await.unwrap();
+
+        refund(&db, &payment.id, "refund", Some(500.0)).await.unwrap();
+        assert_eq!(installments(&db, "s1").await, [due("2026-09", 1000.0, "paid")]);
+
+        refund(&db, &payment.id, "refund", Some(300.0)).await.unwrap();
+        assert_eq!(installments(&db, "s1").await, [due("2026-09", 700.0, "partial")]);
+    }
+
+    #[tokio::test]
+    async fn cancellation_reopens_every_due_once() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        add_plan(&db, "s1", 2).await;
+        let payment = pay(&db, "s1", 1500.0, true).await.unwrap();
+
+        let reversal = refund(&db, &payment.id, "cancellation", Some(10.0)).await.unwrap();
+        assert_eq!(reversal.amount, 1500.0);
+        assert_eq!(installments(&db, "s1").await, [due("2026-09", 0.0, "unpaid"), due("2026-10", 0.0, "unpaid")]);
+        assert_eq!(db.get_payment(&payment.id).await.unwrap().unwrap().status, "cancelled");
+        assert!(refund(&db, &payment.id, "cancellation", None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn scan_after_the_session_ended_checks_out_an_open_check_in() {
+        let db = test_db().await;
+        add_student(&db, "s1").await;
+        let created_at = Utc.with_ymd_and_hms(2026, 10, 1, 8, 0, 0).unwrap();
+        db.create_subject(Subject {
+            id: "math".to_string(),
+            name: "Mathématiques".to_string(),
+            code: None,
+            description: None,
+            created_at,
+        })
+        .await
+        .unwrap();
+        db.create_class_group(ClassGroup {
+            id: "g1".to_string(),
+            name: "Maths 4AM".to_string(),
+            academic_level: "4ème CEM".to_string(),
+            subject_id: "math".to_string(),
+            teacher_id: None,
+            capacity: 20,
+            academic_year: YEAR.to_string(),
+            is_active: true,
+            notes: None,
+            subject_name: String::new(),
+            teacher_name: None,
+            enrolled_count: 0,
+            created_at,
+            updated_at: created_at,
         })
+        .await
+        .unwrap();
+        db.enroll_student(Enrollment {
+            id: "e1".to_string(),
+            group_id: "g1".to_string(),
+            student_id: "s1".to_string(),
+            student_name: String::new(),
+            group_name: String::new(),
+            status: "active".to_string(),
+            enrolled_at: created_at,
+            withdrawn_at: None,
+            notes: None,
+        })
+        .await
+        .unwrap();
+        let at = |hour, minute| Utc.with_ymd_and_hms(2026, 10, 19, hour, minute, 0).unwrap();
+        let session = db
+            .create_session_occurrence(SessionOccurrence {
+                id: "o1".to_string(),
+                slot_id: None,
+                group_id: "g1".to_string(),
+                teacher_id: None,
+                room: "Salle 1".to_string(),
+                occurrence_date: NaiveDate::from_ymd_opt(2026, 10, 19).unwrap(),
+                starts_at: at(14, 0),
+                ends_at: at(15, 0),
+                status: "scheduled".to_string(),
+                original_starts_at: None,
+                cancel_reason: None,
+                notes: None,
+                group_name: String::new(),
+                teacher_name: None,
+                created_at,
+                updated_at: created_at,
+            })
+            .await
+            .unwrap();
+        db.record_session_scan("s1", &session, "present", at(14, 5)).await.unwrap();
+
+        // Once the session is over only the open check-in brings it back
+        let now = at(15, 20);
+        assert!(db.get_student_sessions("s1", now, now, None).await.unwrap().is_empty());
+        let checked_in = db.get_open_check_in("s1", at(0, 0)).await.unwrap();
+        assert_eq!(checked_in.as_deref(), Some("o1"));
+        let sessions = db.get_student_sessions("s1", now, now, checked_in.as_deref()).await.unwrap();
+        let session = schedule::session_for_scan(&sessions, checked_in.as_deref(), now, 30).unwrap();
+
+        let attendance = db.record_session_scan("s1", session, "present", now).await.unwrap();
+        assert_eq!(attendance.check_out_time, Some(now));
+        assert!(db.get_open_check_in("s1", at(0, 0)).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn import_moves_a_student_to_the_guardian_of_the_new_parent_phone() {
+        let db = test_db().await;
+        let row = |id: &str, first_name: &str, parent_phone: &str| {
+            serde_json::json!({
+                "id": id,
+                "first_name": first_name,
+                "last_name": "Benali",
+                "academic_level": "1ère CEM",
+                "parent_name": "Karim Benali",
+                "parent_phone": parent_phone,
+            })
+        };
+        let rows = vec![row("s1", "Amine", "0555 12 34 56"), row("s2", "Sara", "+213 555-12-34-56")];
+        db.import_students(rows, ImportStrategy::Upsert, false).await.unwrap();
+
+        let shared = db.find_guardians_by_phone("0555123456").await.unwrap();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(db.get_guardian_links(Some(&shared[0].id), None).await.unwrap().len(), 2);
+
+        let rows = vec![row("s1", "Amine", "0661 00 00 00")];
+        db.import_students(rows, ImportStrategy::Upsert, false).await.unwrap();
+
+        let links = db.get_guardian_links(None, Some("s1")).await.unwrap();
+        assert_eq!(links.len(), 1);
+        assert!(links[0].is_primary);
+        assert_eq!(db.find_guardians_by_phone("0661000000").await.unwrap()[0].id, links[0].guardian_id);
+        let left = db.get_guardian_links(Some(&shared[0].id), None).await.unwrap();
+        assert_eq!(left.iter().map(|l| l.student_id.as_str()).collect::<Vec<_>>(), vec!["s2"]);
     }
 }
@@ -0,0 +1,739 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat, Utc};
+use rust_xlsxwriter::{Color, Format, Workbook};
+use crate::models::{Attendance, DueDiscount, Expense, ExportOptions, Payment, Payslip, ProfitReport, Student, ZReport};
+use crate::payroll;
+
+/// Excel cannot guess UTF-8 in a CSV without it, which breaks Arabic names.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const MAX_SHEET_NAME: usize = 31;
+
+#[derive(Debug, Clone)]
+pub enum Cell {
+    Empty,
+    Text(String),
+    Number(f64),
+    Integer(i64),
+    Bool(bool),
+    Date(NaiveDate),
+    DateTime(DateTime<Local>),
+}
+
+impl From<String> for Cell {
+    fn from(value: String) -> Self {
+        Cell::Text(value)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(value: &str) -> Self {
+        Cell::Text(value.to_string())
+    }
+}
+
+impl From<Option<String>> for Cell {
+    fn from(value: Option<String>) -> Self {
+        value.map(Cell::Text).unwrap_or(Cell::Empty)
+    }
+}
+
+impl From<DateTime<Utc>> for Cell {
+    fn from(value: DateTime<Utc>) -> Self {
+        Cell::DateTime(value.with_timezone(&Local))
+    }
+}
+
+impl From<Option<DateTime<Utc>>> for Cell {
+    fn from(value: Option<DateTime<Utc>>) -> Self {
+        value.map(Cell::from).unwrap_or(Cell::Empty)
+    }
+}
+
+impl Cell {
+    fn to_text(&self) -> String {
+        match self {
+            Cell::Empty => String::new(),
+            Cell::Text(value) => value.clone(),
+            Cell::Number(value) => format!("{:.2}", value),
+            Cell::Integer(value) => value.to_string(),
+            Cell::Bool(value) => if *value { "Oui" } else { "Non" }.to_string(),
+            Cell::Date(value) => value.format("%d/%m/%Y").to_string(),
+            Cell::DateTime(value) => value.format("%d/%m/%Y %H:%M").to_string(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Cell::Empty => serde_json::Value::Null,
+            Cell::Number(value) => serde_json::json!(value),
+            Cell::Integer(value) => serde_json::json!(value),
+            Cell::Bool(value) => serde_json::json!(value),
+            Cell::Date(value) => serde_json::json!(value.format("%Y-%m-%d").to_string()),
+            // With the offset, so the import reads back the same instant
+            Cell::DateTime(value) => serde_json::json!(value.to_rfc3339_opts(SecondsFormat::Secs, false)),
+            Cell::Text(value) => serde_json::json!(value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub key: &'static str,
+    pub header: &'static str,
+}
+
+const fn column(key: &'static str, header: &'static str) -> Column {
+    Column { key, header }
+}
+
+#[derive(Debug, Clone)]
+pub struct Row {
+    /// Academic level of the row, used to split sheets per level.
+    pub level: String,
+    pub cells: Vec<Cell>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sheet {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub rows: Vec<Row>,
+}
+
+impl Sheet {
+    /// Keeps only the requested columns, in the requested order. Unknown
+    /// keys are ignored; an empty selection keeps every column.
+    pub fn select_columns(mut self, keys: &[String]) -> Self {
+        if keys.is_empty() {
+            return self;
+        }
+
+        let indexes: Vec<usize> = keys
+            .iter()
+            .filter_map(|key| self.columns.iter().position(|c| c.key == key))
+            .collect();
+        if indexes.is_empty() {
+            return self;
+        }
+
+        self.columns = indexes.iter().map(|&i| self.columns[i].clone()).collect();
+        for row in &mut self.rows {
+            row.cells = indexes.iter().map(|&i| row.cells[i].clone()).collect();
+        }
+        self
+    }
+
+    pub fn split_by_level(self) -> Vec<Sheet> {
+        let mut groups: BTreeMap<String, Vec<Row>> = BTreeMap::new();
+        for row in self.rows {
+            groups.entry(row.level.clone()).or_default().push(row);
+        }
+
+        if groups.is_empty() {
+            return vec![Sheet { name: self.name, columns: self.columns, rows: vec![] }];
+        }
+
+        groups
+            .into_iter()
+            .map(|(level, rows)| Sheet {
+                name: if level.is_empty() { self.name.clone() } else { level },
+                columns: self.columns.clone(),
+                rows,
+            })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                let object = self
+                    .columns
+                    .iter()
+                    .zip(&row.cells)
+                    .map(|(column, cell)| (column.key.to_string(), cell.to_json()))
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::Value::Object(object)
+            })
+            .collect();
+        serde_json::Value::Array(rows)
+    }
+}
+
+pub fn filter_students(students: Vec<Student>, options: &ExportOptions) -> Vec<Student> {
+    students
+        .into_iter()
+        .filter(|s| options.academic_level.as_ref().map_or(true, |level| &s.academic_level == level))
+        .filter(|s| !options.active_only.unwrap_or(false) || s.is_active)
+        .filter(|s| in_range(s.enrollment_date, options))
+        .collect()
+}
+
+pub fn filter_payments(payments: Vec<Payment>, students: &HashMap<String, Student>, options: &ExportOptions) -> Vec<Payment> {
+    payments
+        .into_iter()
+        .filter(|p| level_matches(&p.student_id, students, options))
+        .filter(|p| options.status.as_ref().map_or(true, |status| &p.status == status))
+        .filter(|p| in_range(p.payment_date, options))
+        .collect()
+}
+
+pub fn filter_attendance(records: Vec<Attendance>, students: &HashMap<String, Student>, options: &ExportOptions) -> Vec<Attendance> {
+    records
+        .into_iter()
+        .filter(|a| level_matches(&a.student_id, students, options))
+        .filter(|a| options.status.as_ref().map_or(true, |status| &a.status == status))
+        .filter(|a| in_range(a.date, options))
+        .collect()
+}
+
+fn level_matches(student_id: &str, students: &HashMap<String, Student>, options: &ExportOptions) -> bool {
+    match &options.academic_level {
+        Some(level) => students.get(student_id).is_some_and(|s| &s.academic_level == level),
+        None => true,
+    }
+}
+
+/// `date_from` and `date_to` are inclusive `YYYY-MM-DD` local dates.
+fn in_range(date: DateTime<Utc>, options: &ExportOptions) -> bool {
+    let day = date.with_timezone(&Local).date_naive();
+    let parse = |value: &Option<String>| {
+        value
+            .as_ref()
+            .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok())
+    };
+
+    parse(&options.date_from).map_or(true, |from| day >= from) && parse(&options.date_to).map_or(true, |to| day <= to)
+}
+
+pub fn students_sheet(students: &[Student]) -> Sheet {
+    let columns = vec![
+        column("id", "ID"),
+        column("last_name", "Nom"),
+        column("first_name", "Prénom"),
+        column("academic_level", "Niveau"),
+        column("phone", "Téléphone"),
+        column("email", "Email"),
+        column("parent_name", "Parent"),
+        column("parent_phone", "Téléphone parent"),
+        column("address", "Adresse"),
+        column("birth_date", "Date de naissance"),
+        column("rfid_card", "Carte RFID"),
+        column("enrollment_date", "Date d'inscription"),
+        column("is_active", "Actif"),
+        column("notes", "Notes"),
+    ];
+
+    let rows = students
+        .iter()
+        .map(|s| Row {
+            level: s.academic_level.clone(),
+            cells: vec![
+                s.id.clone().into(),
+                s.last_name.clone().into(),
+                s.first_name.clone().into(),
+                s.academic_level.clone().into(),
+                s.phone.clone().into(),
+                s.email.clone().into(),
+                s.parent_name.clone().into(),
+                s.parent_phone.clone().into(),
+                s.address.clone().into(),
+                s.birth_date
+                    .as_ref()
+                    .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+                    .map(Cell::Date)
+                    .unwrap_or_else(|| s.birth_date.clone().into()),
+                s.rfid_card.clone().into(),
+                s.enrollment_date.into(),
+                Cell::Bool(s.is_active),
+                s.notes.clone().into(),
+            ],
+        })
+        .collect();
+
+    Sheet { name: "Élèves".to_string(), columns, rows }
+}
+
+pub fn payments_sheet(payments: &[Payment], students: &HashMap<String, Student>) -> Sheet {
+    let columns = vec![
+        column("receipt_number", "N° reçu"),
+        column("payment_date", "Date"),
+        column("student_name", "Élève"),
+        column("academic_level", "Niveau"),
+        column("amount", "Montant"),
+        column("payment_type", "Type"),
+        column("payment_method", "Mode de paiement"),
+        column("status", "Statut"),
+        column("due_date", "Échéance"),
+        column("notes", "Notes"),
+    ];
+
+    let rows = payments
+        .iter()
+        .map(|p| {
+            let student = students.get(&p.student_id);
+            let level = student.map(|s| s.academic_level.clone()).unwrap_or_default();
+            Row {
+                level: level.clone(),
+                cells: vec![
+                    p.receipt_number.clone().into(),
+                    p.payment_date.into(),
+                    student_name(student).into(),
+                    level.into(),
+                    Cell::Number(p.amount),
+                    p.payment_type.clone().into(),
+                    p.payment_method.clone().into(),
+                    p.status.clone().into(),
+                    p.due_date.into(),
+                    p.notes.clone().into(),
+                ],
+            }
+        })
+        .collect();
+
+    Sheet { name: "Paiements".to_string(), columns, rows }
+}
+
+pub fn filter_expenses(expenses: Vec<Expense>, options: &ExportOptions) -> Vec<Expense> {
+    expenses.into_iter().filter(|e| in_range(e.expense_date, options)).collect()
+}
+
+pub fn expenses_sheet(expenses: &[Expense]) -> Sheet {
+    let columns = vec![
+        column("expense_date", "Date"),
+        column("category", "Catégorie"),
+        column("description", "Description"),
+        column("supplier", "Fournisseur"),
+        column("amount", "Montant"),
+        column("payment_method", "Mode de paiement"),
+        column("receipt_path", "Justificatif"),
+        column("notes", "Notes"),
+    ];
+
+    let rows = expenses
+        .iter()
+        .map(|e| Row {
+            level: String::new(),
+            cells: vec![
+                e.expense_date.into(),
+                e.category.clone().into(),
+                e.description.clone().into(),
+                e.supplier.clone().into(),
+                Cell::Number(e.amount),
+                e.payment_method.clone().into(),
+                e.receipt_path.clone().into(),
+                e.notes.clone().into(),
+            ],
+        })
+        .collect();
+
+    Sheet { name: "Dépenses".to_string(), columns, rows }
+}
+
+pub fn attendance_sheet(records: &[Attendance], students: &HashMap<String, Student>) -> Sheet {
+    let columns = vec![
+        column("date", "Date"),
+        column("student_name", "Élève"),
+        column("academic_level", "Niveau"),
+        column("status", "Statut"),
+        column("check_in_time", "Arrivée"),
+        column("check_out_time", "Départ"),
+        column("notes", "Notes"),
+    ];
+
+    let rows = records
+        .iter()
+        .map(|a| {
+            let student = students.get(&a.student_id);
+            let level = student.map(|s| s.academic_level.clone()).unwrap_or_default();
+            Row {
+                level: level.clone(),
+                cells: vec![
+                    Cell::Date(a.date.with_timezone(&Local).date_naive()),
+                    student_name(student).into(),
+                    level.into(),
+                    a.status.clone().into(),
+                    a.check_in_time.into(),
+                    a.check_out_time.into(),
+                    a.notes.clone().into(),
+                ],
+            }
+        })
+        .collect();
+
+    Sheet { name: "Présences".to_string(), columns, rows }
+}
+
+/// Revenue per month and academic level.
+pub fn revenue_report_sheet(payments: &[Payment], students: &HashMap<String, Student>) -> Sheet {
+    let columns = vec![
+        column("month", "Mois"),
+        column("academic_level", "Niveau"),
+        column("payment_count", "Nombre de paiements"),
+        column("total_amount", "Total"),
+    ];
+
+    let mut totals: BTreeMap<(String, String), (i64, f64)> = BTreeMap::new();
+    for payment in payments {
+        let month = payment.payment_date.with_timezone(&Local).format("%Y-%m").to_string();
+        let level = students
+            .get(&payment.student_id)
+            .map(|s| s.academic_level.clone())
+            .unwrap_or_default();
+        let entry = totals.entry((month, level)).or_insert((0, 0.0));
+        entry.0 += 1;
+        entry.1 += payment.amount;
+    }
+
+    let rows = totals
+        .into_iter()
+        .map(|((month, level), (count, total))| Row {
+            level: level.clone(),
+            cells: vec![month.into(), level.into(), Cell::Integer(count), Cell::Number(total)],
+        })
+        .collect();
+
+    Sheet { name: "Revenus".to_string(), columns, rows }
+}
+
+/// Revenue, expenses and net profit per month, followed by the detail of
+/// each category.
+pub fn profit_report_sheet(report: &ProfitReport) -> Sheet {
+    let columns = vec![
+        column("month", "Mois"),
+        column("category", "Catégorie"),
+        column("revenue", "Recettes"),
+        column("expenses", "Dépenses"),
+        column("net_profit", "Résultat net"),
+    ];
+
+    let mut rows: Vec<Row> = report
+        .months
+        .iter()
+        .map(|m| Row {
+            level: String::new(),
+            cells: vec![
+                m.month.clone().into(),
+                "Total".into(),
+                Cell::Number(m.revenue),
+                Cell::Number(m.expenses),
+                Cell::Number(m.net_profit),
+            ],
+        })
+        .collect();
+    rows.extend(report.categories.iter().map(|c| {
+        let (revenue, expenses) = if c.kind == "expense" { (0.0, c.amount) } else { (c.amount, 0.0) };
+        Row {
+            level: String::new(),
+            cells: vec![
+                c.month.clone().into(),
+                c.category.clone().into(),
+                Cell::Number(revenue),
+                Cell::Number(expenses),
+                Cell::Number(revenue - expenses),
+            ],
+        }
+    }));
+    rows.sort_by_key(|r| r.cells[0].to_text());
+
+    Sheet { name: "Résultat".to_string(), columns, rows }
+}
+
+/// Discount lines of generated dues, one row per due and discount.
+pub fn discounts_report_sheet(lines: &[DueDiscount], options: &ExportOptions) -> Sheet {
+    let columns = vec![
+        column("month", "Mois"),
+        column("due_date", "Échéance"),
+        column("student_name", "Élève"),
+        column("academic_level", "Niveau"),
+        column("discount", "Réduction"),
+        column("amount", "Montant"),
+    ];
+
+    let rows = lines
+        .iter()
+        .filter(|l| options.academic_level.as_ref().map_or(true, |level| &l.academic_level == level))
+        .filter(|l| in_range(l.due_date, options))
+        .map(|l| Row {
+            level: l.academic_level.clone(),
+            cells: vec![
+                l.month.clone().into(),
+                Cell::Date(l.due_date.with_timezone(&Local).date_naive()),
+                l.student_name.clone().into(),
+                l.academic_level.clone().into(),
+                l.label.clone().into(),
+                Cell::Number(l.amount),
+            ],
+        })
+        .collect();
+
+    Sheet { name: "Réductions".to_string(), columns, rows }
+}
+
+/// Z-report of a cash session, one row per payment method.
+/// One row per group and adjustment of a payslip, followed by its totals.
+pub fn payslip_sheet(payslip: &Payslip) -> Sheet {
+    let columns = vec![
+        column("label", "Libellé"),
+        column("sessions", "Séances"),
+        column("revenue", "Recettes"),
+        column("rate", "Taux"),
+        column("amount", "Montant"),
+    ];
+
+    let mut rows: Vec<Row> = payslip
+        .lines
+        .iter()
+        .map(|l| Row {
+            level: String::new(),
+            cells: vec![
+                payroll::line_label(l).into(),
+                Cell::Integer(l.sessions),
+                Cell::Number(l.revenue),
+                Cell::Number(l.rate),
+                Cell::Number(l.amount),
+            ],
+        })
+        .collect();
+    rows.extend(payslip.adjustments.iter().map(|a| Row {
+        level: String::new(),
+        cells: vec![a.label.clone().into(), Cell::Empty, Cell::Empty, Cell::Empty, Cell::Number(a.amount)],
+    }));
+    for (label, amount) in [("Total brut", payslip.gross), ("Ajustements", payslip.adjustments_total), ("Net à payer", payslip.net)] {
+        rows.push(Row {
+            level: String::new(),
+            cells: vec![label.into(), Cell::Empty, Cell::Empty, Cell::Empty, Cell::Number(amount)],
+        });
+    }
+
+    Sheet { name: format!("Paie {}", payslip.month), columns, rows }
+}
+
+pub fn z_report_sheet(report: &ZReport) -> Sheet {
+    let columns = vec![
+        column("payment_method", "Mode de paiement"),
+        column("payment_count", "Nombre de paiements"),
+        column("collected", "Encaissé"),
+        column("refunded", "Remboursé"),
+        column("expected", "Attendu"),
+        column("counted", "Compté"),
+        column("difference", "Écart"),
+    ];
+
+    let rows = report
+        .totals
+        .iter()
+        .map(|t| Row {
+            level: String::new(),
+            cells: vec![
+                t.payment_method.clone().into(),
+                Cell::Integer(t.payment_count),
+                Cell::Number(t.collected),
+                Cell::Number(t.refunded),
+                Cell::Number(t.expected),
+                t.counted.map(Cell::Number).unwrap_or(Cell::Empty),
+                t.difference.map(Cell::Number).unwrap_or(Cell::Empty),
+            ],
+        })
+        .collect();
+
+    let opened = report.session.opened_at.with_timezone(&Local).format("%Y-%m-%d");
+    Sheet { name: format!("Z {}", opened), columns, rows }
+}
+
+/// Attendance totals and rate per student.
+pub fn attendance_report_sheet(records: &[Attendance], students: &HashMap<String, Student>) -> Sheet {
+    let columns = vec![
+        column("student_name", "Élève"),
+        column("academic_level", "Niveau"),
+        column("present", "Présent"),
+        column("late", "En retard"),
+        column("absent", "Absent"),
+        column("total", "Total"),
+        column("attendance_rate", "Taux de présence (%)"),
+    ];
+
+    let mut counts: HashMap<&str, (i64, i64, i64)> = HashMap::new();
+    for record in records {
+        let entry = counts.entry(record.student_id.as_str()).or_insert((0, 0, 0));
+        match record.status.as_str() {
+            "present" => entry.0 += 1,
+            "late" => entry.1 += 1,
+            _ => entry.2 += 1,
+        }
+    }
+
+    let mut rows: Vec<Row> = counts
+        .into_iter()
+        .map(|(student_id, (present, late, absent))| {
+            let student = students.get(student_id);
+            let level = student.map(|s| s.academic_level.clone()).unwrap_or_default();
+            let total = present + late + absent;
+            Row {
+                level: level.clone(),
+                cells: vec![
+                    student_name(student).into(),
+                    level.into(),
+                    Cell::Integer(present),
+                    Cell::Integer(late),
+                    Cell::Integer(absent),
+                    Cell::Integer(total),
+                    Cell::Number(if total > 0 { (present + late) as f64 * 100.0 / total as f64 } else { 0.0 }),
+                ],
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| (&a.level, a.cells[0].to_text()).cmp(&(&b.level, b.cells[0].to_text())));
+
+    Sheet { name: "Assiduité".to_string(), columns, rows }
+}
+
+fn student_name(student: Option<&Student>) -> String {
+    student
+        .map(|s| format!("{} {}", s.last_name, s.first_name))
+        .unwrap_or_default()
+}
+
+/// Writes every sheet under a single header row. Returns the row count.
+pub fn write_csv(path: &Path, sheets: &[Sheet], delimiter: u8) -> Result<usize> {
+    let columns = &sheets.first().ok_or_else(|| anyhow!("Nothing to export"))?.columns;
+
+    let mut content = UTF8_BOM.to_vec();
+    let mut count = 0;
+    {
+        let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(&mut content);
+        writer.write_record(columns.iter().map(|c| c.header))?;
+        for row in sheets.iter().flat_map(|s| &s.rows) {
+            writer.write_record(row.cells.iter().map(Cell::to_text))?;
+            count += 1;
+        }
+        writer.flush()?;
+    }
+
+    std::fs::write(path, content)?;
+    Ok(count)
+}
+
+/// Writes one worksheet per sheet with typed cells. Returns the row count.
+pub fn write_xlsx(path: &Path, sheets: &[Sheet]) -> Result<usize> {
+    let mut workbook = Workbook::new();
+    let header = Format::new().set_bold().set_background_color(Color::RGB(0xDDEBF7));
+    let number = Format::new().set_num_format("#,##0.00");
+    let date = Format::new().set_num_format("dd/mm/yyyy");
+    let datetime = Format::new().set_num_format("dd/mm/yyyy hh:mm");
+
+    let mut used_names: Vec<String> = Vec::new();
+    let mut count = 0;
+
+    for sheet in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(unique_sheet_name(&sheet.name, &mut used_names))?;
+
+        for (col, column) in sheet.columns.iter().enumerate() {
+            worksheet.write_string_with_format(0, col as u16, column.header, &header)?;
+        }
+        worksheet.set_freeze_panes(1, 0)?;
+
+        for (index, row) in sheet.rows.iter().enumerate() {
+            let r = index as u32 + 1;
+            for (col, cell) in row.cells.iter().enumerate() {
+                let c = col as u16;
+                match cell {
+                    Cell::Empty => {}
+                    Cell::Text(value) => {
+                        worksheet.write_string(r, c, value)?;
+                    }
+                    Cell::Number(value) => {
+                        worksheet.write_number_with_format(r, c, *value, &number)?;
+                    }
+                    Cell::Integer(value) => {
+                        worksheet.write_number(r, c, *value as f64)?;
+                    }
+                    Cell::Bool(value) => {
+                        worksheet.write_boolean(r, c, *value)?;
+                    }
+                    Cell::Date(value) => {
+                        worksheet.write_datetime_with_format(r, c, value, &date)?;
+                    }
+                    Cell::DateTime(value) => {
+                        worksheet.write_datetime_with_format(r, c, value.naive_local(), &datetime)?;
+                    }
+                }
+            }
+            count += 1;
+        }
+
+        worksheet.autofit();
+    }
+
+    workbook.save(path)?;
+    Ok(count)
+}
+
+/// Excel limits sheet names to 31 characters without `[]:*?/\` and
+/// requires them to be unique.
+fn unique_sheet_name(name: &str, used: &mut Vec<String>) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| if "[]:*?/\\".contains(c) { '-' } else { c })
+        .take(MAX_SHEET_NAME)
+        .collect();
+    let clean = if clean.trim().is_empty() { "Feuille".to_string() } else { clean };
+
+    let mut candidate = clean.clone();
+    let mut suffix = 2;
+    while used.iter().any(|u| u.eq_ignore_ascii_case(&candidate)) {
+        let tail = format!(" ({})", suffix);
+        let base: String = clean.chars().take(MAX_SHEET_NAME - tail.len()).collect();
+        candidate = format!("{}{}", base, tail);
+        suffix += 1;
+    }
+
+    used.push(candidate.clone());
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{self, StudentRow};
+    use chrono::TimeZone;
+
+    #[test]
+    fn json_export_reads_back_through_the_import() {
+        let enrolled = Utc.with_ymd_and_hms(2026, 9, 14, 8, 30, 15).unwrap();
+        let student = Student {
+            id: "s1".to_string(),
+            first_name: "Amine".to_string(),
+            last_name: "Benali".to_string(),
+            email: None,
+            phone: Some("0555123456".to_string()),
+            academic_level: "1ère CEM".to_string(),
+            rfid_card: None,
+            parent_name: "Karim Benali".to_string(),
+            parent_phone: "0555123456".to_string(),
+            address: None,
+            birth_date: Some("2014-03-02".to_string()),
+            enrollment_date: enrolled,
+            is_active: true,
+            notes: None,
+            sessions_remaining: 0,
+            photo_id: None,
+            created_at: enrolled,
+            updated_at: enrolled,
+        };
+
+        let json = students_sheet(&[student]).to_json();
+        let rows = json.as_array().unwrap().clone();
+        let imported = import::prepare_rows::<StudentRow, _>(rows, import::student_from_row, |s| &s.id);
+
+        let (result, student) = &imported[0];
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        let student = student.as_ref().unwrap();
+        assert_eq!(student.enrollment_date, enrolled);
+        assert_eq!(student.birth_date.as_deref(), Some("2014-03-02"));
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, de::DeserializeOwned};
+use uuid::Uuid;
+use sqlx::{Sqlite, Transaction};
+use crate::models::{Attendance, ImportReport, ImportRowResult, Payment, Student, ACADEMIC_LEVELS};
+use crate::spreadsheet::SheetData;
+
+pub const PAYMENT_METHODS: [&str; 3] = ["cash", "bank", "check"];
+pub const PAYMENT_STATUSES: [&str; 3] = ["paid", "unpaid", "partial"];
+pub const ATTENDANCE_STATUSES: [&str; 4] = ["present", "late", "absent", "excused"];
+
+/// `Student` fields a spreadsheet column can be mapped to.
+pub const STUDENT_FIELDS: [&str; 14] = [
+    "id", "first_name", "last_name", "email", "phone", "academic_level", "rfid_card",
+    "parent_name", "parent_phone", "address", "birth_date", "enrollment_date", "is_active", "notes",
+];
+const REQUIRED_STUDENT_FIELDS: [&str; 5] = ["first_name", "last_name", "academic_level", "parent_name", "parent_phone"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStrategy {
+    /// Rows whose id already exists replace the stored record.
+    Upsert,
+    /// Rows whose id already exists are left untouched.
+    SkipExisting,
+}
+
+impl ImportStrategy {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "upsert" => Ok(ImportStrategy::Upsert),
+            "skip_existing" => Ok(ImportStrategy::SkipExisting),
+            other => Err(anyhow!("Unsupported import strategy: {}", other)),
+        }
+    }
+}
+
+/// What a row does to the database once parsed and checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowAction {
+    Insert,
+    Update,
+    Skip,
+}
+
+impl ImportReport {
+    pub fn new(data_type: &str, dry_run: bool, total_rows: usize) -> Self {
+        ImportReport {
+            data_type: data_type.to_string(),
+            dry_run,
+            committed: false,
+            total_rows,
+            inserted: 0,
+            updated: 0,
+            skipped: 0,
+            failed: 0,
+            duplicates: 0,
+            rows: Vec::with_capacity(total_rows),
+        }
+    }
+
+    pub fn push(&mut self, mut result: ImportRowResult) {
+        if !result.errors.is_empty() {
+            result.action = "error".to_string();
+        }
+
+        match result.action.as_str() {
+            "insert" => self.inserted += 1,
+            "update" => self.updated += 1,
+            "skip" => self.skipped += 1,
+            _ => self.failed += 1,
+        }
+        if result.duplicate {
+            self.duplicates += 1;
+        }
+        self.rows.push(result);
+    }
+
+    /// Records a row along with the outcome of writing it.
+    pub fn push_outcome(&mut self, mut result: ImportRowResult, outcome: Result<()>) {
+        if let Err(e) = outcome {
+            result.errors.push(e.to_string());
+        }
+        self.push(result);
+    }
+
+    /// Commits only a real import without any failed row; dry runs and
+    /// imports with errors leave the database untouched.
+    pub async fn finish(mut self, tx: Transaction<'_, Sqlite>) -> Result<Self> {
+        if self.dry_run || self.failed > 0 {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+            self.committed = true;
+        }
+        Ok(self)
+    }
+}
+
+impl ImportRowResult {
+    pub fn new(row: usize) -> Self {
+        ImportRowResult {
+            row,
+            id: None,
+            action: "insert".to_string(),
+            duplicate: false,
+            errors: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Inserts a new record; an existing one is updated or skipped per
+    /// `strategy`, with `existing` as the warning when skipped.
+    pub fn resolve(&mut self, strategy: ImportStrategy, exists: bool, existing: &str) -> RowAction {
+        if !exists {
+            return RowAction::Insert;
+        }
+        self.duplicate = true;
+        match strategy {
+            ImportStrategy::Upsert => {
+                self.action = "update".to_string();
+                RowAction::Update
+            }
+            ImportStrategy::SkipExisting => {
+                self.skip(existing);
+                RowAction::Skip
+            }
+        }
+    }
+
+    pub fn skip(&mut self, warning: &str) {
+        self.action = "skip".to_string();
+        self.warnings.push(warning.to_string());
+    }
+}
+
+/// Parses each row as `R` and builds its record with `build`, flagging ids
+/// used twice in the file. Rows that cannot be parsed carry no record.
+pub fn prepare_rows<R, T>(
+    rows: Vec<serde_json::Value>,
+    mut build: impl FnMut(R, &mut ImportRowResult) -> T,
+    id: impl Fn(&T) -> &str,
+) -> Vec<(ImportRowResult, Option<T>)>
+where
+    R: DeserializeOwned,
+{
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let mut result = ImportRowResult::new(index + 1);
+            let record = match serde_json::from_value::<R>(value) {
+                Ok(row) => build(row, &mut result),
+                Err(e) => {
+                    result.errors.push(e.to_string());
+                    return (result, None);
+                }
+            };
+
+            let record_id = id(&record).to_string();
+            if let Some(first_row) = seen.insert(record_id.clone(), result.row) {
+                result.duplicate = true;
+                result.errors.push(format!("Duplicate id, already used on row {}", first_row));
+            }
+            result.id = Some(record_id);
+            (result, Some(record))
+        })
+        .collect()
+}
+
+/// A student as found in an import file: ids and timestamps are optional so
+/// spreadsheets converted to JSON can be imported as they are.
+#[derive(Debug, Deserialize)]
+pub struct StudentRow {
+    pub id: Option<String>,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    pub academic_level: String,
+    pub rfid_card: Option<String>,
+    pub parent_name: String,
+    pub parent_phone: String,
+    pub address: Option<String>,
+    pub birth_date: Option<String>,
+    pub enrollment_date: Option<String>,
+    pub is_active: Option<bool>,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaymentRow {
+    pub id: Option<String>,
+    pub student_id: String,
+    pub amount: f64,
+    pub payment_type: String,
+    pub payment_method: String,
+    pub payment_date: Option<String>,
+    pub due_date: Option<String>,
+    pub status: Option<String>,
+    pub notes: Option<String>,
+    pub receipt_number: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttendanceRow {
+    pub id: Option<String>,
+    pub student_id: String,
+    pub date: String,
+    pub status: String,
+    pub check_in_time: Option<String>,
+    pub check_out_time: Option<String>,
+    pub notes: Option<String>,
+}
+
+/// Splits a JSON array into its elements so each row can fail on its own.
+pub fn parse_rows(content: &str) -> Result<Vec<serde_json::Value>> {
+    match serde_json::from_str::<serde_json::Value>(content)? {
+        serde_json::Value::Array(rows) => Ok(rows),
+        _ => Err(anyhow!("Import file must contain a JSON array")),
+    }
+}
+
+/// Accepts RFC 3339 timestamps as well as plain `YYYY-MM-DD` or
+/// `DD/MM/YYYY` dates (taken as midnight UTC).
+pub fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%d", "%d/%m/%Y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| Utc.from_utc_datetime(&date))
+}
+
+fn optional_datetime(value: &Option<String>, field: &str, result: &mut ImportRowResult) -> Option<DateTime<Utc>> {
+    match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(raw) => {
+            let parsed = parse_datetime(raw);
+            if parsed.is_none() {
+                result.errors.push(format!("Invalid {}: {}", field, raw));
+            }
+            parsed
+        }
+        None => None,
+    }
+}
+
+fn require(value: &str, field: &str, result: &mut ImportRowResult) {
+    if value.trim().is_empty() {
+        result.errors.push(format!("{} is required", field));
+    }
+}
+
+pub fn student_from_row(row: StudentRow, result: &mut ImportRowResult) -> Student {
+    require(&row.first_name, "first_name", result);
+    require(&row.last_name, "last_name", result);
+    require(&row.academic_level, "academic_level", result);
+    require(&row.parent_name, "parent_name", result);
+    require(&row.parent_phone, "parent_phone", result);
+
+    if !row.academic_level.is_empty() && !ACADEMIC_LEVELS.contains(&row.academic_level.as_str()) {
+        result.warnings.push(format!("Unknown academic level: {}", row.academic_level));
+    }
+    if let Some(email) = row.email.as_ref().filter(|e| !e.is_empty() && !e.contains('@')) {
+        result.warnings.push(format!("Suspicious email address: {}", email));
+    }
+    if !row.parent_phone.is_empty() && row.parent_phone.chars().filter(|c| c.is_ascii_digit()).count() < 9 {
+        result.warnings.push(format!("Parent phone looks incomplete: {}", row.parent_phone));
+    }
+
+    let enrollment_date = optional_datetime(&row.enrollment_date, "enrollment_date", result);
+    let now = Utc::now();
+
+    Student {
+        id: row.id.filter(|id| !id.is_empty()).unwrap_or_else(|| Uuid::new_v4().to_string()),
+        first_name: row.first_name.trim().to_string(),
+        last_name: row.last_name.trim().to_string(),
+        email: row.email.filter(|v| !v.is_empty()),
+        phone: row.phone.filter(|v| !v.is_empty()),
+        academic_level: row.academic_level.trim().to_string(),
+        rfid_card: row.rfid_card.filter(|v| !v.is_empty()),
+        parent_name: row.parent_name.trim().to_string(),
+        parent_phone: row.parent_phone.trim().to_string(),
+        address: row.address.filter(|v| !v.is_empty()),
+        birth_date: row.birth_date.filter(|v| !v.is_empty()),
+        enrollment_date: enrollment_date.unwrap_or(now),
+        is_active: row.is_active.unwrap_or(true),
+        notes: row.notes.filter(|v| !v.is_empty()),
+        sessions_remaining: 0,
+        photo_id: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+pub fn payment_from_row(row: PaymentRow, result: &mut ImportRowResult) -> Payment {
+    require(&row.student_id, "student_id", result);
+    require(&row.payment_type, "payment_type", result);
+
+    if row.amount.is_nan() || row.amount <= 0.0 {
+        result.errors.push(format!("Amount must be positive: {}", row.amount));
+    }
+    if !PAYMENT_METHODS.contains(&row.payment_method.as_str()) {
+        result.warnings.push(format!("Unknown payment method: {}", row.payment_method));
+    }
+    let status = row.status.filter(|s| !s.is_empty()).unwrap_or_else(|| "paid".to_string());
+    if !PAYMENT_STATUSES.contains(&status.as_str()) {
+        result.warnings.push(format!("Unknown payment status: {}", status));
+    }
+
+    let payment_date = optional_datetime(&row.payment_date, "payment_date", result);
+    if row.payment_date.is_none() {
+        result.warnings.push("Missing payment_date, using the import time".to_string());
+    }
+    let due_date = optional_datetime(&row.due_date, "due_date", result);
+    let now = Utc::now();
+
+    Payment {
+        id: row.id.filter(|id| !id.is_empty()).unwrap_or_else(|| Uuid::new_v4().to_string()),
+        student_id: row.student_id,
+        amount: row.amount,
+        payment_type: row.payment_type,
+        payment_method: row.payment_method,
+        payment_date: payment_date.unwrap_or(now),
+        due_date,
+        status,
+        notes: row.notes.filter(|v| !v.is_empty()),
+        receipt_number: row.receipt_number.filter(|v| !v.is_empty()),
+        cashier: None,
+        sessions: 0,
+        cash_session_id: None,
+        group_id: None,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+pub fn attendance_from_row(row: AttendanceRow, result: &mut ImportRowResult) -> Attendance {
+    require(&row.student_id, "student_id", result);
+
+    if !ATTENDANCE_STATUSES.contains(&row.status.as_str()) {
+        result.warnings.push(format!("Unknown attendance status: {}", row.status));
+    }
+
+    let date = parse_datetime(&row.date);
+    if date.is_none() {
+        result.errors.push(format!("Invalid date: {}", row.date));
+    }
+    let check_in_time = optional_datetime(&row.check_in_time, "check_in_time", result);
+    let check_out_time = optional_datetime(&row.check_out_time, "check_out_time", result);
+    if let (Some(check_in), Some(check_out)) = (check_in_time, check_out_time) {
+        if check_out < check_in {
+            result.warnings.push("Check-out is before check-in".to_string());
+        }
+    }
+    let now = Utc::now();
+
+    Attendance {
+        id: row.id.filter(|id| !id.is_empty()).unwrap_or_else(|| Uuid::new_v4().to_string()),
+        student_id: row.student_id,
+        date: date.unwrap_or(now),
+        status: row.status,
+        check_in_time,
+        check_out_time,
+        notes: row.notes.filter(|v| !v.is_empty()),
+        group_id: None,
+        occurrence_id: None,
+        created_at: now,
+    }
+}
+
+/// Guesses the `Student` field of each spreadsheet header from the usual
+/// French column titles.
+pub fn suggest_student_mapping(headers: &[String]) -> HashMap<String, String> {
+    let mut mapping = HashMap::new();
+
+    for header in headers {
+        let key = fold(header);
+        let field = match key.as_str() {
+            "id" | "identifiant" => "id",
+            "prenom" | "first name" | "firstname" => "first_name",
+            "nom" | "nom de famille" | "last name" | "lastname" => "last_name",
+            "email" | "e-mail" | "mail" => "email",
+            "niveau" | "classe" | "niveau scolaire" | "annee" | "level" => "academic_level",
+            "carte" | "carte rfid" | "rfid" | "badge" => "rfid_card",
+            "adresse" | "address" => "address",
+            "date de naissance" | "naissance" | "ne le" | "birth date" => "birth_date",
+            "date d'inscription" | "inscription" | "inscrit le" => "enrollment_date",
+            "actif" | "active" => "is_active",
+            "notes" | "remarques" | "observations" => "notes",
+            _ if key.contains("parent") || key.contains("tuteur") || key.contains("pere") || key.contains("mere") => {
+                if key.contains("tel") || key.contains("phone") || key.contains("mobile") {
+                    "parent_phone"
+                } else {
+                    "parent_name"
+                }
+            }
+            _ if key.starts_with("tel") || key.contains("phone") || key.contains("mobile") => "phone",
+            _ => continue,
+        };
+
+        if !mapping.values().any(|f| f == field) {
+            mapping.insert(header.clone(), field.to_string());
+        }
+    }
+
+    mapping
+}
+
+/// Turns spreadsheet rows into import rows following `mapping`
+/// (spreadsheet header -> `Student` field), normalising phone numbers and
+/// academic levels. Returns the rows with their line numbers in the file.
+pub fn student_rows_from_sheet(data: &SheetData, mapping: &HashMap<String, String>) -> Result<(Vec<serde_json::Value>, Vec<usize>)> {
+    for (header, field) in mapping {
+        if !STUDENT_FIELDS.contains(&field.as_str()) {
+            return Err(anyhow!("Unknown student field {} for column {}", field, header));
+        }
+        if !data.headers.contains(header) {
+            return Err(anyhow!("Column {} is not in the file", header));
+        }
+    }
+
+    let mut rows = Vec::with_capacity(data.rows.len());
+    let mut lines = Vec::with_capacity(data.rows.len());
+
+    for (line, cells) in &data.rows {
+        let mut object = serde_json::Map::new();
+        for field in REQUIRED_STUDENT_FIELDS {
+            object.insert(field.to_string(), serde_json::Value::String(String::new()));
+        }
+
+        for (header, field) in mapping {
+            let value = match data.value(cells, header) {
+                Some(value) => value,
+                None => continue,
+            };
+            let value = match field.as_str() {
+                "phone" | "parent_phone" => serde_json::Value::String(normalize_phone(&value)),
+                "academic_level" => serde_json::Value::String(
+                    normalize_level(&value).map(str::to_string).unwrap_or(value),
+                ),
+                "birth_date" | "enrollment_date" => serde_json::Value::String(excel_serial_date(&value).unwrap_or(value)),
+                "is_active" => serde_json::Value::Bool(!matches!(fold(&value).as_str(), "non" | "no" | "0" | "false" | "inactif")),
+                _ => serde_json::Value::String(value),
+            };
+            object.insert(field.clone(), value);
+        }
+
+        rows.push(serde_json::Value::Object(object));
+        lines.push(*line);
+    }
+
+    Ok((rows, lines))
+}
+
+/// Dates typed in cells without a date format reach us as Excel serial
+/// numbers (days since 1899-12-30).
+fn excel_serial_date(value: &str) -> Option<String> {
+    let serial: f64 = value.parse().ok()?;
+    if !(1.0..100_000.0).contains(&serial) {
+        return None;
+    }
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?;
+    let date = epoch.checked_add_signed(chrono::Duration::days(serial as i64))?;
+    Some(date.format("%Y-%m-%d").to_string())
+}
+
+/// Brings Algerian numbers to the national `0XXXXXXXXX` form: drops
+/// separators and the +213/00213 prefix, and restores the leading zero lost
+/// when a spreadsheet stored the number as a numeric cell.
+pub fn normalize_phone(raw: &str) -> String {
+    let digits: String = raw.chars().filter(|c| c.is_ascii_digit()).collect();
+    let international = raw.trim_start().starts_with('+');
+
+    let national = if let Some(rest) = digits.strip_prefix("00213") {
+        rest
+    } else if digits.starts_with("213") && (international || digits.len() == 12) {
+        &digits[3..]
+    } else {
+        digits.as_str()
+    };
+
+    if national.is_empty() {
+        raw.trim().to_string()
+    } else if national.len() == 9 && !national.starts_with('0') {
+        format!("0{}", national)
+    } else {
+        national.to_string()
+    }
+}
+
+/// Last nine digits of a phone number, which identify it regardless of the
+/// way it was typed.
+pub fn phone_key(phone: &str) -> String {
+    let digits: String = normalize_phone(phone).chars().filter(|c| c.is_ascii_digit()).collect();
+    let skip = digits.len().saturating_sub(9);
+    digits[skip..].to_string()
+}
+
+/// Maps the many ways a level is written ("1AM", "1 A.S", "2eme annee
+/// moyenne", "Terminale", ...) to one of `ACADEMIC_LEVELS`.
+pub fn normalize_level(raw: &str) -> Option<&'static str> {
+    if let Some(level) = ACADEMIC_LEVELS.iter().find(|l| fold(l) == fold(raw)) {
+        return Some(level);
+    }
+
+    let compact: String = fold(raw).chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    if compact.starts_with("terminale") || compact.starts_with("tle") {
+        return Some(ACADEMIC_LEVELS[5]);
+    }
+
+    let (year, rest) = match compact.chars().next().and_then(|c| c.to_digit(10)) {
+        Some(year) => (year, &compact[1..]),
+        None => {
+            let words = [("premiere", 1), ("deuxieme", 2), ("troisieme", 3)];
+            let (word, year) = words.iter().find(|(w, _)| compact.starts_with(w))?;
+            (*year, &compact[word.len()..])
+        }
+    };
+
+    let rest = ["ere", "eme", "er", "e"]
+        .iter()
+        .find_map(|suffix| rest.strip_prefix(suffix))
+        .unwrap_or(rest);
+    let rest = rest.strip_prefix("annee").unwrap_or(rest);
+
+    let offset = if rest.starts_with("am") || rest.contains("cem") || rest.starts_with("moy") {
+        0
+    } else if rest.starts_with("as") || rest.contains("lyc") || rest.starts_with("sec") {
+        3
+    } else {
+        return None;
+    };
+
+    match year {
+        1..=3 => Some(ACADEMIC_LEVELS[offset + year as usize - 1]),
+        _ => None,
+    }
+}
+
+/// Lowercases and strips French accents for loose comparisons.
+fn fold(value: &str) -> String {
+    value
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'â' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'î' | 'ï' => 'i',
+            'ô' | 'ö' => 'o',
+            'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            '’' => '\'',
+            other => other,
+        })
+        .collect()
+}
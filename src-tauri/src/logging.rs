@@ -0,0 +1,193 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use anyhow::Result;
+use chrono::Utc;
+use lazy_static::lazy_static;
+use log::{LevelFilter, Log, Metadata, Record};
+use crate::models::{LogEntry, SystemSettings};
+
+const LOG_FILE: &str = "app.log";
+/// Number of rotated files kept next to the active one (`app.log.1` ...).
+const ROTATED_FILES: usize = 4;
+const DEFAULT_MAX_SIZE_MB: u64 = 10;
+
+lazy_static! {
+    static ref LOGGER: FileLogger = FileLogger {
+        inner: Mutex::new(LoggerState {
+            dir: None,
+            file: None,
+            size: 0,
+            max_size: DEFAULT_MAX_SIZE_MB * 1024 * 1024,
+        }),
+    };
+}
+
+struct LoggerState {
+    dir: Option<PathBuf>,
+    file: Option<File>,
+    size: u64,
+    max_size: u64,
+}
+
+struct FileLogger {
+    inner: Mutex<LoggerState>,
+}
+
+impl LoggerState {
+    fn open(&mut self) -> std::io::Result<()> {
+        if let Some(dir) = &self.dir {
+            fs::create_dir_all(dir)?;
+            let path = dir.join(LOG_FILE);
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            self.size = file.metadata()?.len();
+            self.file = Some(file);
+        }
+        Ok(())
+    }
+
+    /// Shifts `app.log` -> `app.log.1` -> ... dropping the oldest file.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file = None;
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_file(rotated_path(dir, ROTATED_FILES));
+            for index in (1..ROTATED_FILES).rev() {
+                let from = rotated_path(dir, index);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(dir, index + 1))?;
+                }
+            }
+            let current = dir.join(LOG_FILE);
+            if current.exists() {
+                fs::rename(&current, rotated_path(dir, 1))?;
+            }
+        }
+        self.open()
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            self.size += line.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let entry = LogEntry {
+            timestamp: Utc::now(),
+            level: record.level().to_string(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+
+        if cfg!(debug_assertions) {
+            eprintln!("[{}] {} {}: {}", entry.timestamp.format("%H:%M:%S"), entry.level, entry.target, entry.message);
+        }
+
+        if let Ok(mut line) = serde_json::to_string(&entry) {
+            line.push('\n');
+            let mut state = self.inner.lock().unwrap();
+            if let Err(e) = state.write_line(&line) {
+                eprintln!("Failed to write log file: {}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.inner.lock().unwrap().file.as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Installs the file logger writing to `dir`. Until settings are applied the
+/// logger records `info` and above.
+pub fn init(dir: PathBuf) {
+    LOGGER.inner.lock().unwrap().dir = Some(dir);
+    if log::set_logger(&*LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Applies `enable_logging`, `log_level` and `max_log_size` (in MB).
+pub fn configure(settings: &SystemSettings) {
+    let level = if settings.enable_logging {
+        parse_level(&settings.log_level)
+    } else {
+        LevelFilter::Off
+    };
+    log::set_max_level(level);
+
+    let max_size_mb = if settings.max_log_size > 0 {
+        settings.max_log_size as u64
+    } else {
+        DEFAULT_MAX_SIZE_MB
+    };
+    LOGGER.inner.lock().unwrap().max_size = max_size_mb * 1024 * 1024;
+}
+
+pub fn log_dir() -> Option<PathBuf> {
+    LOGGER.inner.lock().unwrap().dir.clone()
+}
+
+/// Returns the most recent entries, oldest first, across the active and
+/// rotated log files, optionally keeping only `min_level` and more severe.
+pub fn recent_entries(limit: usize, min_level: Option<&str>) -> Result<Vec<LogEntry>> {
+    let dir = match log_dir() {
+        Some(dir) => dir,
+        None => return Ok(vec![]),
+    };
+    let filter = min_level.map(parse_level).unwrap_or(LevelFilter::Trace);
+    log::logger().flush();
+
+    let mut entries = Vec::new();
+    let mut files: Vec<PathBuf> = (1..=ROTATED_FILES).rev().map(|i| rotated_path(&dir, i)).collect();
+    files.push(dir.join(LOG_FILE));
+
+    for path in files.iter().filter(|p| p.exists()) {
+        let reader = BufReader::new(File::open(path)?);
+        for line in reader.lines() {
+            let line = line?;
+            if let Ok(entry) = serde_json::from_str::<LogEntry>(&line) {
+                if entry.level.parse::<log::Level>().map(|l| l <= filter).unwrap_or(true) {
+                    entries.push(entry);
+                }
+            }
+        }
+    }
+
+    let skip = entries.len().saturating_sub(limit);
+    Ok(entries.split_off(skip))
+}
+
+fn rotated_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{}.{}", LOG_FILE, index))
+}
+
+fn parse_level(level: &str) -> LevelFilter {
+    match level.to_lowercase().as_str() {
+        "error" => LevelFilter::Error,
+        "warn" | "warning" => LevelFilter::Warn,
+        "debug" => LevelFilter::Debug,
+        "trace" => LevelFilter::Trace,
+        _ => LevelFilter::Info,
+    }
+}
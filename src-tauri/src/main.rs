@@ -5,6 +5,20 @@ mod database;
 mod rfid;
 mod models;
 mod commands;
+mod cloud_sync;
+mod system_monitor;
+mod logging;
+mod export;
+mod import;
+mod spreadsheet;
+mod receipts;
+mod arabic;
+mod thermal;
+mod billing;
+mod schedule;
+mod payroll;
+mod rollover;
+mod attachments;
 
 use tauri::{Manager, SystemTray, SystemTrayMenu, SystemTrayMenuItem, CustomMenuItem};
 use database::Database;
@@ -27,6 +41,16 @@ fn create_system_tray() -> SystemTray {
     SystemTray::new().with_menu(tray_menu)
 }
 
+/// Wraps the generated command dispatcher so every invocation is logged.
+fn logged_handler<R: tauri::Runtime>(
+    handler: impl Fn(tauri::Invoke<R>) + Send + Sync + 'static,
+) -> impl Fn(tauri::Invoke<R>) + Send + Sync + 'static {
+    move |invoke| {
+        log::debug!(target: "commands", "{}", invoke.message.command());
+        handler(invoke)
+    }
+}
+
 #[tokio::main]
 async fn main() {
     // Initialize database
@@ -61,7 +85,7 @@ async fn main() {
             },
             _ => {}
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(logged_handler(tauri::generate_handler![
             commands::database::get_students,
             commands::database::create_student,
             commands::database::update_student,
@@ -69,29 +93,193 @@ async fn main() {
             commands::database::get_payments,
             commands::database::create_payment,
             commands::database::update_payment,
+            commands::database::get_receipt_sequence_report,
+            commands::billing::create_payment_plan,
+            commands::billing::get_payment_plans,
+            commands::billing::get_dues,
+            commands::billing::get_outstanding_dues,
+            commands::billing::get_payment_calendar,
+            commands::billing::apply_late_fees,
+            commands::billing::waive_late_fee,
+            commands::billing::get_student_balance,
+            commands::billing::get_student_statement,
+            commands::billing::cancel_payment,
+            commands::billing::refund_payment,
+            commands::billing::get_payment_reversals,
+            commands::billing::create_discount,
+            commands::billing::update_discount,
+            commands::billing::get_discounts,
+            commands::billing::get_discount_lines,
             commands::database::get_attendance,
             commands::database::create_attendance,
             commands::database::get_database_stats,
+            commands::database::optimize_database,
+            commands::database::check_database_integrity,
+            commands::receipts::generate_receipt,
+            commands::receipts::generate_credit_note,
+            commands::printing::print_receipt_ticket,
+            commands::printing::print_daily_closing,
+            commands::printing::print_z_report,
+            commands::cash_register::open_cash_session,
+            commands::cash_register::get_current_cash_session,
+            commands::cash_register::get_cash_sessions,
+            commands::cash_register::close_cash_session,
+            commands::cash_register::get_z_report,
+            commands::cash_register::export_z_report,
+            commands::expenses::create_expense,
+            commands::expenses::update_expense,
+            commands::expenses::delete_expense,
+            commands::expenses::get_expenses,
+            commands::expenses::get_profit_report,
+            commands::classes::create_teacher,
+            commands::classes::update_teacher,
+            commands::classes::get_teachers,
+            commands::classes::delete_teacher,
+            commands::classes::create_subject,
+            commands::classes::update_subject,
+            commands::classes::get_subjects,
+            commands::classes::delete_subject,
+            commands::classes::create_class_group,
+            commands::classes::update_class_group,
+            commands::classes::get_class_groups,
+            commands::classes::delete_class_group,
+            commands::classes::enroll_student,
+            commands::classes::withdraw_enrollment,
+            commands::classes::get_enrollments,
+            commands::timetable::create_timetable_slot,
+            commands::timetable::update_timetable_slot,
+            commands::timetable::get_timetable_slots,
+            commands::timetable::delete_timetable_slot,
+            commands::timetable::generate_week_sessions,
+            commands::timetable::get_session_occurrences,
+            commands::timetable::get_schedule_conflicts,
+            commands::timetable::create_session_occurrence,
+            commands::timetable::cancel_session_occurrence,
+            commands::timetable::reschedule_session_occurrence,
+            commands::attendance::mark_absentees,
+            commands::attendance::get_session_attendance,
+            commands::payroll::create_payroll_rule,
+            commands::payroll::update_payroll_rule,
+            commands::payroll::delete_payroll_rule,
+            commands::payroll::get_payroll_rules,
+            commands::payroll::add_payroll_adjustment,
+            commands::payroll::delete_payroll_adjustment,
+            commands::payroll::get_payslip,
+            commands::payroll::get_payroll,
+            commands::payroll::pay_teacher_salary,
+            commands::payroll::export_payslip,
+            commands::attachments::upload_attachment,
+            commands::attachments::get_attachments,
+            commands::attachments::get_attachment,
+            commands::attachments::delete_attachment,
+            commands::guardians::create_guardian,
+            commands::guardians::update_guardian,
+            commands::guardians::delete_guardian,
+            commands::guardians::get_guardians,
+            commands::guardians::link_guardian,
+            commands::guardians::unlink_guardian,
+            commands::guardians::get_student_guardians,
+            commands::guardians::get_family,
+            commands::guardians::find_family,
+            commands::rollover::preview_year_rollover,
+            commands::rollover::rollover_academic_year,
+            commands::rollover::get_year_rollovers,
+            commands::rooms::create_room,
+            commands::rooms::update_room,
+            commands::rooms::get_rooms,
+            commands::rooms::delete_room,
+            commands::rooms::get_occupancy,
             commands::rfid::scan_rfid_card,
             commands::rfid::connect_rfid_reader,
             commands::rfid::disconnect_rfid_reader,
             commands::rfid::get_available_ports,
             commands::system::export_data,
             commands::system::import_data,
+            commands::system::preview_student_spreadsheet,
+            commands::system::import_student_spreadsheet,
             commands::system::backup_database,
             commands::system::restore_database,
             commands::system::get_system_info,
             commands::system::save_settings,
             commands::system::load_settings,
-        ])
+            commands::backup::sync_backups,
+            commands::backup::upload_backup,
+            commands::backup::list_remote_backups,
+            commands::backup::restore_from_remote,
+            commands::system::get_recent_logs,
+            commands::system::export_logs,
+        ]))
         .setup(|app| {
-            // Initialize the database tables
+            let log_dir = app.path_resolver().app_log_dir().unwrap_or_else(|| "logs".into());
+            logging::init(log_dir);
+
+            // Initialize the database tables, apply the saved logging settings, then run the periodic jobs
             let app_handle = app.handle();
             tauri::async_runtime::spawn(async move {
                 let state: tauri::State<AppState> = app_handle.state();
                 let db = state.db.lock().await;
                 if let Err(e) = db.initialize_tables().await {
-                    eprintln!("Failed to initialize database tables: {}", e);
+                    log::error!(target: "database", "Failed to initialize database tables: {}", e);
+                    return;
+                }
+                match db.load_settings().await {
+                    Ok(Some(settings)) => logging::configure(&settings.system),
+                    Ok(None) => {}
+                    Err(e) => log::error!(target: "database", "Failed to load settings: {}", e),
+                }
+                log::info!("Centre Éducatif Excellence {} started", env!("CARGO_PKG_VERSION"));
+                drop(db);
+
+                // Mark absences a few minutes after each session ends
+                let absences_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5 * 60));
+                    loop {
+                        interval.tick().await;
+
+                        let state: tauri::State<AppState> = absences_handle.state();
+                        let db = state.db.lock().await;
+                        match commands::attendance::run_absence_marking(&db).await {
+                            Ok(absences) if !absences.is_empty() => {
+                                let _ = absences_handle.emit_all("absences-marked", &absences);
+                            }
+                            Ok(_) => {}
+                            Err(e) => log::error!(target: "attendance", "Failed to mark absences: {}", e),
+                        }
+                    }
+                });
+
+                // Publish who is inside the building, like the system stats
+                let occupancy_handle = app_handle.clone();
+                tauri::async_runtime::spawn(async move {
+                    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+                    loop {
+                        interval.tick().await;
+
+                        let state: tauri::State<AppState> = occupancy_handle.state();
+                        let db = state.db.lock().await;
+                        match commands::rooms::current_occupancy(&db).await {
+                            Ok(occupancy) => {
+                                let _ = occupancy_handle.emit_all("occupancy-updated", &occupancy);
+                            }
+                            Err(e) => log::error!(target: "rooms", "Failed to compute occupancy: {}", e),
+                        }
+                    }
+                });
+
+                // Charge late fees now and then once a day
+                let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(24 * 60 * 60));
+                loop {
+                    interval.tick().await;
+
+                    let db = state.db.lock().await;
+                    match commands::billing::run_late_fees(&db).await {
+                        Ok(fees) if !fees.is_empty() => {
+                            let _ = app_handle.emit_all("late-fees-applied", &fees);
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::error!(target: "billing", "Failed to apply late fees: {}", e),
+                    }
                 }
             });
 
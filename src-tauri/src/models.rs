@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
+pub const ACADEMIC_LEVELS: [&str; 6] = ["1ère CEM", "2ème CEM", "3ème CEM", "1ère Lycée", "2ème Lycée", "3ème Lycée"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Student {
     pub id: String,
@@ -17,6 +19,12 @@ pub struct Student {
     pub enrollment_date: DateTime<Utc>,
     pub is_active: bool,
     pub notes: Option<String>,
+    /// Paid sessions not yet attended.
+    #[serde(default)]
+    pub sessions_remaining: i64,
+    /// Attachment shown as the student's photo.
+    #[serde(default)]
+    pub photo_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -33,10 +41,618 @@ pub struct Payment {
     pub status: String,
     pub notes: Option<String>,
     pub receipt_number: Option<String>,
+    #[serde(default)]
+    pub cashier: Option<String>,
+    /// Sessions credited to the student by this payment.
+    #[serde(default)]
+    pub sessions: i64,
+    /// Cash register session the payment was taken in.
+    #[serde(default)]
+    pub cash_session_id: Option<String>,
+    /// Class group the payment is for, when not for the whole level.
+    #[serde(default)]
+    pub group_id: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Monthly fee schedule of a student; its installments are stored as `Due`s.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentPlan {
+    pub id: String,
+    pub student_id: String,
+    pub academic_year: String,
+    pub monthly_fee: f64,
+    pub installments: i64,
+    /// First month billed, `YYYY-MM`.
+    pub start_month: String,
+    pub due_day: i64,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A document kept for a student: photo, scanned certificate, form or
+/// medical note. The file lives in the attachment store under
+/// `content_hash`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attachment {
+    pub id: String,
+    pub student_id: String,
+    pub kind: String,
+    /// Name of the uploaded file, kept for downloads.
+    pub file_name: String,
+    pub content_hash: String,
+    pub extension: String,
+    pub mime_type: String,
+    pub size: i64,
+    pub has_thumbnail: bool,
+    pub notes: Option<String>,
+    pub uploaded_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Where an attachment and its thumbnail are on disk.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachmentFile {
+    pub attachment: Attachment,
+    pub path: String,
+    pub thumbnail_path: Option<String>,
+}
+
+/// A parent or other adult responsible for one or more students.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Guardian {
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    pub secondary_phone: Option<String>,
+    pub email: Option<String>,
+    /// "fr" or "ar", used for messages sent to the family.
+    pub preferred_language: String,
+    pub address: Option<String>,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Link between a guardian and a student. The primary guardian's name and
+/// phone are copied to the student's `parent_name`/`parent_phone`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentGuardian {
+    pub guardian_id: String,
+    pub student_id: String,
+    /// "father", "mother", "tutor", "sibling" or "other".
+    pub relationship: String,
+    pub is_primary: bool,
+    pub can_pick_up: bool,
+    #[serde(default)]
+    pub guardian_name: String,
+    #[serde(default)]
+    pub guardian_phone: String,
+    #[serde(default)]
+    pub student_name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FamilyChild {
+    pub student: Student,
+    pub guardians: Vec<StudentGuardian>,
+    pub balance: StudentBalance,
+}
+
+/// Guardians and children connected through their links, with what the
+/// family owes as a whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Family {
+    pub guardians: Vec<Guardian>,
+    pub children: Vec<FamilyChild>,
+    pub total_balance: f64,
+    pub total_overdue: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Teacher {
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub is_active: bool,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subject {
+    pub id: String,
+    pub name: String,
+    pub code: Option<String>,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A course: one subject taught to one academic level by one teacher, with
+/// at most `capacity` students enrolled.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassGroup {
+    pub id: String,
+    pub name: String,
+    pub academic_level: String,
+    pub subject_id: String,
+    pub teacher_id: Option<String>,
+    pub capacity: i64,
+    pub academic_year: String,
+    pub is_active: bool,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub subject_name: String,
+    #[serde(default)]
+    pub teacher_name: Option<String>,
+    /// Students currently enrolled.
+    #[serde(default)]
+    pub enrolled_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A student's place in a class group; `status` is "active" or "withdrawn".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Enrollment {
+    pub id: String,
+    pub group_id: String,
+    pub student_id: String,
+    #[serde(default)]
+    pub student_name: String,
+    #[serde(default)]
+    pub group_name: String,
+    pub status: String,
+    pub enrolled_at: DateTime<Utc>,
+    pub withdrawn_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+/// Decision for one student at the year rollover, replacing the default
+/// promotion. `outcome` is "promote", "repeat" or "leave";
+/// `academic_level` picks the level a promoted student moves to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromotionOverride {
+    pub student_id: String,
+    pub outcome: String,
+    pub academic_level: Option<String>,
+}
+
+/// What the rollover does to one active student. `outcome` is "promote",
+/// "repeat", "leave", "graduate" after the last level, or "unchanged" for
+/// a level outside `ACADEMIC_LEVELS`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Promotion {
+    pub student_id: String,
+    pub student_name: String,
+    pub from_level: String,
+    pub to_level: String,
+    pub outcome: String,
+}
+
+/// Record of the move from `from_year` to `to_year`. `session_policy` is
+/// "reset" or "carry" for the students' session balances.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YearRollover {
+    pub id: String,
+    pub from_year: String,
+    pub to_year: String,
+    pub session_policy: String,
+    pub promotions: Vec<Promotion>,
+    pub sessions_reset: i64,
+    pub payments_archived: i64,
+    pub attendance_archived: i64,
+    pub groups_archived: i64,
+    pub plans_created: i64,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A room of the building. Timetable slots and sessions refer to it by
+/// name; `capacity` is the fire-safety limit of people in the room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Room {
+    pub id: String,
+    pub name: String,
+    pub capacity: i64,
+    pub is_active: bool,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A weekly slot of a class group's timetable. `weekday` runs from 1
+/// (Monday) to 7 and times are local `HH:MM`; `starts_on` and `ends_on`
+/// bound the weeks it runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimetableSlot {
+    pub id: String,
+    pub group_id: String,
+    pub weekday: u32,
+    pub start_time: String,
+    pub end_time: String,
+    pub room: String,
+    pub teacher_id: Option<String>,
+    pub starts_on: Option<chrono::NaiveDate>,
+    pub ends_on: Option<chrono::NaiveDate>,
+    pub is_active: bool,
+    #[serde(default)]
+    pub group_name: String,
+    #[serde(default)]
+    pub teacher_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One dated session of a group, generated from a timetable slot or added
+/// by hand. `status` is "scheduled" or "cancelled"; a rescheduled session
+/// keeps its first start in `original_starts_at` and the day the timetable
+/// planned it for in `occurrence_date`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionOccurrence {
+    pub id: String,
+    pub slot_id: Option<String>,
+    pub group_id: String,
+    pub teacher_id: Option<String>,
+    pub room: String,
+    pub occurrence_date: chrono::NaiveDate,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub status: String,
+    pub original_starts_at: Option<DateTime<Utc>>,
+    pub cancel_reason: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub group_name: String,
+    #[serde(default)]
+    pub teacher_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A student checked in and not yet checked out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Occupant {
+    pub student_id: String,
+    pub student_name: String,
+    pub group_name: Option<String>,
+    pub room: Option<String>,
+    pub check_in_time: DateTime<Utc>,
+}
+
+/// Who is in one room; `room` is `None` for check-ins outside any session
+/// and `capacity` is `None` for rooms that are not registered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomOccupancy {
+    pub room: Option<String>,
+    pub capacity: Option<i64>,
+    pub occupants: Vec<Occupant>,
+    pub over_capacity: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Occupancy {
+    pub total: i64,
+    pub rooms: Vec<RoomOccupancy>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Two scheduled sessions sharing a room or a teacher at the same time.
+/// `kind` is "room" or "teacher" and `resource` the room or teacher name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleConflict {
+    pub kind: String,
+    pub resource: String,
+    pub first: SessionOccurrence,
+    pub second: SessionOccurrence,
+}
+
+/// An expected payment. `kind` is "installment" for plan dues or
+/// "late_fee" for a penalty attached to `parent_due_id`. `status` is
+/// "unpaid", "partial" or "paid" depending on the payments applied to it,
+/// or "waived" for a cancelled late fee.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Due {
+    pub id: String,
+    pub plan_id: Option<String>,
+    pub kind: String,
+    pub parent_due_id: Option<String>,
+    pub student_id: String,
+    pub student_name: String,
+    pub academic_level: String,
+    /// Billed month, `YYYY-MM`.
+    pub month: String,
+    pub due_date: DateTime<Utc>,
+    /// Amount owed once discounts are taken off.
+    pub amount: f64,
+    /// Total of the discounts taken off this due.
+    #[serde(default)]
+    pub discount_amount: f64,
+    pub paid_amount: f64,
+    pub status: String,
+    pub last_payment_date: Option<DateTime<Utc>>,
+    pub last_payment_method: Option<String>,
+    pub waived_by: Option<String>,
+    pub waived_at: Option<DateTime<Utc>>,
+    pub waive_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A reduction applied to monthly dues. `scope` is "student", "family" or
+/// "level" and `target` the student id, parent phone or academic level it
+/// applies to. A family discount without target applies to every family
+/// with at least `min_siblings` active children. `kind` is "percent" or
+/// "fixed"; `starts_on` and `ends_on` bound the due dates it covers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discount {
+    pub id: String,
+    pub name: String,
+    pub scope: String,
+    pub target: Option<String>,
+    pub kind: String,
+    pub value: f64,
+    #[serde(default)]
+    pub min_siblings: i64,
+    pub starts_on: Option<chrono::NaiveDate>,
+    pub ends_on: Option<chrono::NaiveDate>,
+    pub is_active: bool,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A discount taken off one due, kept as its own line for statements.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DueDiscount {
+    pub id: String,
+    pub due_id: String,
+    pub discount_id: Option<String>,
+    pub student_id: String,
+    pub student_name: String,
+    pub academic_level: String,
+    pub month: String,
+    pub due_date: DateTime<Utc>,
+    pub label: String,
+    pub amount: f64,
+}
+
+/// Dues falling on one day of the payment calendar.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalendarDay {
+    pub date: chrono::NaiveDate,
+    pub expected_amount: f64,
+    pub paid_amount: f64,
+    pub dues: Vec<Due>,
+}
+
+/// Reversal of all or part of a payment. The payment row is kept; a
+/// "cancellation" voids it entirely, a "refund" gives money back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentReversal {
+    pub id: String,
+    pub payment_id: String,
+    pub kind: String,
+    pub amount: f64,
+    pub sessions: i64,
+    pub refund_method: Option<String>,
+    pub reason: String,
+    pub created_by: String,
+    pub credit_note_number: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A cash drawer shift, "open" or "closed". Payments and refunds made
+/// while it is open are recorded against it; once closed its payments can
+/// no longer be edited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CashSession {
+    pub id: String,
+    pub opened_by: String,
+    pub opened_at: DateTime<Utc>,
+    pub opening_float: f64,
+    pub status: String,
+    pub closed_by: Option<String>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub notes: Option<String>,
+}
+
+/// Money of one payment method over a cash session. `expected` includes
+/// the opening float for cash; `counted` is set at closing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CashSessionTotal {
+    pub payment_method: String,
+    pub payment_count: i64,
+    pub collected: f64,
+    pub refunded: f64,
+    pub expected: f64,
+    pub counted: Option<f64>,
+    /// Counted minus expected; negative when money is missing.
+    pub difference: Option<f64>,
+}
+
+/// Closing summary of a cash session (Z-report).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZReport {
+    pub session: CashSession,
+    pub totals: Vec<CashSessionTotal>,
+    pub payment_count: i64,
+    pub reversal_count: i64,
+    pub first_receipt: Option<String>,
+    pub last_receipt: Option<String>,
+}
+
+/// Money spent by the centre. `category` is free text, typically "rent",
+/// "salaries", "supplies" or "utilities"; `receipt_path` is the copy of the
+/// supplier's receipt kept in the app data folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Expense {
+    pub id: String,
+    pub category: String,
+    pub description: String,
+    pub amount: f64,
+    pub expense_date: DateTime<Utc>,
+    pub payment_method: String,
+    pub supplier: Option<String>,
+    pub receipt_path: Option<String>,
+    pub notes: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// How a teacher is paid: `mode` "per_session" pays `rate` per session
+/// held, "percent" pays `rate` percent of the group's collected revenue.
+/// A rule without `group_id` applies to every group of the teacher that
+/// has no rule of its own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayrollRule {
+    pub id: String,
+    pub teacher_id: String,
+    pub group_id: Option<String>,
+    pub mode: String,
+    pub rate: f64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Bonus (positive) or deduction (negative) added to a teacher's pay for
+/// `month` (`YYYY-MM`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayrollAdjustment {
+    pub id: String,
+    pub teacher_id: String,
+    pub month: String,
+    pub label: String,
+    pub amount: f64,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Pay earned with one group during the month.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayslipLine {
+    pub group_id: String,
+    pub group_name: String,
+    /// "per_session", "percent", or "none" when no rule covers the group.
+    pub mode: String,
+    pub rate: f64,
+    pub sessions: i64,
+    pub revenue: f64,
+    pub amount: f64,
+}
+
+/// A salary paid, recorded as the expense `expense_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayrollPayment {
+    pub id: String,
+    pub teacher_id: String,
+    pub month: String,
+    pub amount: f64,
+    pub payment_method: String,
+    pub expense_id: String,
+    pub paid_by: Option<String>,
+    pub paid_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payslip {
+    pub teacher_id: String,
+    pub teacher_name: String,
+    pub month: String,
+    pub lines: Vec<PayslipLine>,
+    pub adjustments: Vec<PayrollAdjustment>,
+    pub gross: f64,
+    pub adjustments_total: f64,
+    pub net: f64,
+    pub payment: Option<PayrollPayment>,
+}
+
+/// Income and spending of one month. `revenue` is net of refunds and
+/// cancellations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlyProfit {
+    pub month: String,
+    pub revenue: f64,
+    pub refunds: f64,
+    pub expenses: f64,
+    pub net_profit: f64,
+}
+
+/// Total of one category in a month; `kind` is "revenue" (by payment type)
+/// or "expense".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryTotal {
+    pub month: String,
+    pub kind: String,
+    pub category: String,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfitReport {
+    pub months: Vec<MonthlyProfit>,
+    pub categories: Vec<CategoryTotal>,
+    pub total_revenue: f64,
+    pub total_expenses: f64,
+    pub net_profit: f64,
+}
+
+/// One line of a student's account. `debit` increases what the student
+/// owes (charges, late fees), `credit` decreases it (payments, discounts,
+/// waivers).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub date: DateTime<Utc>,
+    pub entry_type: String,
+    pub description: String,
+    pub reference: Option<String>,
+    pub due_id: Option<String>,
+    pub payment_id: Option<String>,
+    pub debit: f64,
+    pub credit: f64,
+    /// Amount owed after this line; negative when the student is in credit.
+    pub balance: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentBalance {
+    pub student_id: String,
+    pub total_charged: f64,
+    pub total_paid: f64,
+    /// Cancelled and refunded amounts.
+    pub total_reversed: f64,
+    pub total_discounts: f64,
+    /// Discounts, waivers and other credits besides payments.
+    pub total_credited: f64,
+    /// Owed as of today; negative when the student is in credit.
+    pub balance: f64,
+    /// Part of `balance` on dues past their due date.
+    pub overdue: f64,
+    /// Dues scheduled after today, not yet part of the balance.
+    pub upcoming: f64,
+    /// Paid amounts not yet applied to any due.
+    pub unallocated: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentStatement {
+    pub student_id: String,
+    pub student_name: String,
+    pub academic_level: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: DateTime<Utc>,
+    pub opening_balance: f64,
+    pub entries: Vec<LedgerEntry>,
+    pub closing_balance: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Attendance {
     pub id: String,
@@ -46,6 +662,12 @@ pub struct Attendance {
     pub check_in_time: Option<DateTime<Utc>>,
     pub check_out_time: Option<DateTime<Utc>>,
     pub notes: Option<String>,
+    /// Class group the student attended, when known.
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// Scheduled session the attendance was taken for.
+    #[serde(default)]
+    pub occurrence_id: Option<String>,
     pub created_at: DateTime<Utc>,
 }
 
@@ -56,6 +678,18 @@ pub struct DatabaseStats {
     pub total_payments: i64,
     pub total_attendance: i64,
     pub database_size: i64,
+    pub wal_size: i64,
+    pub table_counts: Vec<TableCount>,
+    pub last_backup_time: Option<DateTime<Utc>>,
+    pub integrity_check: String,
+    pub freelist_ratio: f64,
+    pub maintenance_recommended: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableCount {
+    pub table: String,
+    pub row_count: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +700,23 @@ pub struct SystemInfo {
     pub memory_usage: f32,
     pub disk_usage: f32,
     pub uptime: String,
+    pub system_uptime: String,
+    pub database_size: u64,
+    pub rfid: RfidStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RfidStatus {
+    pub connected: bool,
+    pub port_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub level: String,
+    pub target: String,
+    pub message: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +726,88 @@ pub struct RfidScanResult {
     pub scan_time: DateTime<Utc>,
     pub success: bool,
     pub message: String,
+    /// Session the scan was matched to and the attendance it recorded.
+    #[serde(default)]
+    pub session: Option<SessionOccurrence>,
+    #[serde(default)]
+    pub attendance: Option<Attendance>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteBackup {
+    pub name: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub uploaded: Vec<String>,
+    pub already_synced: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Attachment files uploaded along with the backups.
+    pub attachments_uploaded: usize,
+    pub errors: Vec<String>,
+}
+
+/// Receipt numbers allocated for one academic year and the numbers that
+/// have no payment attached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptSequenceReport {
+    pub academic_year: String,
+    pub last_number: i64,
+    pub issued: i64,
+    pub missing: Vec<i64>,
+    pub first_receipt: Option<String>,
+    pub last_receipt: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub data_type: String,
+    pub dry_run: bool,
+    pub committed: bool,
+    pub total_rows: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub duplicates: usize,
+    pub rows: Vec<ImportRowResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowResult {
+    pub row: usize,
+    pub id: Option<String>,
+    pub action: String,
+    pub duplicate: bool,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpreadsheetPreview {
+    pub sheet_names: Vec<String>,
+    pub headers: Vec<String>,
+    pub sample_rows: Vec<Vec<String>>,
+    pub total_rows: usize,
+    /// Suggested spreadsheet header -> `Student` field mapping.
+    pub suggested_mapping: std::collections::HashMap<String, String>,
+}
+
+/// Column selection and filters sent by the reports screen with an export.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub columns: Vec<String>,
+    pub academic_level: Option<String>,
+    pub status: Option<String>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub active_only: Option<bool>,
+    pub sheet_per_level: bool,
+    pub csv_delimiter: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -84,6 +817,80 @@ pub struct AppSettings {
     pub payments: PaymentSettings,
     pub backup: BackupSettings,
     pub system: SystemSettings,
+    #[serde(default)]
+    pub printer: PrinterSettings,
+    #[serde(default)]
+    pub attendance: AttendanceSettings,
+}
+
+/// Same defaults as the settings page shows before anything is saved.
+impl Default for AppSettings {
+    fn default() -> Self {
+        let default_session_price = [
+            ("1ère CEM", 2500.0),
+            ("2ème CEM", 3000.0),
+            ("3ème CEM", 3500.0),
+            ("1ère Lycée", 4000.0),
+            ("2ème Lycée", 4500.0),
+            ("3ème Lycée", 5000.0),
+        ]
+        .into_iter()
+        .map(|(level, price)| (level.to_string(), price))
+        .collect();
+
+        AppSettings {
+            general: GeneralSettings {
+                school_name: "Centre Éducatif Excellence".to_string(),
+                school_address: "123 Rue de l'Éducation, Alger".to_string(),
+                school_phone: "021-123-456".to_string(),
+                school_email: "contact@centre-excellence.dz".to_string(),
+                academic_year: "2023-2024".to_string(),
+                language: "fr".to_string(),
+                theme: "system".to_string(),
+                currency: "DZD".to_string(),
+                timezone: "Africa/Algiers".to_string(),
+            },
+            rfid: RfidSettings {
+                reader_type: "JT308".to_string(),
+                com_port: "COM3".to_string(),
+                baud_rate: 9600,
+                auto_connect: true,
+                scan_timeout: 5000,
+                enable_sound: true,
+                enable_led: true,
+            },
+            payments: PaymentSettings {
+                default_session_price,
+                late_fee_amount: 500.0,
+                late_fee_after_days: 7,
+                allow_partial_payments: true,
+                require_payment_notes: false,
+                auto_generate_receipts: true,
+                receipt_template: "default".to_string(),
+                receipt_prefix: String::new(),
+                require_cash_session: false,
+            },
+            backup: BackupSettings {
+                auto_backup: true,
+                backup_interval: "daily".to_string(),
+                backup_location: "./backups".to_string(),
+                keep_backups: 30,
+                cloud_sync: false,
+                cloud_provider: "google".to_string(),
+                cloud: CloudSyncSettings::default(),
+            },
+            system: SystemSettings {
+                enable_logging: true,
+                log_level: "info".to_string(),
+                max_log_size: 10,
+                enable_updates: true,
+                update_channel: "stable".to_string(),
+                enable_telemetry: false,
+            },
+            printer: PrinterSettings::default(),
+            attendance: AttendanceSettings::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -119,6 +926,12 @@ pub struct PaymentSettings {
     pub require_payment_notes: bool,
     pub auto_generate_receipts: bool,
     pub receipt_template: String,
+    /// Text put before `<year>-<number>` in receipt numbers.
+    #[serde(default)]
+    pub receipt_prefix: String,
+    /// Refuse payments while no cash register session is open.
+    #[serde(default)]
+    pub require_cash_session: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -129,6 +942,72 @@ pub struct BackupSettings {
     pub keep_backups: i32,
     pub cloud_sync: bool,
     pub cloud_provider: String,
+    #[serde(default)]
+    pub cloud: CloudSyncSettings,
+}
+
+/// Connection details for the off-site backup target selected by
+/// `BackupSettings::cloud_provider` ("s3" or "webdav").
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CloudSyncSettings {
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub username: String,
+    pub password: String,
+    pub remote_path: String,
+}
+
+/// ESC/POS receipt printer. `connection` is "serial" for a (USB-)serial
+/// port or "file" for a raw device path written to directly.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrinterSettings {
+    pub enabled: bool,
+    pub connection: String,
+    pub device: String,
+    pub baud_rate: u32,
+    pub paper_width_mm: u32,
+    pub logo_path: Option<String>,
+    pub print_on_payment: bool,
+}
+
+impl Default for PrinterSettings {
+    fn default() -> Self {
+        PrinterSettings {
+            enabled: false,
+            connection: "serial".to_string(),
+            device: String::new(),
+            baud_rate: 9600,
+            paper_width_mm: 80,
+            logo_path: None,
+            print_on_payment: false,
+        }
+    }
+}
+
+/// How card scans are matched to scheduled sessions. A scan counts for a
+/// session from `check_in_window_minutes` before it starts until it ends,
+/// and is late after `grace_minutes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttendanceSettings {
+    pub grace_minutes: i64,
+    pub check_in_window_minutes: i64,
+    /// Mark enrolled students who never scanned as absent once a session ends.
+    pub auto_mark_absent: bool,
+}
+
+impl Default for AttendanceSettings {
+    fn default() -> Self {
+        AttendanceSettings {
+            grace_minutes: 10,
+            check_in_window_minutes: 30,
+            auto_mark_absent: true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
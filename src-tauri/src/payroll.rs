@@ -0,0 +1,198 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::billing;
+use crate::models::{PayrollAdjustment, PayrollPayment, PayrollRule, Payslip, PayslipLine, Teacher};
+
+/// Sessions a teacher held with one group in a month and their share of
+/// the revenue the group brought in, net of refunds.
+pub struct GroupActivity {
+    pub group_id: String,
+    pub group_name: String,
+    pub sessions: i64,
+    pub revenue: f64,
+}
+
+/// Checks a payroll rule before it is saved.
+pub fn validate_rule(rule: &PayrollRule) -> Result<()> {
+    match rule.mode.as_str() {
+        "percent" if rule.rate > 100.0 => return Err(anyhow!("A revenue share cannot exceed 100 %")),
+        "per_session" | "percent" => {}
+        other => return Err(anyhow!("Unknown payroll mode {}", other)),
+    }
+    if rule.rate <= 0.0 {
+        return Err(anyhow!("The payroll rate must be positive"));
+    }
+    Ok(())
+}
+
+/// Start and end (exclusive) of the local month `YYYY-MM`.
+pub fn month_bounds(month: &str) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid month {}, expected YYYY-MM", month))?;
+    let next = first
+        .checked_add_months(chrono::Months::new(1))
+        .ok_or_else(|| anyhow!("Month {} is out of range", month))?;
+    Ok((billing::local_midnight(first), billing::local_midnight(next)))
+}
+
+/// Pay of `teacher` for `month`. Each group uses its own rule, else the
+/// teacher's general one; a group without any rule earns nothing.
+pub fn payslip(
+    teacher: &Teacher,
+    month: &str,
+    rules: &[PayrollRule],
+    activity: &[GroupActivity],
+    adjustments: Vec<PayrollAdjustment>,
+    payment: Option<PayrollPayment>,
+) -> Payslip {
+    let lines: Vec<PayslipLine> = activity
+        .iter()
+        .map(|group| {
+            let rule = rules
+                .iter()
+                .find(|r| r.group_id.as_deref() == Some(group.group_id.as_str()))
+                .or_else(|| rules.iter().find(|r| r.group_id.is_none()));
+            let (mode, rate, amount) = match rule {
+                Some(r) if r.mode == "per_session" => (r.mode.clone(), r.rate, group.sessions as f64 * r.rate),
+                Some(r) => (r.mode.clone(), r.rate, (group.revenue * r.rate).round() / 100.0),
+                None => ("none".to_string(), 0.0, 0.0),
+            };
+
+            PayslipLine {
+                group_id: group.group_id.clone(),
+                group_name: group.group_name.clone(),
+                mode,
+                rate,
+                sessions: group.sessions,
+                revenue: group.revenue,
+                amount,
+            }
+        })
+        .collect();
+
+    let gross = lines.iter().map(|l| l.amount).fold(0.0, |a, b| a + b);
+    let adjustments_total = adjustments.iter().map(|a| a.amount).fold(0.0, |a, b| a + b);
+
+    Payslip {
+        teacher_id: teacher.id.clone(),
+        teacher_name: format!("{} {}", teacher.first_name, teacher.last_name),
+        month: month.to_string(),
+        lines,
+        adjustments,
+        gross,
+        adjustments_total,
+        net: gross + adjustments_total,
+        payment,
+    }
+}
+
+/// Label of a payslip line, e.g. "Maths 3ème - 8 séance(s) × 1 500,00".
+pub fn line_label(line: &PayslipLine) -> String {
+    match line.mode.as_str() {
+        "per_session" => format!(
+            "{} - {} séance(s) × {}",
+            line.group_name,
+            line.sessions,
+            crate::receipts::format_amount(line.rate)
+        ),
+        "percent" => format!(
+            "{} - {} % de {}",
+            line.group_name,
+            line.rate,
+            crate::receipts::format_amount(line.revenue)
+        ),
+        _ => format!("{} - aucune règle de rémunération", line.group_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn teacher() -> Teacher {
+        Teacher {
+            id: "t1".to_string(),
+            first_name: "Karim".to_string(),
+            last_name: "Haddad".to_string(),
+            phone: None,
+            email: None,
+            is_active: true,
+            notes: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn rule(group_id: Option<&str>, mode: &str, rate: f64) -> PayrollRule {
+        PayrollRule {
+            id: format!("{}-{}", mode, group_id.unwrap_or("all")),
+            teacher_id: "t1".to_string(),
+            group_id: group_id.map(str::to_string),
+            mode: mode.to_string(),
+            rate,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn activity(group_id: &str, sessions: i64, revenue: f64) -> GroupActivity {
+        GroupActivity {
+            group_id: group_id.to_string(),
+            group_name: format!("Groupe {}", group_id),
+            sessions,
+            revenue,
+        }
+    }
+
+    fn adjustment(label: &str, amount: f64) -> PayrollAdjustment {
+        PayrollAdjustment {
+            id: label.to_string(),
+            teacher_id: "t1".to_string(),
+            month: "2026-10".to_string(),
+            label: label.to_string(),
+            amount,
+            created_by: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn payslip_prefers_the_group_rule() {
+        let rules = [rule(None, "per_session", 1500.0), rule(Some("g2"), "percent", 30.0)];
+        let groups = [activity("g1", 8, 40000.0), activity("g2", 4, 12345.0)];
+
+        let payslip = payslip(&teacher(), "2026-10", &rules, &groups, vec![], None);
+        let lines: Vec<(&str, &str, f64)> = payslip.lines.iter().map(|l| (l.group_id.as_str(), l.mode.as_str(), l.amount)).collect();
+        assert_eq!(lines, [("g1", "per_session", 12000.0), ("g2", "percent", 3703.5)]);
+        assert_eq!(payslip.gross, 15703.5);
+        assert_eq!(payslip.teacher_name, "Karim Haddad");
+    }
+
+    #[test]
+    fn payslip_without_rule_earns_nothing() {
+        let rules = [rule(Some("g1"), "per_session", 1000.0)];
+        let groups = [activity("g1", 2, 0.0), activity("g2", 6, 30000.0)];
+
+        let payslip = payslip(&teacher(), "2026-10", &rules, &groups, vec![], None);
+        assert_eq!((payslip.lines[1].mode.as_str(), payslip.lines[1].amount), ("none", 0.0));
+        assert_eq!(payslip.gross, 2000.0);
+    }
+
+    #[test]
+    fn payslip_adds_adjustments_to_the_gross() {
+        let rules = [rule(None, "per_session", 1000.0)];
+        let groups = [activity("g1", 10, 0.0)];
+        let adjustments = vec![adjustment("Prime", 2500.0), adjustment("Avance", -4000.0)];
+
+        let payslip = payslip(&teacher(), "2026-10", &rules, &groups, adjustments, None);
+        assert_eq!((payslip.gross, payslip.adjustments_total, payslip.net), (10000.0, -1500.0, 8500.0));
+    }
+
+    #[test]
+    fn month_bounds_cover_the_whole_month() {
+        let (start, end) = month_bounds("2026-02").unwrap();
+        assert_eq!(end - start, chrono::Duration::days(28));
+        assert!(month_bounds("2026-2").is_ok());
+        assert!(month_bounds("février").is_err());
+    }
+}
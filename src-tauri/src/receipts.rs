@@ -0,0 +1,515 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, Utc};
+use crate::arabic;
+use crate::models::Payslip;
+use printpdf::{IndirectFontRef, Line, Mm, PdfDocument, PdfLayerReference, Point};
+
+/// Built-in receipt layouts selectable through `PaymentSettings::receipt_template`.
+/// Any other value containing placeholders is used as a custom template.
+///
+/// Template lines: `# text` is a title, `---` a separator, `Label | value`
+/// a two-column row and an empty line leaves a gap. Placeholders are
+/// replaced by `ReceiptData::placeholders`; a `{details}` line expands to
+/// one row per `ReceiptData::details` entry.
+const DEFAULT_TEMPLATE: &str = "# REÇU DE PAIEMENT
+N° de reçu | {receipt_number}
+Date | {payment_date}
+---
+Élève | {student_name}
+Niveau | {academic_level}
+Objet | {payment_type}
+Mode de paiement | {payment_method}
+{details}
+---
+# Montant : {amount} {currency}
+Arrêté la présente somme à : {amount_words}
+---
+Caissier | {cashier}
+Notes | {notes}
+
+Cachet et signature";
+
+const COMPACT_TEMPLATE: &str = "# REÇU {receipt_number}
+Date | {payment_date}
+Élève | {student_name}
+Objet | {payment_type}
+Mode | {payment_method}
+{details}
+---
+# {amount} {currency}
+{amount_words}
+Caissier | {cashier}";
+
+const CREDIT_NOTE_TEMPLATE: &str = "# AVOIR {credit_note_number}
+Date | {reversal_date}
+Opération | {reversal_type}
+---
+Reçu d'origine | {receipt_number}
+Date du paiement | {payment_date}
+Élève | {student_name}
+Niveau | {academic_level}
+Montant du reçu | {amount} {currency}
+---
+# Montant de l'avoir : {reversal_amount} {currency}
+Arrêté le présent avoir à : {reversal_amount_words}
+---
+Motif | {reason}
+Mode de remboursement | {refund_method}
+Établi par | {created_by}
+
+Cachet et signature";
+
+const PAYSLIP_TEMPLATE: &str = "# BULLETIN DE PAIE
+Période | {month}
+Enseignant | {teacher_name}
+---
+{details}
+---
+Total brut | {gross} {currency}
+Ajustements | {adjustments_total} {currency}
+# Net à payer : {net} {currency}
+Arrêté la présente somme à : {net_words}
+---
+Payé le | {paid_at}
+Mode de paiement | {payment_method}
+
+Signature";
+
+const MARGIN: f32 = 12.0;
+const PT_TO_MM: f32 = 0.3528;
+
+pub struct ReceiptData {
+    pub school_name: String,
+    pub school_address: String,
+    pub school_phone: String,
+    pub school_email: String,
+    pub title: String,
+    pub receipt_number: String,
+    pub payment_date: DateTime<Utc>,
+    pub student_name: String,
+    pub academic_level: String,
+    pub amount: f64,
+    pub currency: String,
+    pub payment_type: String,
+    pub payment_method: String,
+    pub cashier: Option<String>,
+    pub notes: Option<String>,
+    /// Dues settled by the payment, each followed by its discounts as
+    /// negative amounts.
+    pub details: Vec<(String, f64)>,
+}
+
+impl ReceiptData {
+    fn header(&self) -> [&str; 4] {
+        [&self.school_name, &self.school_address, &self.school_phone, &self.school_email]
+    }
+
+    fn placeholders(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("{school_name}", self.school_name.clone()),
+            ("{school_address}", self.school_address.clone()),
+            ("{school_phone}", self.school_phone.clone()),
+            ("{school_email}", self.school_email.clone()),
+            ("{receipt_number}", self.receipt_number.clone()),
+            ("{payment_date}", self.payment_date.with_timezone(&Local).format("%d/%m/%Y %H:%M").to_string()),
+            ("{student_name}", self.student_name.clone()),
+            ("{academic_level}", self.academic_level.clone()),
+            ("{amount}", format_amount(self.amount)),
+            ("{currency}", self.currency.clone()),
+            ("{amount_words}", amount_in_words(self.amount, &self.currency)),
+            ("{payment_type}", self.payment_type.clone()),
+            ("{payment_method}", payment_method_label(&self.payment_method).to_string()),
+            ("{cashier}", self.cashier.clone().unwrap_or_else(|| "-".to_string())),
+            ("{notes}", self.notes.clone().unwrap_or_else(|| "-".to_string())),
+        ]
+    }
+}
+
+/// Credit note issued when a payment is cancelled or refunded.
+pub struct CreditNoteData {
+    pub receipt: ReceiptData,
+    pub credit_note_number: String,
+    pub kind: String,
+    pub amount: f64,
+    pub refund_method: Option<String>,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CreditNoteData {
+    fn placeholders(&self) -> Vec<(&'static str, String)> {
+        let mut placeholders = self.receipt.placeholders();
+        let kind = match self.kind.as_str() {
+            "cancellation" => "Annulation de paiement",
+            "refund" => "Remboursement",
+            other => other,
+        };
+        placeholders.extend([
+            ("{credit_note_number}", self.credit_note_number.clone()),
+            ("{reversal_date}", self.created_at.with_timezone(&Local).format("%d/%m/%Y %H:%M").to_string()),
+            ("{reversal_type}", kind.to_string()),
+            ("{reversal_amount}", format_amount(self.amount)),
+            ("{reversal_amount_words}", amount_in_words(self.amount, &self.receipt.currency)),
+            ("{reason}", self.reason.clone()),
+            (
+                "{refund_method}",
+                self.refund_method.as_deref().map(payment_method_label).unwrap_or("-").to_string(),
+            ),
+            ("{created_by}", self.created_by.clone()),
+        ]);
+        placeholders
+    }
+}
+
+/// Monthly payslip of a teacher; `details` holds one row per group and
+/// adjustment.
+pub struct PayslipData {
+    pub school_name: String,
+    pub school_address: String,
+    pub school_phone: String,
+    pub school_email: String,
+    pub currency: String,
+    pub payslip: Payslip,
+    pub details: Vec<(String, f64)>,
+}
+
+impl PayslipData {
+    fn placeholders(&self) -> Vec<(&'static str, String)> {
+        let payment = self.payslip.payment.as_ref();
+        vec![
+            ("{month}", self.payslip.month.clone()),
+            ("{teacher_name}", self.payslip.teacher_name.clone()),
+            ("{gross}", format_amount(self.payslip.gross)),
+            ("{adjustments_total}", format_amount(self.payslip.adjustments_total)),
+            ("{net}", format_amount(self.payslip.net)),
+            ("{net_words}", amount_in_words(self.payslip.net, &self.currency)),
+            ("{currency}", self.currency.clone()),
+            (
+                "{paid_at}",
+                payment
+                    .map(|p| p.paid_at.with_timezone(&Local).format("%d/%m/%Y").to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+            (
+                "{payment_method}",
+                payment.map(|p| payment_method_label(&p.payment_method)).unwrap_or("-").to_string(),
+            ),
+        ]
+    }
+}
+
+/// DejaVu Sans covers French as well as the Arabic contextual letter forms,
+/// so student and parent names print in either script.
+const REGULAR_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+const BOLD_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans-Bold.ttf");
+
+/// Page size in millimetres and template text for a `receipt_template` value.
+fn template(name: &str) -> ((f32, f32), &str) {
+    match name {
+        "compact" => ((105.0, 148.0), COMPACT_TEMPLATE),
+        custom if custom.contains('{') => ((148.0, 210.0), custom),
+        _ => ((148.0, 210.0), DEFAULT_TEMPLATE),
+    }
+}
+
+struct Page<'a> {
+    layer: PdfLayerReference,
+    regular: &'a IndirectFontRef,
+    bold: &'a IndirectFontRef,
+    width: f32,
+    y: f32,
+}
+
+impl<'a> Page<'a> {
+    fn text(&mut self, text: &str, size: f32, bold: bool) {
+        let font = if bold { self.bold } else { self.regular };
+        for line in wrap(text, chars_per_line(self.width - 2.0 * MARGIN, size)) {
+            self.y -= size * PT_TO_MM * 1.4;
+            self.layer.use_text(pdf_text(&line), size, Mm(MARGIN), Mm(self.y), font);
+        }
+    }
+
+    fn row(&mut self, label: &str, value: &str) {
+        let label_width = (self.width - 2.0 * MARGIN) * 0.38;
+        let value_width = self.width - 2.0 * MARGIN - label_width;
+        let lines = wrap(value, chars_per_line(value_width, 10.0));
+
+        self.y -= 10.0 * PT_TO_MM * 1.6;
+        self.layer.use_text(pdf_text(label), 10.0, Mm(MARGIN), Mm(self.y), self.bold);
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                self.y -= 10.0 * PT_TO_MM * 1.4;
+            }
+            self.layer.use_text(pdf_text(line), 10.0, Mm(MARGIN + label_width), Mm(self.y), self.regular);
+        }
+    }
+
+    fn separator(&mut self) {
+        self.y -= 3.0;
+        self.layer.set_outline_thickness(0.5);
+        self.layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(MARGIN), Mm(self.y)), false),
+                (Point::new(Mm(self.width - MARGIN), Mm(self.y)), false),
+            ],
+            is_closed: false,
+        });
+        self.y -= 1.0;
+    }
+}
+
+/// Renders a receipt to `path`.
+pub fn render_pdf(data: &ReceiptData, template_name: &str, path: &Path) -> Result<()> {
+    let (size, template) = template(template_name);
+    render(data.header(), &data.details, &data.title, size, template, &data.placeholders(), path)
+}
+
+/// Renders a credit note to `path`, on the same page format as receipts.
+pub fn render_credit_note(data: &CreditNoteData, path: &Path) -> Result<()> {
+    let title = format!("Avoir {}", data.credit_note_number);
+    let receipt = &data.receipt;
+    render(receipt.header(), &receipt.details, &title, (148.0, 210.0), CREDIT_NOTE_TEMPLATE, &data.placeholders(), path)
+}
+
+/// Renders a payslip to `path` on an A4 page.
+pub fn render_payslip(data: &PayslipData, path: &Path) -> Result<()> {
+    let title = format!("Bulletin de paie {} {}", data.payslip.teacher_name, data.payslip.month);
+    let header = [
+        data.school_name.as_str(),
+        data.school_address.as_str(),
+        data.school_phone.as_str(),
+        data.school_email.as_str(),
+    ];
+    render(header, &data.details, &title, (210.0, 297.0), PAYSLIP_TEMPLATE, &data.placeholders(), path)
+}
+
+/// `header` holds the school name, address, phone and e-mail.
+fn render(
+    header: [&str; 4],
+    details: &[(String, f64)],
+    title: &str,
+    (width, height): (f32, f32),
+    template: &str,
+    placeholders: &[(&'static str, String)],
+    path: &Path,
+) -> Result<()> {
+    let (document, page, layer) = PdfDocument::new(title, Mm(width), Mm(height), "Reçu");
+    let regular = document.add_external_font(REGULAR_FONT).map_err(|e| anyhow!("{}", e))?;
+    let bold = document.add_external_font(BOLD_FONT).map_err(|e| anyhow!("{}", e))?;
+
+    let mut page = Page {
+        layer: document.get_page(page).get_layer(layer),
+        regular: &regular,
+        bold: &bold,
+        width,
+        y: height - MARGIN + 4.0,
+    };
+
+    let [school_name, contact @ ..] = header;
+    page.text(school_name, 16.0, true);
+    for detail in contact {
+        if !detail.is_empty() {
+            page.text(detail, 9.0, false);
+        }
+    }
+    page.separator();
+
+    for raw_line in template.lines() {
+        if raw_line.trim() == "{details}" {
+            for (label, amount) in details {
+                page.row(label, &format_amount(*amount));
+            }
+            continue;
+        }
+
+        let mut line = raw_line.trim_end().to_string();
+        for (key, value) in placeholders {
+            line = line.replace(key, value);
+        }
+
+        if line.trim() == "---" {
+            page.separator();
+        } else if let Some(title) = line.strip_prefix("# ") {
+            page.y -= 2.0;
+            page.text(title, 13.0, true);
+        } else if let Some((label, value)) = line.split_once(" | ") {
+            page.row(label.trim(), value.trim());
+        } else if line.trim().is_empty() {
+            page.y -= 5.0;
+        } else {
+            page.text(&line, 10.0, false);
+        }
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    document
+        .save(&mut BufWriter::new(File::create(path)?))
+        .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// File name for a receipt number, without characters forbidden on Windows.
+pub fn file_name(receipt_number: &str) -> String {
+    let clean: String = receipt_number
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}.pdf", clean)
+}
+
+pub fn payment_method_label(method: &str) -> &str {
+    match method {
+        "cash" => "Espèces",
+        "bank" => "Virement bancaire",
+        "check" => "Chèque",
+        other => other,
+    }
+}
+
+/// `1500.5` -> `1 500,50`
+pub fn format_amount(amount: f64) -> String {
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let units = (cents / 100).to_string();
+
+    let mut grouped = String::new();
+    for (index, digit) in units.chars().enumerate() {
+        if index > 0 && (units.len() - index) % 3 == 0 {
+            grouped.push(' ');
+        }
+        grouped.push(digit);
+    }
+
+    let sign = if amount < 0.0 && cents > 0 { "-" } else { "" };
+    format!("{}{},{:02}", sign, grouped, cents % 100)
+}
+
+/// Amount spelled out in French, e.g. `2500` -> `deux mille cinq cents dinars`.
+pub fn amount_in_words(amount: f64, currency: &str) -> String {
+    let cents = (amount.abs() * 100.0).round() as u64;
+    let (units, cents) = (cents / 100, cents % 100);
+
+    let (singular, plural) = match currency.to_uppercase().as_str() {
+        "DZD" | "DA" => ("dinar", "dinars"),
+        "EUR" | "€" => ("euro", "euros"),
+        _ => (currency, currency),
+    };
+
+    let mut words = format!("{} {}", number_in_words(units), if units > 1 { plural } else { singular });
+    if cents > 0 {
+        words.push_str(&format!(" et {} centime{}", number_in_words(cents), if cents > 1 { "s" } else { "" }));
+    }
+    words
+}
+
+const UNITS: [&str; 17] = [
+    "zéro", "un", "deux", "trois", "quatre", "cinq", "six", "sept", "huit", "neuf", "dix",
+    "onze", "douze", "treize", "quatorze", "quinze", "seize",
+];
+const TENS: [&str; 7] = ["", "dix", "vingt", "trente", "quarante", "cinquante", "soixante"];
+
+fn below_hundred(n: u64) -> String {
+    match n {
+        0..=16 => UNITS[n as usize].to_string(),
+        17..=19 => format!("dix-{}", UNITS[(n - 10) as usize]),
+        20..=69 => {
+            let (tens, unit) = (TENS[(n / 10) as usize], n % 10);
+            match unit {
+                0 => tens.to_string(),
+                1 => format!("{} et un", tens),
+                _ => format!("{}-{}", tens, UNITS[unit as usize]),
+            }
+        }
+        71 => "soixante et onze".to_string(),
+        70..=79 => format!("soixante-{}", below_hundred(n - 60)),
+        80 => "quatre-vingts".to_string(),
+        _ => format!("quatre-vingt-{}", below_hundred(n - 80)),
+    }
+}
+
+fn below_thousand(n: u64) -> String {
+    let (hundreds, rest) = (n / 100, n % 100);
+    let mut parts = Vec::new();
+
+    match hundreds {
+        0 => {}
+        1 => parts.push("cent".to_string()),
+        _ => parts.push(format!("{} cent{}", UNITS[hundreds as usize], if rest == 0 { "s" } else { "" })),
+    }
+    if rest > 0 || hundreds == 0 {
+        parts.push(below_hundred(rest));
+    }
+
+    parts.join(" ")
+}
+
+/// "quatre-vingts" and "cents" lose their final s before "mille".
+fn before_multiplier(words: String) -> String {
+    if words.ends_with("vingts") || words.ends_with("cents") {
+        words[..words.len() - 1].to_string()
+    } else {
+        words
+    }
+}
+
+pub fn number_in_words(n: u64) -> String {
+    if n == 0 {
+        return UNITS[0].to_string();
+    }
+
+    let mut parts = Vec::new();
+    let (millions, thousands, rest) = (n / 1_000_000, (n / 1_000) % 1_000, n % 1_000);
+
+    if millions > 0 {
+        let words = if millions < 1_000 { below_thousand(millions) } else { number_in_words(millions) };
+        parts.push(format!("{} million{}", words, if millions > 1 { "s" } else { "" }));
+    }
+    match thousands {
+        0 => {}
+        1 => parts.push("mille".to_string()),
+        _ => parts.push(format!("{} mille", before_multiplier(below_thousand(thousands)))),
+    }
+    if rest > 0 {
+        parts.push(below_thousand(rest));
+    }
+
+    parts.join(" ")
+}
+
+/// Rough line capacity for DejaVu Sans, whose average glyph is a little
+/// over half an em wide.
+fn chars_per_line(width_mm: f32, size_pt: f32) -> usize {
+    ((width_mm / (size_pt * PT_TO_MM * 0.55)) as usize).max(10)
+}
+
+/// PDF text is drawn glyph by glyph from left to right: Arabic letters get
+/// their joined forms and are put in visual order.
+fn pdf_text(text: &str) -> String {
+    arabic::visual_order(&arabic::join_letters(text))
+}
+
+fn wrap(text: &str, max_chars: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for word in text.split_whitespace() {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() || lines.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
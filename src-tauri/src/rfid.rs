@@ -22,26 +22,38 @@ impl RfidReader {
     pub fn connect(&mut self) -> Result<()> {
         let port = serialport::new(&self.port_name, self.baud_rate)
             .timeout(Duration::from_millis(1000))
-            .open()?;
+            .open()
+            .map_err(|e| {
+                log::warn!(target: "rfid", "Failed to open {} at {} baud: {}", self.port_name, self.baud_rate, e);
+                e
+            })?;
 
+        log::info!(target: "rfid", "Connected to {} at {} baud", self.port_name, self.baud_rate);
         self.port = Some(port);
         Ok(())
     }
 
     pub fn disconnect(&mut self) {
-        self.port = None;
+        if self.port.take().is_some() {
+            log::info!(target: "rfid", "Disconnected from {}", self.port_name);
+        }
     }
 
     pub fn is_connected(&self) -> bool {
         self.port.is_some()
     }
 
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
     pub fn scan_card(&mut self) -> Result<String> {
         let port = self.port.as_mut()
             .ok_or_else(|| anyhow!("RFID reader not connected"))?;
 
         // Send scan command (this depends on your RFID reader protocol)
         let command = b"SCAN\r\n";
+        log::debug!(target: "rfid", "-> {:?}", String::from_utf8_lossy(command));
         port.write_all(command)?;
 
         // Read response
@@ -50,9 +62,11 @@ impl RfidReader {
         
         if bytes_read > 0 {
             let response = String::from_utf8_lossy(&buffer[..bytes_read]);
+            log::debug!(target: "rfid", "<- {:?}", response);
             let card_id = response.trim().to_string();
             
             if !card_id.is_empty() && card_id != "NO_CARD" {
+                log::info!(target: "rfid", "Card {} read on {}", card_id, self.port_name);
                 return Ok(card_id);
             }
         }
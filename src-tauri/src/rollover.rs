@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use crate::models::{ACADEMIC_LEVELS, Promotion, PromotionOverride, Student};
+
+/// Level following `level` in `ACADEMIC_LEVELS`, `None` after the last one
+/// or for a level outside the list.
+pub fn next_level(level: &str) -> Option<&'static str> {
+    let index = ACADEMIC_LEVELS.iter().position(|l| *l == level)?;
+    ACADEMIC_LEVELS.get(index + 1).copied()
+}
+
+/// Outcome of the rollover for each active student: promotion to the next
+/// level unless `overrides` says otherwise.
+pub fn plan_promotions(students: &[Student], overrides: &[PromotionOverride]) -> Result<Vec<Promotion>> {
+    let active: HashMap<&str, &Student> = students.iter().filter(|s| s.is_active).map(|s| (s.id.as_str(), s)).collect();
+    let mut decided: HashMap<&str, &PromotionOverride> = HashMap::new();
+    for decision in overrides {
+        if !active.contains_key(decision.student_id.as_str()) {
+            return Err(anyhow!("Student {} is not an active student", decision.student_id));
+        }
+        match decision.outcome.as_str() {
+            "promote" | "repeat" | "leave" => {}
+            other => return Err(anyhow!("Unknown promotion outcome {}", other)),
+        }
+        decided.insert(&decision.student_id, decision);
+    }
+
+    let mut promotions: Vec<Promotion> = students
+        .iter()
+        .filter(|s| s.is_active)
+        .map(|student| {
+            let from_level = student.academic_level.clone();
+            let chosen = decided
+                .get(student.id.as_str())
+                .and_then(|d| d.academic_level.as_deref())
+                .map(str::trim)
+                .filter(|l| !l.is_empty());
+            let (outcome, to_level) = match decided.get(student.id.as_str()).map(|d| d.outcome.as_str()) {
+                Some("repeat") => ("repeat", from_level.clone()),
+                Some("leave") => ("leave", from_level.clone()),
+                _ => match chosen.or_else(|| next_level(&from_level)) {
+                    Some(level) => ("promote", level.to_string()),
+                    None if ACADEMIC_LEVELS.contains(&from_level.as_str()) => ("graduate", from_level.clone()),
+                    None => ("unchanged", from_level.clone()),
+                },
+            };
+
+            Promotion {
+                student_id: student.id.clone(),
+                student_name: format!("{} {}", student.first_name, student.last_name),
+                from_level,
+                to_level,
+                outcome: outcome.to_string(),
+            }
+        })
+        .collect();
+    promotions.sort_by(|a, b| a.from_level.cmp(&b.from_level).then_with(|| a.student_name.cmp(&b.student_name)));
+
+    Ok(promotions)
+}
+
+/// Whether the student stays enrolled in the new year.
+pub fn continues(promotion: &Promotion) -> bool {
+    !matches!(promotion.outcome.as_str(), "leave" | "graduate")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn student(id: &str, level: &str, is_active: bool) -> Student {
+        Student {
+            id: id.to_string(),
+            first_name: id.to_uppercase(),
+            last_name: "Benali".to_string(),
+            email: None,
+            phone: None,
+            academic_level: level.to_string(),
+            rfid_card: None,
+            parent_name: "Parent".to_string(),
+            parent_phone: "0555123456".to_string(),
+            address: None,
+            birth_date: None,
+            enrollment_date: Utc::now(),
+            is_active,
+            notes: None,
+            sessions_remaining: 0,
+            photo_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn decision(student_id: &str, outcome: &str, academic_level: Option<&str>) -> PromotionOverride {
+        PromotionOverride {
+            student_id: student_id.to_string(),
+            outcome: outcome.to_string(),
+            academic_level: academic_level.map(str::to_string),
+        }
+    }
+
+    fn outcomes(promotions: &[Promotion]) -> Vec<(&str, &str, &str)> {
+        promotions.iter().map(|p| (p.student_id.as_str(), p.outcome.as_str(), p.to_level.as_str())).collect()
+    }
+
+    #[test]
+    fn students_move_up_one_level_by_default() {
+        let students = [
+            student("a", "1ère CEM", true),
+            student("b", "3ème Lycée", true),
+            student("c", "Primaire", true),
+            student("d", "1ère CEM", false),
+        ];
+
+        let promotions = plan_promotions(&students, &[]).unwrap();
+        assert_eq!(
+            outcomes(&promotions),
+            [("a", "promote", "2ème CEM"), ("b", "graduate", "3ème Lycée"), ("c", "unchanged", "Primaire")]
+        );
+        assert_eq!(promotions.iter().filter(|p| continues(p)).count(), 2);
+    }
+
+    #[test]
+    fn overrides_decide_the_outcome() {
+        let students = [student("a", "1ère CEM", true), student("b", "2ème CEM", true), student("c", "3ème CEM", true)];
+        let overrides = [
+            decision("a", "repeat", None),
+            decision("b", "leave", None),
+            decision("c", "promote", Some("2ème Lycée")),
+        ];
+
+        let promotions = plan_promotions(&students, &overrides).unwrap();
+        assert_eq!(
+            outcomes(&promotions),
+            [("a", "repeat", "1ère CEM"), ("b", "leave", "2ème CEM"), ("c", "promote", "2ème Lycée")]
+        );
+        assert!(!continues(&promotions[1]));
+    }
+
+    #[test]
+    fn overrides_must_target_active_students_with_a_known_outcome() {
+        let students = [student("a", "1ère CEM", true), student("b", "1ère CEM", false)];
+        assert!(plan_promotions(&students, &[decision("b", "repeat", None)]).is_err());
+        assert!(plan_promotions(&students, &[decision("z", "repeat", None)]).is_err());
+        assert!(plan_promotions(&students, &[decision("a", "skip", None)]).is_err());
+    }
+
+    #[test]
+    fn next_level_stops_after_the_last_one() {
+        assert_eq!(next_level("3ème CEM"), Some("1ère Lycée"));
+        assert_eq!(next_level("3ème Lycée"), None);
+        assert_eq!(next_level("Primaire"), None);
+    }
+}
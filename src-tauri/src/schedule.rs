@@ -0,0 +1,282 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, Days, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use crate::models::{Occupancy, Occupant, Room, RoomOccupancy, ScheduleConflict, SessionOccurrence, TimetableSlot};
+
+/// Parses a local `HH:MM` time of the timetable.
+pub fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| anyhow!("Invalid time {}, expected HH:MM", time))
+}
+
+/// Checks a timetable slot before it is saved and writes its times as
+/// zero-padded `HH:MM` so they compare as text.
+pub fn validate_slot(slot: &mut TimetableSlot) -> Result<()> {
+    if !(1..=7).contains(&slot.weekday) {
+        return Err(anyhow!("Invalid weekday {}, expected 1 (Monday) to 7 (Sunday)", slot.weekday));
+    }
+    let (start, end) = (parse_time(&slot.start_time)?, parse_time(&slot.end_time)?);
+    if end <= start {
+        return Err(anyhow!("The session must end after it starts"));
+    }
+    slot.start_time = start.format("%H:%M").to_string();
+    slot.end_time = end.format("%H:%M").to_string();
+    if slot.room.trim().is_empty() {
+        return Err(anyhow!("A timetable slot needs a room"));
+    }
+    if let (Some(starts_on), Some(ends_on)) = (slot.starts_on, slot.ends_on) {
+        if ends_on < starts_on {
+            return Err(anyhow!("The slot ends before it starts"));
+        }
+    }
+    Ok(())
+}
+
+/// Monday of the week containing `date`.
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday() as u64)
+}
+
+/// `time` on `date` in the local time zone, as stored in the database.
+pub fn local_datetime(date: NaiveDate, time: NaiveTime) -> DateTime<Utc> {
+    let local = date.and_time(time);
+    Local
+        .from_local_datetime(&local)
+        .earliest()
+        .map(|d| d.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+}
+
+/// Date, start and end of one run of a timetable slot.
+pub type SlotTimes = (NaiveDate, DateTime<Utc>, DateTime<Utc>);
+
+/// When `slot` runs in the week starting on `monday`, or `None` when it
+/// does not run that week.
+pub fn slot_occurrence(slot: &TimetableSlot, monday: NaiveDate) -> Result<Option<SlotTimes>> {
+    let date = monday + Days::new(slot.weekday.saturating_sub(1) as u64);
+    if !slot.is_active || slot.starts_on.is_some_and(|d| date < d) || slot.ends_on.is_some_and(|d| date > d) {
+        return Ok(None);
+    }
+
+    let starts_at = local_datetime(date, parse_time(&slot.start_time)?);
+    let ends_at = local_datetime(date, parse_time(&slot.end_time)?);
+    Ok(Some((date, starts_at, ends_at)))
+}
+
+/// Scans of the same card closer than this are read as one.
+pub const RESCAN_MINUTES: i64 = 2;
+
+/// The session a scan at `now` is for: the one `checked_in` names, so a
+/// student still checked in is checked out even after the session ended,
+/// else the one already running, or else the next one starting within
+/// `window_minutes`.
+pub fn session_for_scan<'a>(
+    occurrences: &'a [SessionOccurrence],
+    checked_in: Option<&str>,
+    now: DateTime<Utc>,
+    window_minutes: i64,
+) -> Option<&'a SessionOccurrence> {
+    if let Some(open) = checked_in.and_then(|id| occurrences.iter().find(|o| o.id == id)) {
+        return Some(open);
+    }
+    occurrences
+        .iter()
+        .filter(|o| o.status == "scheduled" && o.ends_at > now && o.starts_at - Duration::minutes(window_minutes) <= now)
+        .min_by_key(|o| o.starts_at)
+}
+
+/// "present", or "late" when arriving more than `grace_minutes` after the start.
+pub fn arrival_status(occurrence: &SessionOccurrence, now: DateTime<Utc>, grace_minutes: i64) -> &'static str {
+    if now > occurrence.starts_at + Duration::minutes(grace_minutes) {
+        "late"
+    } else {
+        "present"
+    }
+}
+
+pub fn overlaps(a: &SessionOccurrence, b: &SessionOccurrence) -> bool {
+    a.starts_at < b.ends_at && b.starts_at < a.ends_at
+}
+
+/// Scheduled sessions double-booking a room or a teacher.
+pub fn find_conflicts(occurrences: &[SessionOccurrence]) -> Vec<ScheduleConflict> {
+    let mut scheduled: Vec<&SessionOccurrence> = occurrences.iter().filter(|o| o.status == "scheduled").collect();
+    scheduled.sort_by_key(|o| o.starts_at);
+
+    let mut conflicts = Vec::new();
+    for (i, first) in scheduled.iter().enumerate() {
+        for second in scheduled[i + 1..].iter().take_while(|o| o.starts_at < first.ends_at) {
+            if !overlaps(first, second) {
+                continue;
+            }
+            if first.room.trim().eq_ignore_ascii_case(second.room.trim()) {
+                conflicts.push(ScheduleConflict {
+                    kind: "room".to_string(),
+                    resource: first.room.clone(),
+                    first: (*first).clone(),
+                    second: (*second).clone(),
+                });
+            }
+            if first.teacher_id.is_some() && first.teacher_id == second.teacher_id {
+                conflicts.push(ScheduleConflict {
+                    kind: "teacher".to_string(),
+                    resource: first.teacher_name.clone().or_else(|| first.teacher_id.clone()).unwrap_or_default(),
+                    first: (*first).clone(),
+                    second: (*second).clone(),
+                });
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// Key under which rooms are matched: names are compared trimmed and
+/// case-insensitively, as in conflict detection.
+pub fn room_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// Groups `occupants` per room. Every active room is listed, even empty;
+/// rooms that are not registered follow, then check-ins without a room.
+pub fn occupancy(rooms: &[Room], occupants: Vec<Occupant>, now: DateTime<Utc>) -> Occupancy {
+    let total = occupants.len() as i64;
+    let mut by_room: BTreeMap<String, Vec<Occupant>> = BTreeMap::new();
+    let mut unassigned = Vec::new();
+    for occupant in occupants {
+        match occupant.room.as_deref() {
+            Some(room) => by_room.entry(room_key(room)).or_default().push(occupant),
+            None => unassigned.push(occupant),
+        }
+    }
+
+    let mut result: Vec<RoomOccupancy> = rooms
+        .iter()
+        .filter(|r| r.is_active)
+        .map(|room| {
+            let occupants = by_room.remove(&room_key(&room.name)).unwrap_or_default();
+            RoomOccupancy {
+                room: Some(room.name.clone()),
+                capacity: Some(room.capacity),
+                over_capacity: occupants.len() as i64 > room.capacity,
+                occupants,
+            }
+        })
+        .collect();
+    result.extend(by_room.into_values().map(|occupants| RoomOccupancy {
+        room: occupants[0].room.clone(),
+        capacity: None,
+        over_capacity: false,
+        occupants,
+    }));
+    if !unassigned.is_empty() {
+        result.push(RoomOccupancy { room: None, capacity: None, over_capacity: false, occupants: unassigned });
+    }
+
+    Occupancy { total, rooms: result, updated_at: now }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 19, hour, minute, 0).unwrap()
+    }
+
+    fn occurrence(id: &str, room: &str, teacher_id: Option<&str>, start: (u32, u32), end: (u32, u32)) -> SessionOccurrence {
+        SessionOccurrence {
+            id: id.to_string(),
+            slot_id: None,
+            group_id: format!("group-{}", id),
+            teacher_id: teacher_id.map(str::to_string),
+            room: room.to_string(),
+            occurrence_date: NaiveDate::from_ymd_opt(2026, 10, 19).unwrap(),
+            starts_at: at(start.0, start.1),
+            ends_at: at(end.0, end.1),
+            status: "scheduled".to_string(),
+            original_starts_at: None,
+            cancel_reason: None,
+            notes: None,
+            group_name: format!("Groupe {}", id),
+            teacher_name: None,
+            created_at: at(0, 0),
+            updated_at: at(0, 0),
+        }
+    }
+
+    fn kinds(conflicts: &[ScheduleConflict]) -> Vec<(&str, &str, &str)> {
+        conflicts.iter().map(|c| (c.kind.as_str(), c.first.id.as_str(), c.second.id.as_str())).collect()
+    }
+
+    #[test]
+    fn conflicts_on_the_same_room_ignore_case_and_spaces() {
+        let occurrences = [
+            occurrence("a", "Salle 1", Some("t1"), (14, 0), (15, 30)),
+            occurrence("b", " salle 1", Some("t2"), (15, 0), (16, 0)),
+        ];
+        assert_eq!(kinds(&find_conflicts(&occurrences)), [("room", "a", "b")]);
+    }
+
+    #[test]
+    fn conflicts_on_the_same_teacher() {
+        let occurrences = [
+            occurrence("b", "Salle 2", Some("t1"), (14, 30), (15, 30)),
+            occurrence("a", "Salle 1", Some("t1"), (14, 0), (15, 0)),
+            occurrence("c", "Salle 3", None, (14, 0), (15, 0)),
+            occurrence("d", "Salle 4", None, (14, 0), (15, 0)),
+        ];
+        assert_eq!(kinds(&find_conflicts(&occurrences)), [("teacher", "a", "b")]);
+    }
+
+    #[test]
+    fn back_to_back_and_cancelled_sessions_do_not_conflict() {
+        let mut cancelled = occurrence("c", "Salle 1", Some("t1"), (14, 30), (15, 30));
+        cancelled.status = "cancelled".to_string();
+        let occurrences = [
+            occurrence("a", "Salle 1", Some("t1"), (14, 0), (15, 0)),
+            occurrence("b", "Salle 1", Some("t1"), (15, 0), (16, 0)),
+            cancelled,
+        ];
+        assert!(find_conflicts(&occurrences).is_empty());
+    }
+
+    #[test]
+    fn scan_goes_to_the_running_session_first() {
+        let occurrences = [
+            occurrence("later", "Salle 1", None, (16, 0), (17, 0)),
+            occurrence("running", "Salle 1", None, (14, 0), (16, 0)),
+        ];
+        assert_eq!(session_for_scan(&occurrences, None, at(15, 50), 30).map(|o| o.id.as_str()), Some("running"));
+        assert_eq!(session_for_scan(&occurrences, None, at(16, 10), 30).map(|o| o.id.as_str()), Some("later"));
+    }
+
+    #[test]
+    fn scan_matches_an_upcoming_session_within_the_window() {
+        let mut cancelled = occurrence("cancelled", "Salle 1", None, (13, 50), (15, 0));
+        cancelled.status = "cancelled".to_string();
+        let occurrences = [occurrence("next", "Salle 1", None, (14, 0), (15, 0)), cancelled];
+
+        assert_eq!(session_for_scan(&occurrences, None, at(13, 30), 30).map(|o| o.id.as_str()), Some("next"));
+        assert!(session_for_scan(&occurrences, None, at(13, 29), 30).is_none());
+        assert!(session_for_scan(&occurrences, None, at(15, 0), 30).is_none());
+    }
+
+    #[test]
+    fn scan_checks_out_of_an_ended_session_still_checked_in() {
+        let occurrences = [
+            occurrence("ended", "Salle 1", None, (14, 0), (15, 0)),
+            occurrence("next", "Salle 2", None, (15, 0), (16, 0)),
+        ];
+        assert_eq!(session_for_scan(&occurrences, Some("ended"), at(15, 20), 30).map(|o| o.id.as_str()), Some("ended"));
+        assert_eq!(session_for_scan(&occurrences, None, at(15, 20), 30).map(|o| o.id.as_str()), Some("next"));
+        // A check-in to a session that is no longer listed falls back to the usual match
+        assert_eq!(session_for_scan(&occurrences, Some("other"), at(15, 20), 30).map(|o| o.id.as_str()), Some("next"));
+    }
+
+    #[test]
+    fn arrival_after_the_grace_period_is_late() {
+        let session = occurrence("a", "Salle 1", None, (14, 0), (15, 0));
+        assert_eq!(arrival_status(&session, at(14, 10), 10), "present");
+        assert_eq!(arrival_status(&session, at(14, 11), 10), "late");
+    }
+}
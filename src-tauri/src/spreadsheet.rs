@@ -0,0 +1,115 @@
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use calamine::{open_workbook_auto, Data, Reader};
+
+/// Rows of a CSV file or of one worksheet, every cell read as text.
+#[derive(Debug, Clone)]
+pub struct SheetData {
+    pub sheet_names: Vec<String>,
+    pub headers: Vec<String>,
+    /// Data rows with their 1-based line number in the file.
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+impl SheetData {
+    pub fn value(&self, row: &[String], header: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .position(|h| h == header)
+            .and_then(|i| row.get(i))
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    }
+}
+
+pub fn read(path: &Path, sheet: Option<&str>) -> Result<SheetData> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+
+    match extension.as_str() {
+        "csv" | "txt" => read_csv(path),
+        "xlsx" | "xlsm" | "xls" | "ods" => read_workbook(path, sheet),
+        _ => Err(anyhow!("Unsupported spreadsheet format: {}", path.display())),
+    }
+}
+
+fn read_csv(path: &Path) -> Result<SheetData> {
+    let bytes = std::fs::read(path)?;
+    let content = String::from_utf8_lossy(&bytes);
+    let content = content.trim_start_matches('\u{feff}');
+
+    // Excel in French locales saves with semicolons.
+    let first_line = content.lines().next().unwrap_or("");
+    let delimiter = if first_line.matches(';').count() > first_line.matches(',').count() { b';' } else { b',' };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    let mut records = reader.records();
+    let headers = match records.next() {
+        Some(record) => record?.iter().map(|h| h.trim().to_string()).collect(),
+        None => return Err(anyhow!("The file is empty")),
+    };
+
+    let mut rows = Vec::new();
+    for (index, record) in records.enumerate() {
+        let cells: Vec<String> = record?.iter().map(|c| c.to_string()).collect();
+        if cells.iter().any(|c| !c.trim().is_empty()) {
+            rows.push((index + 2, cells));
+        }
+    }
+
+    Ok(SheetData { sheet_names: vec![], headers, rows })
+}
+
+fn read_workbook(path: &Path, sheet: Option<&str>) -> Result<SheetData> {
+    let mut workbook = open_workbook_auto(path)?;
+    let sheet_names = workbook.sheet_names();
+    let name = match sheet {
+        Some(name) => name.to_string(),
+        None => sheet_names.first().cloned().ok_or_else(|| anyhow!("The workbook has no sheet"))?,
+    };
+
+    let range = workbook.worksheet_range(&name)?;
+    let first_row = range.start().map(|(row, _)| row as usize).unwrap_or(0);
+    let mut lines = range.rows();
+
+    let headers = match lines.next() {
+        Some(cells) => cells.iter().map(|c| cell_text(c).trim().to_string()).collect(),
+        None => return Err(anyhow!("Sheet {} is empty", name)),
+    };
+
+    let mut rows = Vec::new();
+    for (index, cells) in lines.enumerate() {
+        let cells: Vec<String> = cells.iter().map(cell_text).collect();
+        if cells.iter().any(|c| !c.trim().is_empty()) {
+            rows.push((first_row + index + 2, cells));
+        }
+    }
+
+    Ok(SheetData { sheet_names, headers, rows })
+}
+
+fn cell_text(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(value) => value.clone(),
+        Data::Int(value) => value.to_string(),
+        // Phone numbers typed as numbers arrive as floats: keep them integral.
+        Data::Float(value) if value.fract() == 0.0 && value.abs() < 1e15 => format!("{}", *value as i64),
+        Data::Float(value) => value.to_string(),
+        Data::Bool(value) => value.to_string(),
+        Data::DateTime(value) => value
+            .as_datetime()
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .unwrap_or_default(),
+        Data::DateTimeIso(value) | Data::DurationIso(value) => value.clone(),
+        Data::Error(_) => String::new(),
+    }
+}
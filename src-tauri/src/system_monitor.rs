@@ -0,0 +1,86 @@
+use std::path::Path;
+use sysinfo::{Disks, System};
+
+/// Long-lived sampler: CPU usage is measured between two refreshes, so the
+/// same `System` is kept across the periodic `system-stats-updated` ticks.
+pub struct SystemMonitor {
+    system: System,
+    primed: bool,
+}
+
+impl SystemMonitor {
+    pub fn new() -> Self {
+        SystemMonitor {
+            system: System::new(),
+            primed: false,
+        }
+    }
+
+    /// Global CPU usage in percent since the previous call. The first call
+    /// blocks for sysinfo's minimum sampling interval.
+    pub fn cpu_usage(&mut self) -> f32 {
+        if !self.primed {
+            self.system.refresh_cpu();
+            std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+            self.primed = true;
+        }
+
+        self.system.refresh_cpu();
+        self.system.global_cpu_info().cpu_usage()
+    }
+
+    pub fn memory_usage(&mut self) -> f32 {
+        self.system.refresh_memory();
+        percent(self.system.used_memory(), self.system.total_memory())
+    }
+
+    /// Seconds since this application process started.
+    pub fn process_uptime(&mut self) -> u64 {
+        match sysinfo::get_current_pid() {
+            Ok(pid) => {
+                self.system.refresh_process(pid);
+                self.system.process(pid).map(|p| p.run_time()).unwrap_or(0)
+            }
+            Err(_) => 0,
+        }
+    }
+}
+
+pub fn system_uptime() -> u64 {
+    System::uptime()
+}
+
+/// Usage in percent of the volume holding `path`, picked as the disk with the
+/// longest mount point that prefixes the canonical path.
+pub fn disk_usage(path: &Path) -> f32 {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let disks = Disks::new_with_refreshed_list();
+
+    disks
+        .list()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| percent(disk.total_space() - disk.available_space(), disk.total_space()))
+        .unwrap_or(0.0)
+}
+
+pub fn format_uptime(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let hours = (seconds % 86_400) / 3_600;
+    let minutes = (seconds % 3_600) / 60;
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else {
+        format!("{}h {}m", hours, minutes)
+    }
+}
+
+fn percent(used: u64, total: u64) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        (used as f64 / total as f64 * 100.0) as f32
+    }
+}
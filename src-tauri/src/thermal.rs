@@ -0,0 +1,482 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, Utc};
+use crate::arabic;
+use crate::models::{PrinterSettings, ZReport};
+use crate::receipts::{amount_in_words, format_amount, payment_method_label};
+
+const ESC: u8 = 0x1b;
+const GS: u8 = 0x1d;
+
+/// `ESC t` code page of Windows-1256, which holds Arabic letters as well as
+/// the accented letters used in French.
+const CODE_PAGE_WPC1256: u8 = 50;
+
+/// Widest logo the printer accepts, in dots (80 mm heads have 576 dots).
+const MAX_LOGO_DOTS: u32 = 384;
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left = 0,
+    Center = 1,
+}
+
+/// ESC/POS byte stream for one ticket.
+pub struct Ticket {
+    bytes: Vec<u8>,
+    columns: usize,
+}
+
+impl Ticket {
+    /// 58 mm paper holds 32 characters per line, 80 mm paper 48.
+    pub fn new(paper_width_mm: u32) -> Self {
+        let columns = if paper_width_mm >= 80 { 48 } else { 32 };
+        let mut bytes = vec![ESC, b'@'];
+        bytes.extend_from_slice(&[ESC, b't', CODE_PAGE_WPC1256]);
+        Ticket { bytes, columns }
+    }
+
+    pub fn align(&mut self, align: Align) -> &mut Self {
+        self.bytes.extend_from_slice(&[ESC, b'a', align as u8]);
+        self
+    }
+
+    pub fn bold(&mut self, on: bool) -> &mut Self {
+        self.bytes.extend_from_slice(&[ESC, b'E', on as u8]);
+        self
+    }
+
+    /// Double width and height.
+    pub fn large(&mut self, on: bool) -> &mut Self {
+        self.bytes.extend_from_slice(&[GS, b'!', if on { 0x11 } else { 0x00 }]);
+        self
+    }
+
+    pub fn line(&mut self, text: &str) -> &mut Self {
+        // Letter joining is left to the printer firmware
+        self.bytes.extend_from_slice(&encode(&arabic::visual_order(text)));
+        self.bytes.push(b'\n');
+        self
+    }
+
+    pub fn feed(&mut self, lines: u8) -> &mut Self {
+        self.bytes.extend_from_slice(&[ESC, b'd', lines]);
+        self
+    }
+
+    pub fn separator(&mut self) -> &mut Self {
+        let rule = "-".repeat(self.columns);
+        self.line(&rule)
+    }
+
+    /// `label` on the left and `value` on the right of the same line, or on
+    /// two lines when they do not fit together.
+    pub fn row(&mut self, label: &str, value: &str) -> &mut Self {
+        let used = label.chars().count() + value.chars().count();
+        if used < self.columns {
+            let padding = " ".repeat(self.columns - used);
+            self.line(&format!("{}{}{}", label, padding, value))
+        } else {
+            self.line(label);
+            let padding = " ".repeat(self.columns.saturating_sub(value.chars().count()));
+            self.line(&format!("{}{}", padding, value))
+        }
+    }
+
+    /// Prints `data` as a QR code using the printer's own generator.
+    pub fn qr_code(&mut self, data: &str) -> &mut Self {
+        let payload = data.as_bytes();
+        let length = payload.len() + 3;
+
+        // Model 2, module size 6, error correction M
+        self.bytes.extend_from_slice(&[GS, b'(', b'k', 4, 0, 49, 65, 50, 0]);
+        self.bytes.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 67, 6]);
+        self.bytes.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 69, 49]);
+        self.bytes.extend_from_slice(&[GS, b'(', b'k', (length % 256) as u8, (length / 256) as u8, 49, 80, 48]);
+        self.bytes.extend_from_slice(payload);
+        self.bytes.extend_from_slice(&[GS, b'(', b'k', 3, 0, 49, 81, 48]);
+        self
+    }
+
+    /// Prints an image file as a monochrome raster (`GS v 0`).
+    pub fn logo(&mut self, path: &Path) -> Result<&mut Self> {
+        let image = image::open(path)?;
+        let image = if image.width() > MAX_LOGO_DOTS {
+            image.resize(MAX_LOGO_DOTS, u32::MAX, image::imageops::FilterType::Triangle)
+        } else {
+            image
+        };
+        let pixels = image.to_luma_alpha8();
+        let (width, height) = pixels.dimensions();
+        let row_bytes = width.div_ceil(8);
+
+        self.bytes.extend_from_slice(&[GS, b'v', b'0', 0]);
+        self.bytes.extend_from_slice(&[(row_bytes % 256) as u8, (row_bytes / 256) as u8]);
+        self.bytes.extend_from_slice(&[(height % 256) as u8, (height / 256) as u8]);
+        for y in 0..height {
+            for byte in 0..row_bytes {
+                let mut bits = 0u8;
+                for bit in 0..8 {
+                    let x = byte * 8 + bit;
+                    if x < width {
+                        let [luma, alpha] = pixels.get_pixel(x, y).0;
+                        if alpha > 127 && luma < 128 {
+                            bits |= 0x80 >> bit;
+                        }
+                    }
+                }
+                self.bytes.push(bits);
+            }
+        }
+        self.bytes.push(b'\n');
+        Ok(self)
+    }
+
+    /// Feeds the paper past the cutter and cuts, leaving a small hinge.
+    pub fn cut(&mut self) -> &mut Self {
+        self.bytes.extend_from_slice(&[GS, b'V', 66, 0]);
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// Windows-1256 lacks the accented capitals, which are printed unaccented.
+fn encode(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut buffer = [0u8; 4];
+
+    for c in text.chars() {
+        let (encoded, _, unmappable) = encoding_rs::WINDOWS_1256.encode(c.encode_utf8(&mut buffer));
+        if !unmappable {
+            bytes.extend_from_slice(&encoded);
+            continue;
+        }
+        let fallback = match c {
+            'À' | 'Â' | 'Ä' => "A",
+            'Ç' => "C",
+            'É' | 'È' | 'Ê' | 'Ë' => "E",
+            'Î' | 'Ï' => "I",
+            'Ô' | 'Ö' => "O",
+            'Ù' | 'Û' | 'Ü' => "U",
+            'Œ' => "OE",
+            _ => "?",
+        };
+        bytes.extend_from_slice(fallback.as_bytes());
+    }
+
+    bytes
+}
+
+/// Sends a ticket to the configured printer: a serial port (`COM4`,
+/// `/dev/ttyUSB0`) or a raw device/file (`/dev/usb/lp0`, a pseudo-terminal).
+pub fn send(settings: &PrinterSettings, bytes: &[u8]) -> Result<()> {
+    if settings.device.is_empty() {
+        return Err(anyhow!("No receipt printer configured"));
+    }
+
+    match settings.connection.as_str() {
+        "serial" => {
+            let mut port = serialport::new(&settings.device, settings.baud_rate)
+                .timeout(Duration::from_millis(3000))
+                .open()?;
+            port.write_all(bytes)?;
+            port.flush()?;
+        }
+        "file" => {
+            let mut device = OpenOptions::new().write(true).open(&settings.device)?;
+            device.write_all(bytes)?;
+            device.flush()?;
+        }
+        other => return Err(anyhow!("Unknown printer connection: {}", other)),
+    }
+
+    log::info!(target: "printer", "Sent {} bytes to {}", bytes.len(), settings.device);
+    Ok(())
+}
+
+/// School details printed at the top of every ticket.
+pub struct Header<'a> {
+    pub school_name: &'a str,
+    pub school_address: &'a str,
+    pub school_phone: &'a str,
+}
+
+pub struct ReceiptTicket<'a> {
+    pub receipt_number: &'a str,
+    pub payment_date: DateTime<Utc>,
+    pub student_name: &'a str,
+    pub academic_level: &'a str,
+    pub payment_type: &'a str,
+    pub payment_method: &'a str,
+    pub amount: f64,
+    pub currency: &'a str,
+    pub cashier: Option<&'a str>,
+    /// Dues settled, with discounts as negative amounts.
+    pub details: &'a [(String, f64)],
+}
+
+/// Totals of one day, by payment method.
+pub struct ClosingTicket<'a> {
+    pub date: chrono::NaiveDate,
+    pub payment_count: usize,
+    pub totals: Vec<(String, usize, f64)>,
+    pub first_receipt: Option<&'a str>,
+    pub last_receipt: Option<&'a str>,
+    pub currency: &'a str,
+}
+
+fn header(ticket: &mut Ticket, settings: &PrinterSettings, header: &Header) {
+    ticket.align(Align::Center);
+    if let Some(logo) = settings.logo_path.as_deref().filter(|p| !p.is_empty()) {
+        // A missing or unreadable logo should not block the ticket.
+        if let Err(e) = ticket.logo(Path::new(logo)) {
+            log::warn!(target: "printer", "Failed to load logo {}: {}", logo, e);
+        }
+    }
+    ticket.bold(true).line(header.school_name).bold(false);
+    for detail in [header.school_address, header.school_phone] {
+        if !detail.is_empty() {
+            ticket.line(detail);
+        }
+    }
+    ticket.align(Align::Left).separator();
+}
+
+pub fn receipt(settings: &PrinterSettings, school: &Header, data: &ReceiptTicket) -> Vec<u8> {
+    let mut ticket = Ticket::new(settings.paper_width_mm);
+    header(&mut ticket, settings, school);
+
+    ticket
+        .align(Align::Center)
+        .bold(true)
+        .line("REÇU DE PAIEMENT - وصل دفع")
+        .bold(false)
+        .align(Align::Left)
+        .row("N°", data.receipt_number)
+        .row("Date", &data.payment_date.with_timezone(&Local).format("%d/%m/%Y %H:%M").to_string())
+        .separator()
+        .row("Élève", data.student_name)
+        .row("Niveau", data.academic_level)
+        .row("Objet", data.payment_type)
+        .row("Mode", payment_method_label(data.payment_method));
+
+    if !data.details.is_empty() {
+        ticket.separator();
+        for (label, amount) in data.details {
+            ticket.row(label, &format_amount(*amount));
+        }
+    }
+
+    ticket
+        .separator()
+        .align(Align::Center)
+        .large(true)
+        .line(&format!("{} {}", format_amount(data.amount), data.currency))
+        .large(false)
+        .line(&amount_in_words(data.amount, data.currency))
+        .align(Align::Left);
+
+    if let Some(cashier) = data.cashier {
+        ticket.row("Caissier", cashier);
+    }
+
+    ticket
+        .feed(1)
+        .align(Align::Center)
+        .qr_code(data.receipt_number)
+        .line("Merci - شكرا")
+        .feed(3)
+        .cut();
+
+    ticket.into_bytes()
+}
+
+pub fn daily_closing(settings: &PrinterSettings, school: &Header, data: &ClosingTicket) -> Vec<u8> {
+    let mut ticket = Ticket::new(settings.paper_width_mm);
+    header(&mut ticket, settings, school);
+
+    ticket
+        .align(Align::Center)
+        .bold(true)
+        .line("CLÔTURE DE CAISSE")
+        .bold(false)
+        .line(&data.date.format("%d/%m/%Y").to_string())
+        .align(Align::Left)
+        .separator();
+
+    let mut total = 0.0;
+    for (method, count, amount) in &data.totals {
+        ticket.row(&format!("{} ({})", payment_method_label(method), count), &format_amount(*amount));
+        total += amount;
+    }
+
+    ticket
+        .separator()
+        .bold(true)
+        .row("Paiements", &data.payment_count.to_string())
+        .row(&format!("Total {}", data.currency), &format_amount(total))
+        .bold(false);
+
+    if let (Some(first), Some(last)) = (data.first_receipt, data.last_receipt) {
+        ticket.row("Reçus", &format!("{} à {}", first, last));
+    }
+
+    ticket
+        .row("Imprimé le", &Local::now().format("%d/%m/%Y %H:%M").to_string())
+        .feed(3)
+        .cut();
+
+    ticket.into_bytes()
+}
+
+/// Z-report of a cash session, with expected and counted amounts per method.
+pub fn z_report(settings: &PrinterSettings, school: &Header, report: &ZReport, currency: &str) -> Vec<u8> {
+    let mut ticket = Ticket::new(settings.paper_width_mm);
+    header(&mut ticket, settings, school);
+
+    let format_date = |date: DateTime<Utc>| date.with_timezone(&Local).format("%d/%m/%Y %H:%M").to_string();
+    ticket
+        .align(Align::Center)
+        .bold(true)
+        .line("RAPPORT Z")
+        .bold(false)
+        .align(Align::Left)
+        .row("Ouverture", &format_date(report.session.opened_at))
+        .row("Par", &report.session.opened_by)
+        .row("Fond de caisse", &format_amount(report.session.opening_float));
+    if let Some(closed_at) = report.session.closed_at {
+        ticket.row("Clôture", &format_date(closed_at));
+    }
+    if let Some(closed_by) = report.session.closed_by.as_deref() {
+        ticket.row("Par", closed_by);
+    }
+
+    for total in &report.totals {
+        ticket
+            .separator()
+            .bold(true)
+            .line(&format!("{} ({})", payment_method_label(&total.payment_method), total.payment_count))
+            .bold(false)
+            .row("Encaissé", &format_amount(total.collected))
+            .row("Remboursé", &format_amount(total.refunded))
+            .row("Attendu", &format_amount(total.expected));
+        if let (Some(counted), Some(difference)) = (total.counted, total.difference) {
+            ticket.row("Compté", &format_amount(counted)).row("Écart", &format_amount(difference));
+        }
+    }
+
+    ticket
+        .separator()
+        .row("Paiements", &report.payment_count.to_string())
+        .row("Annulations/remb.", &report.reversal_count.to_string())
+        .bold(true)
+        .row(
+            &format!("Total encaissé {}", currency),
+            &format_amount(report.totals.iter().map(|t| t.collected - t.refunded).sum()),
+        )
+        .bold(false);
+
+    if let (Some(first), Some(last)) = (report.first_receipt.as_deref(), report.last_receipt.as_deref()) {
+        ticket.row("Reçus", &format!("{} à {}", first, last));
+    }
+
+    ticket
+        .row("Imprimé le", &Local::now().format("%d/%m/%Y %H:%M").to_string())
+        .feed(2)
+        .line("Signature caissier :")
+        .feed(3)
+        .cut();
+
+    ticket.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes written by `text` lines, without the reset and code page prefix.
+    fn body(ticket: Ticket) -> Vec<u8> {
+        ticket.into_bytes()[5..].to_vec()
+    }
+
+    #[test]
+    fn qr_code_stores_the_payload_length_in_two_bytes() {
+        let mut ticket = Ticket::new(80);
+        let data = "R".repeat(300);
+        ticket.qr_code(&data);
+        let bytes = body(ticket);
+
+        // Store command: 300 bytes of data plus 3, low byte first
+        let store = [GS, b'(', b'k', 47, 1, 49, 80, 48];
+        let at = bytes.windows(store.len()).position(|w| w == store).unwrap();
+        assert_eq!(&bytes[at + store.len()..at + store.len() + 300], data.as_bytes());
+        assert_eq!(&bytes[bytes.len() - 8..], [GS, b'(', b'k', 3, 0, 49, 81, 48]);
+    }
+
+    #[test]
+    fn row_pads_the_value_to_the_right_edge() {
+        let mut ticket = Ticket::new(58);
+        ticket.row("Mode", "Espèces");
+        let bytes = body(ticket);
+
+        assert_eq!(bytes.len(), 33);
+        assert!(bytes.starts_with(b"Mode   "));
+        assert_eq!(&bytes[25..], b"Esp\xe8ces\n");
+    }
+
+    #[test]
+    fn row_puts_a_long_value_on_its_own_line() {
+        let mut ticket = Ticket::new(58);
+        ticket.row("Élève", "Benali Mohamed Amine Abdelkader");
+        let bytes = body(ticket);
+
+        let lines: Vec<&[u8]> = bytes.split(|b| *b == b'\n').collect();
+        assert_eq!(lines[0], b"El\xe8ve");
+        assert_eq!(lines[1].len(), 32);
+        assert!(lines[1].ends_with(b"Benali Mohamed Amine Abdelkader"));
+    }
+
+    #[test]
+    fn encode_uses_windows_1256_with_fallbacks() {
+        assert_eq!(encode("reçu élève"), b"re\xe7u \xe9l\xe8ve");
+        // Arabic letters: meem, heh
+        assert_eq!(encode("مه"), [0xe3, 0xe5]);
+        assert_eq!(encode("ÉCOLE À"), b"ECOLE A");
+        assert_eq!(encode("→"), b"?");
+    }
+
+    #[test]
+    fn send_writes_the_ticket_to_a_device_file() {
+        let path = std::env::temp_dir().join(format!("ticket-{}.bin", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"").unwrap();
+        let settings = PrinterSettings {
+            connection: "file".to_string(),
+            device: path.to_string_lossy().into_owned(),
+            ..PrinterSettings::default()
+        };
+
+        let mut ticket = Ticket::new(80);
+        ticket.line("Test").cut();
+        let bytes = ticket.into_bytes();
+        send(&settings, &bytes).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn send_rejects_a_missing_or_unknown_printer() {
+        assert!(send(&PrinterSettings::default(), b"x").is_err());
+        let settings = PrinterSettings {
+            connection: "bluetooth".to_string(),
+            device: "printer".to_string(),
+            ..PrinterSettings::default()
+        };
+        assert!(send(&settings, b"x").is_err());
+    }
+}